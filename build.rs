@@ -0,0 +1,79 @@
+// Embeds build metadata for `loco-pilot version`: the commit, date and target
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+
+    // Rebuild when the checked out commit changes, not on every edit
+    let git_dir = Path::new(&manifest_dir).join(".git");
+    println!("cargo:rerun-if-changed=build.rs");
+    let branch_ref = fs::read_to_string(git_dir.join("HEAD"))
+        .ok()
+        .and_then(|head| Some(head.strip_prefix("ref:")?.trim().to_string()));
+    // A missing path would make cargo rerun this script on every build
+    let mut watched = vec!["HEAD".to_string(), "packed-refs".to_string()];
+    watched.extend(branch_ref);
+    for path in watched.iter().map(|path| git_dir.join(path)) {
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    // Empty when building outside a checkout, e.g. from a crates.io tarball
+    let sha = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .current_dir(&manifest_dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=LOCO_PILOT_GIT_SHA={}", sha.trim());
+
+    // SOURCE_DATE_EPOCH keeps reproducible builds reproducible
+    let seconds = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default()
+        });
+    println!(
+        "cargo:rustc-env=LOCO_PILOT_BUILD_DATE={}",
+        civil_date(seconds)
+    );
+
+    println!(
+        "cargo:rustc-env=LOCO_PILOT_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+}
+
+/// The UTC date of a Unix timestamp as YYYY-MM-DD, without pulling chrono into
+/// the build script
+fn civil_date(seconds: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm, with eras of 400 years
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
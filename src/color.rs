@@ -0,0 +1,179 @@
+// Color settings to ANSI escape sequences
+
+use crate::Env;
+
+/// Levels of the 6x6x6 color cube in the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+/// Sequence used when a color setting isn't recognized: bold green
+const FALLBACK: &str = "\x1b[1;32m";
+
+/// ANSI escape sequence for a color setting. A setting is a single color or a
+/// style of space separated words, e.g. `bold white on blue` or
+/// `fg=cyan bg=black underline`.
+///
+/// Colors are names like `bright_cyan`, 256-color palette indices like
+/// `color123`, or truecolor values like `#ff8700`. Truecolor is downgraded to
+/// the nearest palette color unless COLORTERM says the terminal supports it.
+pub fn ansi_code(style: &str, env: &Env) -> String {
+    parse_style(style, supports_truecolor(env)).unwrap_or_else(|| FALLBACK.to_string())
+}
+
+/// Whether a color setting is recognized, rather than falling back to bold green
+pub fn is_valid(style: &str) -> bool {
+    parse_style(style, false).is_some()
+}
+
+/// Escape sequence for a color setting, or None when it isn't recognized
+fn parse_style(style: &str, truecolor: bool) -> Option<String> {
+    if style == "none" {
+        return Some(String::new());
+    }
+
+    let mut params = Vec::new();
+    let mut background_next = false;
+    for word in style.split_whitespace() {
+        let (color, background) = match word {
+            "on" => {
+                background_next = true;
+                continue;
+            }
+            _ => match word.split_once('=') {
+                Some(("fg", color)) => (color, false),
+                Some(("bg", color)) => (color, true),
+                _ => (word, background_next),
+            },
+        };
+        background_next = false;
+
+        params.push(
+            attribute(color)
+                .map(str::to_string)
+                .or_else(|| color_params(color, background, truecolor))?,
+        );
+    }
+
+    (!params.is_empty()).then(|| format!("\x1b[{}m", params.join(";")))
+}
+
+/// SGR parameter for a text attribute
+fn attribute(word: &str) -> Option<&'static str> {
+    match word {
+        "bold" => Some("1"),
+        "dim" => Some("2"),
+        "italic" => Some("3"),
+        "underline" => Some("4"),
+        "blink" => Some("5"),
+        "reverse" => Some("7"),
+        "strikethrough" => Some("9"),
+        _ => None,
+    }
+}
+
+/// SGR parameters for a foreground or background color
+fn color_params(color: &str, background: bool, truecolor: bool) -> Option<String> {
+    let palette_index = color
+        .strip_prefix("color")
+        .and_then(|index| index.parse::<u8>().ok());
+    let extended = if background { 48 } else { 38 };
+    if let Some(index) = palette_index {
+        return Some(format!("{};5;{}", extended, index));
+    }
+
+    if let Some((red, green, blue)) = parse_hex(color) {
+        return Some(if truecolor {
+            format!("{};2;{};{};{}", extended, red, green, blue)
+        } else {
+            format!("{};5;{}", extended, rgb_to_256(red, green, blue))
+        });
+    }
+
+    // Named colors, where the bold_ variants keep their historical meaning
+    let (bold, name) = match color.strip_prefix("bold_") {
+        Some(name) => (true, name),
+        None => (false, color),
+    };
+    let (bright, name) = match name.strip_prefix("bright_") {
+        Some(name) => (true, name),
+        None => (name == "gray", name),
+    };
+    let offset = match name {
+        "black" | "gray" => 0,
+        "red" => 1,
+        "green" => 2,
+        "yellow" => 3,
+        "blue" => 4,
+        "purple" | "magenta" => 5,
+        "cyan" => 6,
+        "white" => 7,
+        _ => return None,
+    };
+    if bold && bright {
+        return None;
+    }
+    let base = match (bright, background) {
+        (false, false) => 30,
+        (false, true) => 40,
+        (true, false) => 90,
+        (true, true) => 100,
+    };
+
+    if bold {
+        Some(format!("1;{}", base + offset))
+    } else {
+        Some((base + offset).to_string())
+    }
+}
+
+/// Parse a `#rrggbb` color
+fn parse_hex(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |range| u8::from_str_radix(&hex[range], 16).ok();
+    Some((channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+/// Whether the terminal advertises 24-bit color support
+fn supports_truecolor(env: &Env) -> bool {
+    env.var("COLORTERM")
+        .is_some_and(|value| value == "truecolor" || value == "24bit")
+}
+
+/// Nearest 256-color palette index for an RGB color, picking the closer of
+/// the color cube and the grayscale ramp
+pub fn rgb_to_256(red: u8, green: u8, blue: u8) -> u8 {
+    let cube_index = |value: u8| match value {
+        0..48 => 0,
+        48..115 => 1,
+        _ => (value - 35) / 40,
+    };
+    let (red_index, green_index, blue_index) =
+        (cube_index(red), cube_index(green), cube_index(blue));
+    let cube_color = (
+        CUBE_LEVELS[red_index as usize],
+        CUBE_LEVELS[green_index as usize],
+        CUBE_LEVELS[blue_index as usize],
+    );
+    let cube = 16 + 36 * red_index + 6 * green_index + blue_index;
+
+    let average = (red as u16 + green as u16 + blue as u16) / 3;
+    let gray_index = if average > 238 {
+        23
+    } else {
+        average.saturating_sub(3) as u8 / 10
+    };
+    let gray_level = 8 + 10 * gray_index;
+    let gray = 232 + gray_index;
+
+    let distance = |(r, g, b): (u8, u8, u8)| {
+        let diff = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        diff(r, red) + diff(g, green) + diff(b, blue)
+    };
+    if distance((gray_level, gray_level, gray_level)) < distance(cube_color) {
+        gray
+    } else {
+        cube
+    }
+}
@@ -0,0 +1,112 @@
+// Long-lived prompt server, so each prompt skips cold caches and repository loads
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A prompt asked for by a shell, with its arguments, directory and environment
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    pub env: Vec<(String, String)>,
+}
+
+/// The rendered prompt sent back to the shell
+#[derive(Debug, Serialize, Deserialize)]
+struct Response {
+    prompt: String,
+}
+
+/// How long either side waits on the other before giving up; the client then
+/// renders the prompt itself
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where the daemon listens, from LOCO_PILOT_SOCKET or in the runtime directory
+pub fn socket_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("LOCO_PILOT_SOCKET").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .map(|dir| dir.join("loco-pilot.sock"))
+}
+
+/// Ask the daemon at `path` for a prompt, or None when it isn't running or
+/// doesn't answer in time
+pub fn query(path: &Path, request: &Request) -> Option<String> {
+    let mut stream = UnixStream::connect(path).ok()?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(TIMEOUT)).ok()?;
+    serde_json::to_writer(&mut stream, request).ok()?;
+    stream.shutdown(Shutdown::Write).ok()?;
+
+    let mut body = String::new();
+    stream.read_to_string(&mut body).ok()?;
+    let response: Response = serde_json::from_str(&body).ok()?;
+    Some(response.prompt)
+}
+
+/// Listen on `path`, only for the current user
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    // A socket left behind by a daemon that died can be replaced, a live one can't
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("a daemon is already listening on {}", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Requests carry the shell's environment, so only the owner may connect.
+    // The socket is bound in a directory only the owner can enter and moved
+    // into place once its permissions are narrowed, so nobody can connect in
+    // between.
+    let mut staging_name = path.file_name().unwrap_or_default().to_os_string();
+    staging_name.push(format!(".{}", std::process::id()));
+    let staging = path.with_file_name(staging_name);
+    let _ = fs::remove_dir_all(&staging);
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staging.join("socket");
+    let listener = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    listener
+}
+
+/// Answer requests one at a time until the process is stopped. Requests
+/// `handle` can't answer are closed unanswered, so the client falls back to
+/// rendering the prompt itself.
+pub fn serve(listener: UnixListener, handle: impl Fn(Request) -> Option<String>) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+        let _ = stream.set_write_timeout(Some(TIMEOUT));
+        let mut body = String::new();
+        if stream.read_to_string(&mut body).is_err() {
+            continue;
+        }
+        let Ok(request) = serde_json::from_str::<Request>(&body) else {
+            continue;
+        };
+        if let Some(prompt) = handle(request) {
+            let _ = serde_json::to_writer(&mut stream, &Response { prompt });
+        }
+    }
+}
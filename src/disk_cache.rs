@@ -0,0 +1,80 @@
+// On-disk cache shared between prompt invocations, since each prompt is a new process
+
+use crate::Env;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+/// Environment variable that turns the cache off, e.g. to measure uncached prompts
+pub const DISABLE_VAR: &str = "LOCO_PILOT_NO_CACHE";
+
+/// Set when this process turned the cache off
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turn the cache off for the rest of this process
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether entries are read and written at all, for a prompt rendered with `env`
+fn enabled(env: &Env) -> bool {
+    !DISABLED.load(Ordering::Relaxed) && env.var_os(DISABLE_VAR).is_none()
+}
+
+/// Location of the entry for `key` in the user cache directory, grouped by
+/// `kind`, or None when the cache is off. The file is named after a SHA-256
+/// of the key as JSON, so it stays the same across builds and Rust versions.
+pub fn entry_path(kind: &str, key: &impl Serialize, env: &Env) -> Option<PathBuf> {
+    if !enabled(env) {
+        return None;
+    }
+    let key = serde_json::to_vec(key).ok()?;
+    dirs::cache_dir().map(|path| {
+        path.join("loco-pilot")
+            .join(kind)
+            .join(format!("{}.json", crate::update::sha256_hex(&key)))
+    })
+}
+
+/// Read an entry along with how long ago it was written
+pub fn read<T: DeserializeOwned>(path: &Path) -> Option<(T, Duration)> {
+    let Some(modified) = fs::metadata(path).ok().and_then(|m| m.modified().ok()) else {
+        debug!("cache miss: {}", path.display());
+        return None;
+    };
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+    let value = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    Some((value, age))
+}
+
+/// Read an entry written less than `ttl` ago
+pub fn read_fresh<T: DeserializeOwned>(path: &Path, ttl: Duration) -> Option<T> {
+    match read(path) {
+        Some((value, age)) if age <= ttl => {
+            debug!("cache hit: {}", path.display());
+            Some(value)
+        }
+        Some((_, age)) => {
+            debug!("cache entry {} expired {:?} ago", path.display(), age - ttl);
+            None
+        }
+        None => None,
+    }
+}
+
+/// Write an entry through a temporary file, so readers never see half of it
+pub fn write<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp_path, serde_json::to_string(value)?)?;
+    fs::rename(temp_path, path)
+}
@@ -0,0 +1,82 @@
+// The environment variables a prompt is rendered with
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Environment variables for rendering a prompt: this process's own, or the
+/// ones a shell sent along with a daemon request
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    /// None reads the process environment
+    vars: Option<HashMap<String, String>>,
+}
+
+impl Env {
+    /// A shell's environment, used in place of this process's
+    pub fn captured(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        Env {
+            vars: Some(vars.into_iter().collect()),
+        }
+    }
+
+    /// The value of `name`, if it's set and valid unicode
+    pub fn var(&self, name: &str) -> Option<String> {
+        match &self.vars {
+            Some(vars) => vars.get(name).cloned(),
+            None => env::var(name).ok(),
+        }
+    }
+
+    /// The value of `name`, if it's set
+    pub fn var_os(&self, name: &str) -> Option<OsString> {
+        match &self.vars {
+            Some(vars) => vars.get(name).map(OsString::from),
+            None => env::var_os(name),
+        }
+    }
+
+    /// Whether `name` is set to something other than an empty string
+    pub fn is_set(&self, name: &str) -> bool {
+        self.var_os(name).is_some_and(|value| !value.is_empty())
+    }
+
+    /// Every variable that's valid unicode
+    pub fn vars(&self) -> Vec<(String, String)> {
+        match &self.vars {
+            Some(vars) => vars
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            None => env::vars_os()
+                .filter_map(|(name, value)| {
+                    Some((name.into_string().ok()?, value.into_string().ok()?))
+                })
+                .collect(),
+        }
+    }
+
+    /// The home directory, which a shell's environment names in HOME
+    pub fn home_dir(&self) -> Option<PathBuf> {
+        if self.vars.is_none() {
+            return dirs::home_dir();
+        }
+        ["HOME", "USERPROFILE"]
+            .iter()
+            .filter_map(|name| self.var(name))
+            .find(|home| !home.is_empty())
+            .map(PathBuf::from)
+            .or_else(dirs::home_dir)
+    }
+
+    /// A command for `program` that runs with these variables
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut command = Command::new(program);
+        if let Some(vars) = &self.vars {
+            command.env_clear().envs(vars);
+        }
+        command
+    }
+}
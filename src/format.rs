@@ -0,0 +1,267 @@
+// Prompt format templates
+
+/// Render a prompt template such as `$user{@$host}:$dir{ $git} $char `.
+///
+/// `$name` is replaced by the value `lookup` returns for it, `$$` is a literal
+/// dollar sign, and unknown variables are kept as written. Text inside `{...}`
+/// is only shown when a variable in it has a value, so separators disappear
+/// along with hidden segments. Groups without variables are always shown.
+pub fn render(template: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut chars = template.chars().peekable();
+    render_until(&mut chars, lookup, false).0
+}
+
+/// Render until the end of the template, or the closing brace of a group.
+/// Returns the text and whether it contained variables and non-empty ones.
+fn render_until(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    in_group: bool,
+) -> (String, bool, bool) {
+    let mut output = String::new();
+    let mut has_variables = false;
+    let mut has_values = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '$' if chars.peek() == Some(&'$') => {
+                chars.next();
+                output.push('$');
+            }
+            '$' => {
+                let mut name = String::new();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_ascii_lowercase() || next == '_') {
+                        break;
+                    }
+                    name.push(next);
+                    chars.next();
+                }
+                match lookup(&name) {
+                    Some(value) => {
+                        has_variables = true;
+                        has_values |= !value.is_empty();
+                        output.push_str(&value);
+                    }
+                    None => {
+                        output.push('$');
+                        output.push_str(&name);
+                    }
+                }
+            }
+            '{' => {
+                let (group, group_variables, group_values) = render_until(chars, lookup, true);
+                has_variables |= group_variables;
+                has_values |= group_values;
+                if group_values || !group_variables {
+                    output.push_str(&group);
+                }
+            }
+            '}' if in_group => break,
+            _ => output.push(c),
+        }
+    }
+
+    (output, has_variables, has_values)
+}
+
+/// Names of the variables in a template, in order of first appearance
+pub fn variables(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            continue;
+        }
+        if chars.peek() == Some(&'$') {
+            chars.next();
+            continue;
+        }
+        let mut name = String::new();
+        while let Some(&next) = chars.peek() {
+            if !(next.is_ascii_lowercase() || next == '_') {
+                break;
+            }
+            name.push(next);
+            chars.next();
+        }
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Pass each ANSI escape sequence in `text` through `wrap`, e.g. to mark it as
+/// non-printing for the shell, leaving the rest as is
+pub fn wrap_escapes(text: &str, wrap: &dyn Fn(&str) -> String) -> String {
+    map_escapes(text, wrap, &str::to_string)
+}
+
+/// Pass each ANSI escape sequence in `text` through `wrap` and each run of
+/// text between them through `escape`
+pub fn map_escapes(
+    text: &str,
+    wrap: &dyn Fn(&str) -> String,
+    escape: &dyn Fn(&str) -> String,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut plain_start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c != '\x1b' || chars.peek().map(|(_, next)| *next) != Some('[') {
+            continue;
+        }
+        // CSI sequences end with a byte in the @ to ~ range
+        chars.next();
+        let mut end = text.len();
+        for (index, next) in chars.by_ref() {
+            if ('@'..='~').contains(&next) {
+                end = index + next.len_utf8();
+                break;
+            }
+        }
+        output.push_str(&escape(&text[plain_start..start]));
+        output.push_str(&wrap(&text[start..end]));
+        plain_start = end;
+    }
+    output.push_str(&escape(&text[plain_start..]));
+    output
+}
+
+/// Names of the built-in prompt styles
+pub const STYLES: &[&str] = &["default", "minimal", "info", "emoji"];
+
+/// The layout of a built-in prompt style in the template language
+pub fn preset(style: &str) -> &'static str {
+    match style {
+        "minimal" => "{$cmd }$char ",
+        "info" => "[$time] $user{@$host}: $dir{ $env}{ $git}{ $cmd} $char ",
+        "emoji" => "🕒 $time 👤 $user{ 🖥️  $host} 📁 $dir{ $env}{ $git}{ $cmd} $char  ",
+        _ => "$user{@$host}:$dir{ $env}{ $git}{ $cmd} $char ",
+    }
+}
+
+/// Segments that can be reordered or disabled in the `[segments]` table
+pub const SEGMENTS: &[&str] = &["time", "user", "host", "dir", "env", "git", "cmd"];
+
+/// Render segments in the given order separated by spaces, skipping empty ones,
+/// followed by the prompt character
+pub fn render_order(order: &[String], lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut parts: Vec<String> = order
+        .iter()
+        .filter_map(|name| lookup(name))
+        .filter(|value| !value.is_empty())
+        .collect();
+    parts.extend(lookup("char"));
+    format!("{} ", parts.join(" "))
+}
+
+/// Placeholder `$fill` renders to, expanded by `expand_fill` once the width is known
+pub const FILL: char = '\u{1}';
+
+/// Expand the `$fill` placeholders on each line with repeats of `symbol`, so
+/// that whatever follows them ends at the right edge of a `columns` wide
+/// terminal. Several fills on one line share the space.
+pub fn expand_fill(prompt: &str, columns: usize, symbol: &str, color: &str, reset: &str) -> String {
+    let symbol_width = display_width(symbol).max(1);
+    let lines: Vec<String> = prompt
+        .split('\n')
+        .map(|line| {
+            let fills = line.matches(FILL).count();
+            if fills == 0 {
+                return line.to_string();
+            }
+            let free = columns.saturating_sub(display_width(line));
+            let mut expanded = String::new();
+            for (i, part) in line.split(FILL).enumerate() {
+                if i > 0 {
+                    // Earlier fills take the remainder of an uneven split
+                    let width = free / fills + usize::from(i <= free % fills);
+                    expanded.push_str(color);
+                    expanded.push_str(&symbol.repeat(width / symbol_width));
+                    expanded.push_str(&" ".repeat(width % symbol_width));
+                    expanded.push_str(reset);
+                }
+                expanded.push_str(part);
+            }
+            expanded
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Render a prompt with `render`, then again without the segments in
+/// `drop_order` one at a time, until its widest line fits in `max_width`
+/// columns. `render` gets the segments to leave out, which are returned along
+/// with the prompt.
+pub fn fit_width<'a>(
+    max_width: usize,
+    drop_order: &'a [String],
+    render: &dyn Fn(&[&str]) -> String,
+) -> (String, Vec<&'a str>) {
+    let mut dropped: Vec<&str> = Vec::new();
+    let mut prompt = render(&dropped);
+    for name in drop_order {
+        if max_line_width(&prompt) <= max_width {
+            break;
+        }
+        dropped.push(name);
+        prompt = render(&dropped);
+    }
+    (prompt, dropped)
+}
+
+/// Columns the widest line of a prompt takes up, with `$fill` taking none
+pub fn max_line_width(prompt: &str) -> usize {
+    prompt.split('\n').map(display_width).max().unwrap_or(0)
+}
+
+/// Number of terminal columns text takes up, skipping ANSI escape sequences
+/// and the markers bash and zsh use to wrap them
+pub fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' if chars.peek() == Some(&'[') => {
+                // CSI sequences end with a byte in the @ to ~ range
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            '\\' if matches!(chars.peek(), Some('[' | ']')) => {
+                chars.next();
+            }
+            '%' if matches!(chars.peek(), Some('{' | '}')) => {
+                chars.next();
+            }
+            _ => width += char_width(c),
+        }
+    }
+    width
+}
+
+/// Columns a character takes up: none for control and combining characters,
+/// two for wide East Asian characters and most emoji
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0..0x20 | 0x7f..0xa0 => 0,
+        0x300..0x370 | 0x200b..0x2010 | 0xfe00..0xfe10 => 0,
+        0x1100..0x1160
+        | 0x2e80..0xa4d0
+        | 0xac00..0xd7a4
+        | 0xf900..0xfb00
+        | 0xfe30..0xfe50
+        | 0xff00..0xff61
+        | 0xffe0..0xffe7
+        | 0x1f300..0x1f650
+        | 0x1f680..0x1f700
+        | 0x1f900..0x1fa00
+        | 0x20000..0x3fffe => 2,
+        _ => 1,
+    }
+}
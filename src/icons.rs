@@ -0,0 +1,39 @@
+// Nerd Font glyphs for the `icons = "nerd-font"` mode
+
+pub const BRANCH: &str = "\u{e0a0} ";
+pub const TAG: &str = "\u{f02b} ";
+pub const STASH: &str = "\u{f01c} ";
+pub const AHEAD: &str = "\u{f062}";
+pub const BEHIND: &str = "\u{f063}";
+pub const FETCH_AGE: &str = "\u{f0ed} ";
+pub const EMAIL: &str = "\u{f0e0} ";
+pub const CLOCK: &str = "\u{f017} ";
+pub const HOME: &str = "\u{f015}";
+pub const OPERATION: &str = "\u{f071} ";
+pub const ERROR: &str = "\u{f00d}";
+pub const FOLDER: &str = "\u{f07b} ";
+pub const PYTHON: &str = "\u{e73c} ";
+pub const NODE: &str = "\u{e718} ";
+pub const CONTAINER: &str = "\u{f308} ";
+pub const GCP: &str = "\u{e7b2} ";
+pub const NIX: &str = "\u{f313} ";
+pub const LINUX: &str = "\u{f17c} ";
+pub const LOAD: &str = "\u{f4bc} ";
+pub const TMUX: &str = "\u{ebc8} ";
+pub const PROMPT: &str = "\u{276f}";
+
+/// The emoji style's layout with glyphs in place of its emoji, leaving the
+/// user and directory icons to their segments
+pub const EMOJI_LAYOUT: &str =
+    "\u{f017} $time $user{ \u{f108} $host} $dir{ $env}{ $git}{ $cmd} $char ";
+
+/// Logo of the operating system the prompt runs on
+pub fn os_symbol() -> &'static str {
+    match std::env::consts::OS {
+        "linux" => LINUX,
+        "macos" => "\u{f179} ",
+        "windows" => "\u{f17a} ",
+        "freebsd" => "\u{f30c} ",
+        _ => "",
+    }
+}
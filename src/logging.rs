@@ -0,0 +1,46 @@
+// Diagnostic logging, off unless asked for with LOCO_PILOT_LOG or --verbose
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io;
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+
+/// Environment variable holding the log level: error, warn, info, debug or trace
+pub const LEVEL_VAR: &str = "LOCO_PILOT_LOG";
+
+/// Environment variable naming a file to append the log to instead of stderr
+pub const FILE_VAR: &str = "LOCO_PILOT_LOG_FILE";
+
+/// Start logging at the level from LOCO_PILOT_LOG, at least debug when `verbose`
+pub fn init(verbose: bool) {
+    let level = level(env::var(LEVEL_VAR).ok().as_deref(), verbose);
+    if level == LevelFilter::OFF {
+        return;
+    }
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false);
+    // Anything on stderr lands in the middle of the prompt, so a file is kinder
+    let file = env::var_os(FILE_VAR)
+        .filter(|path| !path.is_empty())
+        .and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok());
+    let _ = match file {
+        Some(file) => builder.with_writer(Mutex::new(file)).try_init(),
+        None => builder.with_writer(io::stderr).try_init(),
+    };
+}
+
+/// The level for a LOCO_PILOT_LOG value. Values that aren't a level, like
+/// `yes`, still turn debug logging on.
+pub fn level(value: Option<&str>, verbose: bool) -> LevelFilter {
+    let from_env = value
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse::<LevelFilter>().unwrap_or(LevelFilter::DEBUG));
+    match from_env {
+        Some(level) if verbose => level.max(LevelFilter::DEBUG),
+        Some(level) => level,
+        None if verbose => LevelFilter::DEBUG,
+        None => LevelFilter::OFF,
+    }
+}
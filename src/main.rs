@@ -1,14 +1,40 @@
+use base64::prelude::{BASE64_STANDARD, Engine as _};
 use chrono::Local;
-use clap::{Parser, Subcommand};
+use chrono::format::{Item, StrftimeItems};
+use chrono_tz::Tz;
+use clap::{CommandFactory, Parser, Subcommand};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
-use std::process::Command;
-use std::sync::Mutex;
-use std::time::{Duration, Instant};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, error, warn};
+
+mod color;
+#[cfg(unix)]
+mod daemon;
+mod disk_cache;
+mod environment;
+mod format;
+mod icons;
+mod logging;
+mod presets;
+mod segments;
+mod shell;
+mod starship;
+mod terminal;
+mod test_mode;
+mod update;
+
+use environment::Env;
+use segments::keymap::ViMode;
+use shell::ShellKind;
 
 // Add test_utils module for unit testing
 #[cfg(test)]
@@ -20,583 +46,6975 @@ type CachedItem<T> = Option<(T, Instant)>;
 /// Type alias for the path cache tuple - contains current directory, home directory, and hostname
 type PathCacheTuple = (CachedItem<String>, CachedItem<String>, CachedItem<String>);
 
-/// Cache for git information to avoid repeated expensive git operations
-static GIT_INFO_CACHE: Lazy<Mutex<Option<(GitStatus, Instant)>>> = Lazy::new(|| Mutex::new(None));
+/// Lock one of the caches below, even when a panic left it poisoned. They only
+/// hold values that are replaced wholesale, so a poisoned one is still usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
 /// Cache for filesystem paths and environment variables
 static PATH_CACHE: Lazy<Mutex<PathCacheTuple>> = Lazy::new(|| Mutex::new((None, None, None)));
 
-/// Maximum age of cached git info in seconds
+/// Maximum age in seconds of a git status reused from an earlier prompt
 const GIT_CACHE_TTL_SECS: u64 = 2;
 
+/// Maximum age in seconds of a hostname looked up with the `hostname` command
+const HOSTNAME_CACHE_TTL_SECS: u64 = 3600;
+
 /// Maximum age of cached paths in seconds
 const PATH_CACHE_TTL_SECS: u64 = 5;
 
 /// Configuration for loco-pilot
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 struct Config {
+    /// Version of the config format the file was written for
+    version: u32,
     /// The default style to use for the prompt
     style: String,
+    /// The shell the prompt escapes are generated for
+    shell: String,
+    /// Prompt layout template, e.g. "$time $user@$host $dir $git $char ", empty uses the style's
+    format: String,
+    /// Right prompt template for shells that have one, e.g. "$time{ $cmd}", empty shows nothing
+    right_format: String,
+    /// Icon set for the default symbols, "default" or "nerd-font"
+    icons: String,
+    /// Only print ASCII, turned on automatically when the locale isn't UTF-8
+    ascii: bool,
+    /// Leave colors and terminal control sequences out of the prompt, as do
+    /// `--no-color` and the NO_COLOR environment variable
+    no_color: bool,
+    /// Milliseconds the whole prompt may take to render, 0 for no limit
+    prompt_timeout: u64,
+    /// Shown for segments that missed `prompt_timeout` and have nothing cached
+    timeout_placeholder: String,
     /// Whether to show git information
     show_git: bool,
+    /// Git segment settings
+    git: GitConfig,
     /// Custom colors for different parts of the prompt
     colors: ColorConfig,
+    /// Exit status segment settings
+    status: StatusConfig,
+    /// Command duration segment settings
+    duration: DurationConfig,
+    /// Python virtualenv segment settings
+    python: PythonConfig,
+    /// Conda environment segment settings
+    conda: CondaConfig,
+    /// Node.js version segment settings
+    node: NodeConfig,
+    /// Google Cloud project segment settings
+    gcp: GcpConfig,
+    /// Nix dev shell segment settings
+    nix: NixConfig,
+    /// System load segment settings
+    load: LoadConfig,
+    /// Time segment settings
+    time: TimeConfig,
+    /// Hostname segment settings
+    hostname: HostnameConfig,
+    /// Directory segment settings
+    directory: DirectoryConfig,
+    /// Root/sudo indicator settings
+    root: RootConfig,
+    /// tmux/screen session segment settings
+    tmux: TmuxConfig,
+    /// Environment variable segment settings
+    env_var: EnvVarConfig,
+    /// Vi keymap indicator settings
+    keymap: KeymapConfig,
+    /// Prompt character settings
+    character: CharacterConfig,
+    /// WSL indicator settings
+    wsl: WslConfig,
+    /// Container indicator settings
+    container: ContainerConfig,
+    /// Fill settings for the `$fill` variable
+    fill: FillConfig,
+    /// Dropping segments in narrow terminals
+    truncation: TruncationConfig,
+    /// Terminal integration settings
+    terminal: TerminalConfig,
+    /// External plugin segment settings
+    plugins: PluginsConfig,
+    /// Clipboard settings for the copy commands
+    clipboard: ClipboardConfig,
+    /// Segment order and visibility
+    segments: SegmentsConfig,
 }
 
 /// Color configuration
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 struct ColorConfig {
     username: String,
+    username_root: String,
     hostname: String,
+    hostname_ssh: String,
     directory: String,
     git_branch: String,
     git_dirty: String,
+    git_stash: String,
+    git_staged: String,
+    git_deleted: String,
+    git_untracked: String,
+    git_operation: String,
+    git_tag: String,
+    git_upstream: String,
+    git_commit_age: String,
+    git_email: String,
+    git_sparse: String,
+    git_stale: String,
     time: String,
+    status: String,
+    duration: String,
+    python: String,
+    conda: String,
+    node: String,
+    gcp: String,
+    nix: String,
+    tmux: String,
+    env_var: String,
+    keymap_normal: String,
+    keymap_insert: String,
+    keymap_visual: String,
+    keymap_replace: String,
+    character_success: String,
+    character_error: String,
+    character_root: String,
+    wsl: String,
+    container: String,
+    fill: String,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            style: "default".to_string(),
-            show_git: true,
-            colors: ColorConfig::default(),
+/// Git segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct GitConfig {
+    /// Whether to show how long ago the repository last fetched
+    show_fetch_age: bool,
+    /// Symbol shown before the fetch age
+    fetch_age_symbol: String,
+    /// Seconds after the last fetch when ahead/behind counts are dimmed, 0 never dims them
+    stale_fetch_age: u64,
+    /// Whether to mark repositories with sparse-checkout enabled
+    show_sparse: bool,
+    /// Marker shown for sparse checkouts
+    sparse_symbol: String,
+    /// Show the effective user.email whenever it differs from this one, empty never shows it
+    expected_email: String,
+    /// Symbol shown before an unexpected email
+    email_symbol: String,
+    /// Whether to show how long ago the HEAD commit was made
+    show_commit_age: bool,
+    /// Symbol shown before the commit age
+    commit_age_symbol: String,
+    /// Maximum branch name length before it's truncated, 0 never truncates
+    truncate_branch: usize,
+    /// Symbol marking where a long branch name was cut
+    truncate_symbol: String,
+    /// Where long branch names are cut: "end" or "middle"
+    truncate_position: String,
+    /// Answer from an on-disk cache and refresh it in the background when stale
+    async_refresh: bool,
+    /// Seconds before the on-disk git cache is refreshed
+    cache_ttl: u64,
+    /// Milliseconds to wait for git subprocesses before showing just the branch, 0 waits forever
+    timeout_ms: u64,
+    /// Repository paths or glob patterns where the git segment is hidden
+    disabled_repos: Vec<String>,
+    /// Repository paths or glob patterns where only the branch is shown, skipping `git status`
+    branch_only_repos: Vec<String>,
+    /// Whether to show the upstream branch, e.g. origin/main
+    show_upstream: bool,
+    /// How git status is computed: "git" runs the git CLI, "gix" stays in-process
+    backend: String,
+    /// Whether to show an icon for the provider hosting the origin remote
+    show_remote: bool,
+    /// Symbols for remote hosts, keyed by domain
+    remote_symbols: BTreeMap<String, String>,
+    /// Symbol for remote hosts missing from remote_symbols, e.g. self-hosted servers
+    remote_default_symbol: String,
+    /// Whether to show the tag pointing at HEAD
+    show_tag: bool,
+    /// Symbol shown before the tag name
+    tag_symbol: String,
+    /// Whether to show an in-progress merge, rebase, cherry-pick, revert or bisect
+    show_operation: bool,
+    /// Whether to show the number of staged files
+    show_staged: bool,
+    /// Whether to show the number of modified files
+    show_modified: bool,
+    /// Whether to show the number of deleted files
+    show_deleted: bool,
+    /// Whether to show the number of untracked files
+    show_untracked: bool,
+    /// Symbol shown before the branch name
+    branch_symbol: String,
+    /// Symbol shown before the branch name in the emoji style
+    emoji_branch_symbol: String,
+    /// Symbol shown before the number of commits ahead of upstream
+    ahead_symbol: String,
+    /// Symbol shown before the number of commits behind upstream
+    behind_symbol: String,
+    /// Symbol shown before the number of staged files
+    staged_symbol: String,
+    /// Symbol shown before the number of modified files
+    modified_symbol: String,
+    /// Symbol shown before the number of deleted files
+    deleted_symbol: String,
+    /// Symbol shown before the number of untracked files
+    untracked_symbol: String,
+    /// Whether to show the number of stash entries
+    show_stash: bool,
+    /// Symbol shown before the stash count
+    stash_symbol: String,
+}
+
+/// Exit status segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct StatusConfig {
+    /// Symbol shown before a non-zero exit code
+    symbol: String,
+    /// How pipeline exit codes are shown: "full" for every stage, "collapse" for the failing one
+    pipestatus_format: String,
+}
+
+/// Python virtualenv segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct PythonConfig {
+    /// Whether to show the active virtualenv
+    show: bool,
+    /// Symbol shown before the virtualenv name
+    symbol: String,
+}
+
+/// Conda environment segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct CondaConfig {
+    /// Whether to show the active conda environment
+    show: bool,
+    /// Symbol shown before the environment name
+    symbol: String,
+    /// Whether to hide the segment when the base environment is active
+    hide_base: bool,
+}
+
+/// Node.js version segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct NodeConfig {
+    /// Whether to show the node version inside Node.js projects
+    show: bool,
+    /// Symbol shown before the node version
+    symbol: String,
+    /// How long the detected node version is cached on disk, in seconds
+    cache_ttl: u64,
+}
+
+/// Google Cloud project segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct GcpConfig {
+    /// Whether to show the active gcloud project
+    show: bool,
+    /// Whether to prefix the project with the active account
+    show_account: bool,
+    /// Symbol shown before the project
+    symbol: String,
+}
+
+/// Nix dev shell segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct NixConfig {
+    /// Whether to show an indicator inside nix-shell or `nix develop`
+    show: bool,
+    /// Symbol shown before the shell name
+    symbol: String,
+}
+
+/// WSL segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct WslConfig {
+    /// Whether to show the distribution name when running under WSL
+    show: bool,
+    /// Symbol shown before the distribution name
+    symbol: String,
+    /// Show Windows drives mounted under /mnt as drive letters, e.g. `C:/Users`
+    windows_paths: bool,
+}
+
+/// Segment order and visibility configuration
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+struct SegmentsConfig {
+    /// Segments to show before the prompt character, empty keeps the style's layout
+    order: Vec<String>,
+    /// Segments that are never shown
+    disabled: Vec<String>,
+}
+
+/// Visual settings that can be shared as a theme file, separate from what
+/// the prompt shows
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct Theme {
+    colors: ColorConfig,
+    character: CharacterConfig,
+    keymap: KeymapConfig,
+}
+
+impl Theme {
+    fn from_config(config: &Config) -> Self {
+        Theme {
+            colors: config.colors.clone(),
+            character: config.character.clone(),
+            keymap: config.keymap.clone(),
         }
     }
+
+    fn apply(self, config: &mut Config) {
+        config.colors = self.colors;
+        config.character = self.character;
+        config.keymap = self.keymap;
+    }
 }
 
-impl Default for ColorConfig {
+impl Default for Theme {
     fn default() -> Self {
-        ColorConfig {
-            username: "green".to_string(),
-            hostname: "yellow".to_string(),
-            directory: "cyan".to_string(),
-            git_branch: "green".to_string(),
-            git_dirty: "red".to_string(),
-            time: "blue".to_string(),
-        }
+        Theme::from_config(&Config::default())
     }
 }
 
-// Cache for configuration
-static CONFIG_CACHE: Lazy<Mutex<Option<(Config, Instant)>>> = Lazy::new(|| Mutex::new(None));
+/// Clipboard configuration for the copy commands
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct ClipboardConfig {
+    /// How values are copied: "auto" uses a local clipboard tool and falls back to
+    /// OSC 52 over SSH, "osc52" always asks the terminal to set the clipboard
+    backend: String,
+}
 
-/// Maximum age of cached config in seconds
-const CONFIG_CACHE_TTL_SECS: u64 = 60;
+/// Container segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct ContainerConfig {
+    /// Whether to show an indicator inside docker, podman or other containers
+    show: bool,
+    /// Symbol shown before the container engine and name
+    symbol: String,
+}
 
-/// Gets the config file path
-fn get_config_path() -> Option<PathBuf> {
-    // This could be cached for even more performance, but it's rarely called
-    dirs::config_dir().map(|mut path| {
-        path.push("loco-pilot");
-        fs::create_dir_all(&path).ok()?;
-        path.push("config.toml");
-        Some(path)
-    })?
+/// External plugin segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct PluginsConfig {
+    /// Directories searched for `loco-pilot-<name>` executables before PATH
+    dirs: Vec<String>,
+    /// Milliseconds a plugin may run before its segment is left out
+    timeout_ms: u64,
 }
 
-/// Load configuration from file with caching
-fn load_config() -> Config {
-    let mut cache = CONFIG_CACHE.lock().unwrap();
-    if let Some((cached_config, timestamp)) = &*cache {
-        if timestamp.elapsed() < Duration::from_secs(CONFIG_CACHE_TTL_SECS) {
-            return cached_config.clone();
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        PluginsConfig {
+            dirs: Vec::new(),
+            timeout_ms: 200,
         }
     }
+}
 
-    let config = if let Some(path) = get_config_path() {
-        if let Ok(content) = fs::read_to_string(path) {
-            toml::from_str::<Config>(&content).unwrap_or_default()
-        } else {
-            Config::default()
-        }
-    } else {
-        Config::default()
-    };
+/// Terminal integration configuration
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+struct TerminalConfig {
+    /// Mark prompts and command output with OSC 133, for jumping between prompts
+    semantic_prompt: bool,
+    /// Report the working directory with OSC 7, so new tabs open in it
+    report_directory: bool,
+    /// Window title template using $user, $host and $dir, empty to leave the title alone
+    title: String,
+}
 
-    *cache = Some((config.clone(), Instant::now()));
-    config
+/// Fill configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct FillConfig {
+    /// Symbol repeated by `$fill` to push the rest of the line to the right edge
+    symbol: String,
 }
 
-/// Save configuration to file
-fn save_config(config: &Config) -> io::Result<()> {
-    let config_path = get_config_path().ok_or_else(|| {
-        io::Error::new(
-            io::ErrorKind::NotFound,
-            "Could not determine config directory",
-        )
-    })?;
+/// Prompt width limit configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct TruncationConfig {
+    /// Fraction of the terminal width the prompt may take up before segments
+    /// are dropped, 0 never drops any
+    max_width: f64,
+    /// Segments dropped in this order until the prompt fits; `dir` is shortened
+    /// to its last component instead
+    drop_order: Vec<String>,
+}
 
-    let content =
-        toml::to_string_pretty(&config).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+/// System load segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct LoadConfig {
+    /// Whether to show the 1-minute load average
+    show: bool,
+    /// Symbol shown before the load average
+    symbol: String,
+}
 
-    let mut file = fs::File::create(config_path)?;
-    file.write_all(content.as_bytes())?;
+/// Hostname segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+struct HostnameConfig {
+    /// Only show the hostname when connected over SSH
+    ssh_only: bool,
+}
 
-    // Update the cache with the new config
-    let mut cache = CONFIG_CACHE.lock().unwrap();
-    *cache = Some((config.clone(), Instant::now()));
+/// Directory segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct DirectoryConfig {
+    /// Paths longer than this many characters are shortened, 0 never shortens them
+    max_length: usize,
+    /// Number of trailing path components kept when shortening
+    keep_components: usize,
+    /// Symbol replacing the components left out of a shortened path
+    truncation_symbol: String,
+    /// Inside a git repository, show the path from the repository root, e.g. `repo/src/lib`
+    truncate_to_repo: bool,
+    /// Labels replacing path prefixes before shortening, keyed by prefix
+    substitutions: BTreeMap<String, String>,
+}
 
-    Ok(())
+/// Root/sudo indicator configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct RootConfig {
+    /// Prompt character used when running as root
+    symbol: String,
 }
 
-/// Enable colors even when not in a terminal
-#[inline]
-fn enable_colors_for_bash() {
-    // Force colored to always output colors, even in non-tty environments
-    colored::control::set_override(true);
+/// tmux/screen session segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct TmuxConfig {
+    /// Whether to show the tmux or screen session name
+    show: bool,
+    /// Symbol shown before the session name
+    symbol: String,
+    /// Hide the hostname inside tmux/screen, since the status bar already shows it
+    hide_hostname: bool,
 }
 
-/// A customizable bash prompt application
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// The style of prompt to display
-    #[arg(short, long, default_value = "default")]
-    style: String,
+/// Environment variable segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+struct EnvVarConfig {
+    /// Name of the variable to show, the segment is disabled when empty
+    name: String,
+    /// Text shown before the value
+    prefix: String,
+    /// Text shown after the value
+    suffix: String,
+    /// Value shown when the variable is unset, the segment is hidden when empty
+    fallback: String,
+}
 
-    #[command(subcommand)]
-    command: Option<Commands>,
+/// Prompt character configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct CharacterConfig {
+    /// Prompt character after a successful command
+    success_symbol: String,
+    /// Prompt character after a failed command
+    error_symbol: String,
+    /// Prompt character after a successful command in the emoji style
+    emoji_success_symbol: String,
+    /// Prompt character after a failed command in the emoji style
+    emoji_error_symbol: String,
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    /// Configure prompt settings
-    Config {
-        /// The key to set
-        key: Option<String>,
-        /// The value to set
-        value: Option<String>,
-    },
+/// Vi keymap indicator configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct KeymapConfig {
+    /// Indicator shown in vi normal mode
+    normal_symbol: String,
+    /// Indicator shown in vi insert mode
+    insert_symbol: String,
+    /// Indicator shown in vi visual mode
+    visual_symbol: String,
+    /// Indicator shown in vi replace mode
+    replace_symbol: String,
+}
 
-    /// Display detailed version information
-    Version,
+/// Time segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct TimeConfig {
+    /// "24h", "12h" or any strftime format string
+    format: String,
+    /// Whether to include seconds, hiding them reduces redraw churn
+    show_seconds: bool,
+    /// Extra IANA timezones shown alongside local time, e.g. "UTC" or "America/New_York"
+    timezones: Vec<String>,
 }
 
-/// Returns the current working directory, with home directory replaced by ~
-fn get_current_dir() -> String {
-    let mut path_cache = PATH_CACHE.lock().unwrap();
-    let (current_dir_cache, home_dir_cache, _) = &*path_cache;
+/// Command duration segment configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+struct DurationConfig {
+    /// Minimum command duration in milliseconds before the segment is shown
+    threshold: u64,
+}
 
-    // Check if we have a cached current directory that's still fresh
-    if let Some((cached_dir, timestamp)) = current_dir_cache {
-        if timestamp.elapsed() < Duration::from_secs(PATH_CACHE_TTL_SECS) {
-            return cached_dir.clone();
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version: CONFIG_VERSION,
+            style: "default".to_string(),
+            shell: "auto".to_string(),
+            format: String::new(),
+            right_format: String::new(),
+            icons: "default".to_string(),
+            ascii: false,
+            no_color: false,
+            prompt_timeout: 0,
+            timeout_placeholder: "…".to_string(),
+            show_git: true,
+            git: GitConfig::default(),
+            colors: ColorConfig::default(),
+            status: StatusConfig::default(),
+            duration: DurationConfig::default(),
+            python: PythonConfig::default(),
+            conda: CondaConfig::default(),
+            node: NodeConfig::default(),
+            gcp: GcpConfig::default(),
+            nix: NixConfig::default(),
+            load: LoadConfig::default(),
+            time: TimeConfig::default(),
+            hostname: HostnameConfig::default(),
+            directory: DirectoryConfig::default(),
+            root: RootConfig::default(),
+            tmux: TmuxConfig::default(),
+            env_var: EnvVarConfig::default(),
+            keymap: KeymapConfig::default(),
+            character: CharacterConfig::default(),
+            wsl: WslConfig::default(),
+            container: ContainerConfig::default(),
+            fill: FillConfig::default(),
+            truncation: TruncationConfig::default(),
+            terminal: TerminalConfig::default(),
+            plugins: PluginsConfig::default(),
+            clipboard: ClipboardConfig::default(),
+            segments: SegmentsConfig::default(),
         }
     }
+}
 
-    let current_dir = env::current_dir().unwrap_or_default();
-    let current_path = current_dir.display().to_string();
+impl Default for TruncationConfig {
+    fn default() -> Self {
+        TruncationConfig {
+            max_width: 1.0,
+            drop_order: ["time", "env", "host", "dir", "git"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
 
-    // Check if we have a cached home directory
-    let home_path = if let Some((cached_home, timestamp)) = home_dir_cache {
-        if timestamp.elapsed() < Duration::from_secs(PATH_CACHE_TTL_SECS) {
-            cached_home.clone()
-        } else if let Some(home_dir) = dirs::home_dir() {
-            let home_path = home_dir.display().to_string();
-            path_cache.1 = Some((home_path.clone(), Instant::now()));
-            home_path
-        } else {
-            String::new()
+impl Default for ColorConfig {
+    fn default() -> Self {
+        ColorConfig {
+            username: "green".to_string(),
+            username_root: "red".to_string(),
+            hostname: "yellow".to_string(),
+            hostname_ssh: "yellow".to_string(),
+            directory: "cyan".to_string(),
+            git_branch: "green".to_string(),
+            git_dirty: "red".to_string(),
+            git_stash: "bright_black".to_string(),
+            git_staged: "green".to_string(),
+            git_deleted: "red".to_string(),
+            git_untracked: "bright_red".to_string(),
+            git_operation: "bold_yellow".to_string(),
+            git_tag: "yellow".to_string(),
+            git_upstream: "cyan".to_string(),
+            git_commit_age: "bright_black".to_string(),
+            git_email: "bold_red".to_string(),
+            git_sparse: "bright_black".to_string(),
+            git_stale: "bright_black".to_string(),
+            time: "blue".to_string(),
+            status: "red".to_string(),
+            duration: "yellow".to_string(),
+            python: "yellow".to_string(),
+            conda: "green".to_string(),
+            node: "green".to_string(),
+            gcp: "blue".to_string(),
+            nix: "bright_blue".to_string(),
+            tmux: "magenta".to_string(),
+            env_var: "cyan".to_string(),
+            keymap_normal: "yellow".to_string(),
+            keymap_insert: "green".to_string(),
+            keymap_visual: "magenta".to_string(),
+            keymap_replace: "red".to_string(),
+            character_success: "none".to_string(),
+            character_error: "red".to_string(),
+            character_root: "none".to_string(),
+            wsl: "bright_cyan".to_string(),
+            container: "bright_magenta".to_string(),
+            fill: "bright_black".to_string(),
         }
-    } else if let Some(home_dir) = dirs::home_dir() {
-        let home_path = home_dir.display().to_string();
-        path_cache.1 = Some((home_path.clone(), Instant::now()));
-        home_path
-    } else {
-        String::new()
-    };
+    }
+}
 
-    let result = if !home_path.is_empty() && current_path.starts_with(&home_path) {
-        current_path.replacen(&home_path, "~", 1)
-    } else {
-        current_path
-    };
+impl Default for GitConfig {
+    fn default() -> Self {
+        GitConfig {
+            show_fetch_age: false,
+            fetch_age_symbol: "⇣ ".to_string(),
+            stale_fetch_age: 86400,
+            show_sparse: true,
+            sparse_symbol: "sparse".to_string(),
+            expected_email: String::new(),
+            email_symbol: "✉ ".to_string(),
+            show_commit_age: false,
+            commit_age_symbol: "⏱ ".to_string(),
+            truncate_branch: 0,
+            truncate_symbol: "…".to_string(),
+            truncate_position: "end".to_string(),
+            async_refresh: false,
+            cache_ttl: 5,
+            timeout_ms: 500,
+            disabled_repos: Vec::new(),
+            branch_only_repos: Vec::new(),
+            show_upstream: false,
+            backend: "git".to_string(),
+            show_remote: false,
+            remote_symbols: BTreeMap::from([
+                ("bitbucket.org".to_string(), "🪣".to_string()),
+                ("github.com".to_string(), "🐙".to_string()),
+                ("gitlab.com".to_string(), "🦊".to_string()),
+            ]),
+            remote_default_symbol: "🏠".to_string(),
+            show_tag: false,
+            tag_symbol: "🏷 ".to_string(),
+            show_operation: true,
+            show_staged: true,
+            show_modified: true,
+            show_deleted: true,
+            show_untracked: true,
+            branch_symbol: String::new(),
+            emoji_branch_symbol: "🔖 ".to_string(),
+            ahead_symbol: "↑".to_string(),
+            behind_symbol: "↓".to_string(),
+            staged_symbol: "+".to_string(),
+            modified_symbol: "~".to_string(),
+            deleted_symbol: "-".to_string(),
+            untracked_symbol: "?".to_string(),
+            show_stash: true,
+            stash_symbol: "⚑".to_string(),
+        }
+    }
+}
 
-    // Update the cache
-    path_cache.0 = Some((result.clone(), Instant::now()));
+impl Default for StatusConfig {
+    fn default() -> Self {
+        StatusConfig {
+            symbol: "✘".to_string(),
+            pipestatus_format: "full".to_string(),
+        }
+    }
+}
 
-    result
+impl Default for PythonConfig {
+    fn default() -> Self {
+        PythonConfig {
+            show: true,
+            symbol: "🐍 ".to_string(),
+        }
+    }
 }
 
-/// Returns a shortened version of the current directory path if it's longer than 15 characters
-#[inline]
-fn get_shortened_dir() -> String {
-    let full_path = get_current_dir();
+impl Default for CondaConfig {
+    fn default() -> Self {
+        CondaConfig {
+            show: true,
+            symbol: "🅒 ".to_string(),
+            hide_base: true,
+        }
+    }
+}
 
-    // If the path is short enough, return it as is
-    if full_path.len() <= 15 {
-        return full_path;
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            show: true,
+            symbol: "⬢ ".to_string(),
+            cache_ttl: 300,
+        }
     }
+}
 
-    // Split the path by separator
-    let components: Vec<&str> = full_path.split('/').collect();
+impl Default for GcpConfig {
+    fn default() -> Self {
+        GcpConfig {
+            show: false,
+            show_account: false,
+            symbol: "☁️ ".to_string(),
+        }
+    }
+}
 
-    // If we have 3 or fewer components, just return the full path
-    if components.len() <= 3 {
-        return full_path;
+impl Default for NixConfig {
+    fn default() -> Self {
+        NixConfig {
+            show: true,
+            symbol: "❄️ ".to_string(),
+        }
     }
+}
 
-    // Get the first component (usually ~ or root)
-    let first = components.first().unwrap_or(&"");
+impl Default for DirectoryConfig {
+    fn default() -> Self {
+        DirectoryConfig {
+            max_length: 15,
+            keep_components: 2,
+            truncation_symbol: "...".to_string(),
+            truncate_to_repo: false,
+            substitutions: BTreeMap::new(),
+        }
+    }
+}
 
-    // Get the last two components
-    let last_two = if components.len() >= 2 {
-        let len = components.len();
-        format!("{}/{}", components[len - 2], components[len - 1])
-    } else {
-        components.last().unwrap_or(&"").to_string()
-    };
+impl Default for WslConfig {
+    fn default() -> Self {
+        WslConfig {
+            show: true,
+            symbol: "🐧 ".to_string(),
+            windows_paths: true,
+        }
+    }
+}
 
-    // Format with ellipsis
-    format!("{}/.../{}", first, last_two)
+impl Default for FillConfig {
+    fn default() -> Self {
+        FillConfig {
+            symbol: " ".to_string(),
+        }
+    }
 }
 
-/// Get the hostname of the machine with caching
-fn get_hostname() -> String {
-    let mut path_cache = PATH_CACHE.lock().unwrap();
-    let (_, _, hostname_cache) = &*path_cache;
+impl Default for ContainerConfig {
+    fn default() -> Self {
+        ContainerConfig {
+            show: true,
+            symbol: "📦 ".to_string(),
+        }
+    }
+}
 
-    // Check if we have a cached hostname that's still fresh
-    if let Some((cached_hostname, timestamp)) = hostname_cache {
-        if timestamp.elapsed() < Duration::from_secs(PATH_CACHE_TTL_SECS) {
-            return cached_hostname.clone();
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        ClipboardConfig {
+            backend: "auto".to_string(),
         }
     }
+}
 
-    // Try multiple ways to get the hostname
-    let hostname = if let Ok(hostname) = env::var("HOSTNAME") {
-        hostname
-    } else if let Ok(hostname) = env::var("HOST") {
-        hostname
-    } else if let Ok(output) = Command::new("hostname").output() {
-        if let Ok(hostname) = String::from_utf8(output.stdout) {
-            hostname.trim().to_string()
-        } else {
-            "localhost".to_string()
+impl Default for LoadConfig {
+    fn default() -> Self {
+        LoadConfig {
+            show: false,
+            symbol: "load ".to_string(),
         }
-    } else {
-        "localhost".to_string()
-    };
+    }
+}
 
-    // Update the cache
-    path_cache.2 = Some((hostname.clone(), Instant::now()));
+impl Default for RootConfig {
+    fn default() -> Self {
+        RootConfig {
+            symbol: "#".to_string(),
+        }
+    }
+}
 
-    hostname
+impl Default for TmuxConfig {
+    fn default() -> Self {
+        TmuxConfig {
+            show: true,
+            symbol: "⧉ ".to_string(),
+            hide_hostname: false,
+        }
+    }
 }
 
-/// Git repository status information
-#[derive(Debug, Clone)]
-struct GitStatus {
-    branch: String,
-    dirty: bool,
-    ahead: usize,
-    behind: usize,
+impl Default for CharacterConfig {
+    fn default() -> Self {
+        CharacterConfig {
+            success_symbol: "$".to_string(),
+            error_symbol: "$".to_string(),
+            emoji_success_symbol: "➡️".to_string(),
+            emoji_error_symbol: "➡️".to_string(),
+        }
+    }
 }
 
-/// Get git branch information if in a git repository
-/// This is a highly optimized version that reduces the number of git command executions
-fn get_git_info() -> Option<GitStatus> {
-    // Check the cache first
-    let mut cache = GIT_INFO_CACHE.lock().unwrap();
-    if let Some((cached_status, timestamp)) = &*cache {
-        if timestamp.elapsed() < Duration::from_secs(GIT_CACHE_TTL_SECS) {
-            return Some(cached_status.clone());
+impl Default for KeymapConfig {
+    fn default() -> Self {
+        KeymapConfig {
+            normal_symbol: "[N]".to_string(),
+            insert_symbol: "[I]".to_string(),
+            visual_symbol: "[V]".to_string(),
+            replace_symbol: "[R]".to_string(),
         }
     }
+}
 
-    let current_dir = match env::current_dir() {
-        Ok(dir) => dir,
-        Err(_) => return None,
-    };
+impl Default for TimeConfig {
+    fn default() -> Self {
+        TimeConfig {
+            format: "24h".to_string(),
+            show_seconds: true,
+            timezones: Vec::new(),
+        }
+    }
+}
 
-    // Quick check if this is a git repository
-    // This avoids expensive operations if we're not in a git repo
-    let git_dir = current_dir.join(".git");
-    if !git_dir.exists() {
-        return None;
+impl Default for DurationConfig {
+    fn default() -> Self {
+        DurationConfig { threshold: 2000 }
     }
+}
 
-    // Use a single git command to get branch and status information
-    // This is much faster than multiple separate calls
-    let output = match Command::new("git")
-        .args(["status", "--branch", "--porcelain=v2"])
-        .current_dir(&current_dir)
-        .output()
-    {
-        Ok(output) => output,
-        Err(_) => return None,
-    };
+// Cache for the config file's settings, with its path and modification time when it was read
+type CachedConfig = Option<(Config, Option<PathBuf>, Option<SystemTime>)>;
+static CONFIG_CACHE: Lazy<Mutex<CachedConfig>> = Lazy::new(|| Mutex::new(None));
 
-    if !output.status.success() {
-        return None;
+/// Config file path given with --config, which takes precedence over LOCO_PILOT_CONFIG
+static CONFIG_PATH_OVERRIDE: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+/// Gets the config file path
+fn get_config_path() -> Option<PathBuf> {
+    config_path_in(&Env::default())
+}
+
+/// The config file path for the environment a prompt is rendered with
+fn config_path_in(env: &Env) -> Option<PathBuf> {
+    let custom_path = lock(&CONFIG_PATH_OVERRIDE).clone().or_else(|| {
+        env.var_os("LOCO_PILOT_CONFIG")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    });
+    if let Some(path) = custom_path {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+        if let Some(parent) = parent {
+            fs::create_dir_all(parent).ok()?;
+        }
+        return Some(path);
     }
 
-    let status_output = String::from_utf8_lossy(&output.stdout);
-    let lines: Vec<&str> = status_output.lines().collect();
+    // This could be cached for even more performance, but it's rarely called
+    let dir = dirs::config_dir()?.join("loco-pilot");
+    fs::create_dir_all(&dir).ok()?;
+    // config.toml wins when there are several, and is the one created
+    let existing = ["config.toml", "config.yaml", "config.yml", "config.json"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists());
+    Some(existing.unwrap_or_else(|| dir.join("config.toml")))
+}
 
-    // Parse branch information from the output
-    let mut branch = String::from("unknown");
-    let mut ahead = 0;
-    let mut behind = 0;
+/// Format of a config file, picked by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
 
-    for line in &lines {
-        if let Some(branch_name) = line.strip_prefix("# branch.head ") {
-            branch = branch_name.to_string();
-        } else if let Some(branch_ab_info) = line.strip_prefix("# branch.ab ") {
-            let parts: Vec<&str> = branch_ab_info.split_whitespace().collect();
-            if parts.len() == 2 {
-                ahead = parts[1].parse::<i32>().unwrap_or(0) as usize;
-                behind = parts[0].parse::<i32>().unwrap_or(0).unsigned_abs() as usize;
-            }
+impl ConfigFormat {
+    /// The format for a config path, defaulting to TOML for unknown extensions
+    fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
         }
     }
 
-    // If branch is HEAD, we're in detached HEAD state - get commit hash
-    if branch == "HEAD" {
-        if let Ok(commit_output) = Command::new("git")
-            .args(["rev-parse", "--short", "HEAD"])
-            .current_dir(&current_dir)
-            .output()
+    /// Parse a config, with a short error message when it's invalid
+    fn parse(self, content: &str) -> Result<Config, String> {
+        match self {
+            ConfigFormat::Toml => toml::from_str(content).map_err(|e| e.message().to_string()),
+            ConfigFormat::Yaml => serde_norway::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Parse a config file into untyped values, for checking its keys
+    fn parse_value(self, content: &str) -> Result<toml::Value, String> {
+        match self {
+            ConfigFormat::Toml => content
+                .parse::<toml::Table>()
+                .map(toml::Value::Table)
+                .map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_norway::from_str(content).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(content).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Serialize a config in this format
+    fn serialize(self, config: &Config) -> Result<String, String> {
+        match self {
+            ConfigFormat::Toml => toml::to_string_pretty(config).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_norway::to_string(config).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::to_string_pretty(config)
+                .map(|json| json + "\n")
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Modification time of the config file, or None when it doesn't exist
+fn config_file_mtime(path: Option<&Path>) -> Option<SystemTime> {
+    fs::metadata(path?).ok()?.modified().ok()
+}
+
+/// Load configuration from file and environment overrides with caching. The
+/// file is reread when its path or modification time changes, so edits show
+/// up on the next prompt; environment overrides are applied on every load.
+fn load_config() -> Config {
+    load_config_in(&Env::default())
+}
+
+/// Load configuration as `load_config` does, for the environment a prompt is
+/// rendered with
+fn load_config_in(env: &Env) -> Config {
+    let path = config_path_in(env);
+    let mtime = config_file_mtime(path.as_deref());
+    let mut cache = lock(&CONFIG_CACHE);
+    let file_config = match &*cache {
+        Some((cached_config, cached_path, cached_mtime))
+            if *cached_path == path && *cached_mtime == mtime =>
         {
-            if commit_output.status.success() {
-                if let Ok(commit_hash) = String::from_utf8(commit_output.stdout) {
-                    branch = format!("detached@{}", commit_hash.trim());
-                }
+            debug!("config cache hit");
+            cached_config.clone()
+        }
+        _ => {
+            debug!("config cache miss");
+            let file_config = read_config_from(path.clone());
+            *cache = Some((file_config.clone(), path, mtime));
+            file_config
+        }
+    };
+    apply_env_overrides(file_config, env.vars().into_iter())
+}
+
+/// Read the config file alone, without environment overrides
+fn read_config_file() -> Config {
+    read_config_from(get_config_path())
+}
+
+/// Read the config file at `path`, or the defaults without one
+fn read_config_from(path: Option<PathBuf>) -> Config {
+    if let Some(path) = path {
+        if let Ok(content) = fs::read_to_string(&path) {
+            debug!("reading config from {}", path.display());
+            let config = parse_config(&content, ConfigFormat::from_path(&path));
+            if let Ok(config) = &config
+                && config.version > CONFIG_VERSION
+            {
+                eprintln!(
+                    "loco-pilot: {} is from a newer version of loco-pilot, some settings may be ignored",
+                    path.display()
+                );
             }
+            config.unwrap_or_else(|e| {
+                warn!("invalid config {}: {}", path.display(), e);
+                // Don't silently ignore the user's settings
+                eprintln!(
+                    "loco-pilot: invalid config {}: {} (using defaults, see `loco-pilot config validate`)",
+                    path.display(),
+                    e
+                );
+                Config::default()
+            })
+        } else {
+            debug!("no config at {}, using defaults", path.display());
+            Config::default()
+        }
+    } else {
+        debug!("no config directory, using defaults");
+        Config::default()
+    }
+}
+
+/// Current version of the config format, written to new and saved files.
+/// Bump it along with a new entry in MIGRATIONS whenever keys are renamed or
+/// tables restructured.
+const CONFIG_VERSION: u32 = 2;
+
+/// An upgrade of config files from one format version to the next
+struct Migration {
+    /// Version the migration upgrades from
+    from: u32,
+    /// Rewrite the settings in place
+    apply: fn(&mut toml::Table),
+}
+
+/// Migrations in order, each upgrading files by one version
+const MIGRATIONS: &[Migration] = &[Migration {
+    from: 1,
+    apply: merge_color_table,
+}];
+
+/// Version 2: `config set` and `config show` name colors `color.<name>`, so
+/// a hand-written `[color]` table was silently ignored. Its colors move into
+/// `[colors]`, where the ones already set there win.
+fn merge_color_table(table: &mut toml::Table) {
+    if !matches!(table.get("color"), Some(toml::Value::Table(_))) {
+        return;
+    }
+    let Some(toml::Value::Table(color)) = table.remove("color") else {
+        return;
+    };
+    let colors = table
+        .entry("colors")
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    if let toml::Value::Table(colors) = colors {
+        for (name, value) in color {
+            colors.entry(name).or_insert(value);
         }
     }
+}
+
+/// The format version of untyped config settings. Files from before
+/// versioning are version 1.
+fn config_version(table: &toml::Table) -> u32 {
+    table
+        .get("version")
+        .and_then(toml::Value::as_integer)
+        .and_then(|version| u32::try_from(version).ok())
+        .unwrap_or(1)
+}
 
-    // Check for dirty status - anything that starts with a space and a single letter
-    // indicates a change in git status
-    let dirty = lines
+/// Upgrade untyped config settings to the latest version. Returns the
+/// version the settings had when any migration was needed.
+fn migrate_config(table: &mut toml::Table, migrations: &[Migration]) -> Option<u32> {
+    let version = config_version(table);
+    let pending: Vec<&Migration> = migrations
         .iter()
-        .any(|line| !line.starts_with('#') && line.len() > 1 && !line.starts_with(' '));
+        .filter(|migration| migration.from >= version)
+        .collect();
+    if pending.is_empty() {
+        return None;
+    }
 
-    let git_status = GitStatus {
-        branch,
-        dirty,
-        ahead,
-        behind,
+    for migration in pending {
+        (migration.apply)(table);
+    }
+    let latest = migrations.iter().map(|migration| migration.from + 1).max();
+    let latest = latest.unwrap_or(version).max(CONFIG_VERSION);
+    table.insert("version".to_string(), toml::Value::Integer(latest.into()));
+    Some(version)
+}
+
+/// Parse a config file, upgrading it from an older format version. Only the
+/// loaded settings are upgraded; `config migrate` and saving rewrite the file.
+fn parse_config(content: &str, format: ConfigFormat) -> Result<Config, String> {
+    let Ok(toml::Value::Table(mut table)) = format.parse_value(content) else {
+        return format.parse(content);
     };
+    if migrate_config(&mut table, MIGRATIONS).is_none() {
+        return format.parse(content);
+    }
+    toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| e.message().to_string())
+}
 
-    // Update the cache
-    *cache = Some((git_status.clone(), Instant::now()));
-    Some(git_status)
+/// The format version of a config file, or None when it can't be parsed
+fn config_file_version(content: &str, format: ConfigFormat) -> Option<u32> {
+    match format.parse_value(content) {
+        Ok(toml::Value::Table(table)) => Some(config_version(&table)),
+        _ => None,
+    }
 }
 
-/// Get the current git commit SHA
-fn get_git_commit_sha() -> Option<String> {
-    let current_dir = env::current_dir().ok()?;
+/// Where the original of a config file is kept when it's rewritten in a newer
+/// format version, e.g. `config.toml.bak`
+fn config_backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
 
-    // Try to get short commit hash using git command
-    if let Ok(output) = Command::new("git")
-        .args(["rev-parse", "--short", "HEAD"])
-        .current_dir(&current_dir)
-        .output()
-    {
-        if output.status.success() {
-            if let Ok(sha) = String::from_utf8(output.stdout) {
-                return Some(sha.trim().to_string());
+/// Tables of user-chosen keys rather than settings, e.g. domains to symbols
+const MAP_CONFIG_TABLES: &[&str] = &["git.remote_symbols", "directory.substitutions"];
+
+/// Layer `LOCO_PILOT_<KEY>` environment variables over a config, where the key
+/// is a `config` key with dots as underscores, e.g. LOCO_PILOT_COLOR_DIRECTORY
+fn apply_env_overrides(config: Config, vars: impl Iterator<Item = (String, String)>) -> Config {
+    let overrides: Vec<(String, String)> = vars
+        .filter(|(name, _)| name.starts_with("LOCO_PILOT_"))
+        .collect();
+    if overrides.is_empty() {
+        return config;
+    }
+    let Ok(mut value) = toml::Value::try_from(&config) else {
+        return config;
+    };
+
+    let mut paths = Vec::new();
+    config_leaf_paths(&value, &mut Vec::new(), &mut paths);
+    for path in paths {
+        let key = match path.split_first() {
+            Some((section, rest)) if section == "colors" => std::iter::once("color".to_string())
+                .chain(rest.iter().cloned())
+                .collect(),
+            _ => path.clone(),
+        };
+        let env_name = format!("LOCO_PILOT_{}", key.join("_").to_uppercase());
+        let Some((_, raw)) = overrides.iter().find(|(name, _)| *name == env_name) else {
+            continue;
+        };
+        let Some(setting) = path
+            .iter()
+            .try_fold(&mut value, |value, name| value.get_mut(name.as_str()))
+        else {
+            continue;
+        };
+
+        let parsed = match setting {
+            toml::Value::Boolean(_) => Some(toml::Value::Boolean(matches!(
+                raw.to_lowercase().as_str(),
+                "true" | "1" | "yes"
+            ))),
+            toml::Value::Integer(_) => raw.parse().ok().map(toml::Value::Integer),
+            toml::Value::Float(_) => raw.parse().ok().map(toml::Value::Float),
+            toml::Value::Array(_) => Some(toml::Value::Array(
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| toml::Value::String(item.to_string()))
+                    .collect(),
+            )),
+            _ => Some(toml::Value::String(raw.clone())),
+        };
+        match parsed {
+            Some(parsed) => {
+                debug!("{} overrides {}", env_name, path.join("."));
+                *setting = parsed
             }
+            None => eprintln!("loco-pilot: ignoring {}={}: not a number", env_name, raw),
         }
     }
 
-    // Fallback to gix if git command fails
-    match gix::open(&current_dir) {
-        Ok(repo) => {
-            if let Ok(head) = repo.head() {
-                // Different approach to get the commit id from gix
-                if let Some(id) = head.id() {
-                    // Get short SHA (7 characters)
-                    let short_id = id.to_string()[..7].to_string();
-                    return Some(short_id);
+    value.try_into().unwrap_or(config)
+}
+
+/// Paths of every setting in a serialized config
+fn config_leaf_paths(value: &toml::Value, prefix: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+    match value.as_table() {
+        Some(table) if !MAP_CONFIG_TABLES.contains(&prefix.join(".").as_str()) => {
+            for (key, child) in table {
+                prefix.push(key.clone());
+                config_leaf_paths(child, prefix, paths);
+                prefix.pop();
+            }
+        }
+        Some(_) => {}
+        None => paths.push(prefix.clone()),
+    }
+}
+
+/// Save configuration to file
+fn save_config(config: &Config) -> io::Result<()> {
+    let config_path = get_config_path().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "Could not determine config directory",
+        )
+    })?;
+
+    let format = ConfigFormat::from_path(&config_path);
+    let content = format.serialize(config).map_err(io::Error::other)?;
+
+    // A file from an older format version is kept, since it's about to be
+    // rewritten in the current one
+    if let Ok(original) = fs::read_to_string(&config_path)
+        && config_file_version(&original, format).is_some_and(|version| version < CONFIG_VERSION)
+    {
+        fs::write(config_backup_path(&config_path), original)?;
+    }
+
+    let mut file = fs::File::create(&config_path)?;
+    file.write_all(content.as_bytes())?;
+    drop(file);
+
+    // Update the cache with the new config
+    let mtime = config_file_mtime(Some(&config_path));
+    let mut cache = lock(&CONFIG_CACHE);
+    *cache = Some((config.clone(), Some(config_path), mtime));
+
+    Ok(())
+}
+
+/// Recursively lay the tables of `overlay` over those of `base`
+fn overlay_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => overlay_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
                 }
             }
-            None
         }
-        Err(_) => None,
+        (base, overlay) => *base = overlay,
     }
 }
 
-/// Get the full version string including git commit SHA
-fn get_full_version() -> String {
-    // Get the crate version from Cargo.toml via env
-    let version = env!("CARGO_PKG_VERSION");
+/// `config` with a built-in preset's settings applied
+fn apply_preset(config: &Config, preset: &presets::Preset) -> Result<Config, String> {
+    let settings = preset
+        .settings
+        .parse::<toml::Table>()
+        .map_err(|e| e.to_string())?;
+    let mut value = toml::Value::try_from(config).map_err(|e| e.to_string())?;
+    overlay_toml(&mut value, toml::Value::Table(settings));
+    value.try_into().map_err(|e: toml::de::Error| e.to_string())
+}
 
-    // Append the git SHA if available
-    if let Some(sha) = get_git_commit_sha() {
-        format!("{} ({})", version, sha)
+/// A prompt rendered from sample data, to preview how a config looks
+fn preview_prompt(config: &Config) -> String {
+    let no_color = colors_disabled(false, config, &Env::default());
+    let paint = |color_name: &str, text: &str| {
+        if no_color {
+            text.to_string()
+        } else {
+            format!(
+                "{}{}\x1b[0m",
+                color::ansi_code(color_name, &Env::default()),
+                text
+            )
+        }
+    };
+    let colors = &config.colors;
+    let lookup = |name: &str| -> Option<String> {
+        let value = match name {
+            "time" => paint(&colors.time, "12:34:56"),
+            "user" => paint(&colors.username, "user"),
+            "host" => paint(&colors.hostname, "laptop"),
+            "dir" => paint(&colors.directory, "~/projects/app"),
+            "env" => paint(&colors.python, &format!("{}venv", config.python.symbol)),
+            "git" => format!(
+                "({}) {}",
+                paint(&colors.git_branch, "main"),
+                paint(&colors.git_dirty, "~1")
+            ),
+            "cmd" => String::new(),
+            "fill" => " ".to_string(),
+            "char" => paint(&colors.character_success, &config.character.success_symbol),
+            _ => return None,
+        };
+        Some(value)
+    };
+    let template = if config.format.is_empty() {
+        format::preset(&config.style)
     } else {
-        version.to_string()
-    }
+        config.format.as_str()
+    };
+    format::render(template, &lookup)
 }
 
-// Cache for username
-static USERNAME_CACHE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+/// Directory holding installed theme files
+fn themes_dir() -> Option<PathBuf> {
+    let dir = get_config_path()?.parent()?.join("themes");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
 
-/// Get username with caching
-#[inline]
-fn get_username() -> String {
-    let mut cache = USERNAME_CACHE.lock().unwrap();
-    if let Some(username) = &*cache {
-        return username.clone();
-    }
+/// Name a theme is installed under: the file name without its extension
+fn theme_name(source: &str) -> Option<String> {
+    let file_name = source.trim_end_matches('/').rsplit(['/', '\\']).next()?;
+    let name = file_name.strip_suffix(".toml").unwrap_or(file_name);
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
 
-    let username = env::var("USER").unwrap_or_else(|_| "user".to_string());
-    *cache = Some(username.clone());
-    username
+/// Contents of a theme from a local file, or downloaded with curl for URLs
+fn read_theme_source(source: &str) -> Result<String, String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let output = Command::new("curl")
+            .args(["-fsSL", source])
+            .output()
+            .map_err(|e| format!("could not run curl: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        return String::from_utf8(output.stdout).map_err(|e| e.to_string());
+    }
+    fs::read_to_string(source).map_err(|e| e.to_string())
 }
 
-/// Generate properly escaped bash prompt color codes
-/// This is the key function for fixing the prompt issues
-fn bash_color(ansi_code: &str) -> String {
-    // Properly wrap ANSI escape codes with bash's prompt escaping sequences
-    // This ensures bash correctly calculates prompt width by ignoring non-printing characters
-    // Use literal escape sequences without backslash escaping
-    format!("\\[{}\\]", ansi_code)
+/// Path of a `config` key in the serialized config, e.g. `color.time` lives
+/// in the `colors` table and `git.remote_symbols.github.com` in a nested one
+fn config_key_path(key: &str) -> Vec<&str> {
+    let (section, rest) = match key.split_once('.') {
+        Some(("color", rest)) => ("colors", Some(rest)),
+        Some((section, rest)) => (section, Some(rest)),
+        None => (key, None),
+    };
+    let mut path = vec![section];
+    match rest.map(|rest| rest.split_once('.')) {
+        Some(Some((field, entry))) => path.extend([field, entry]),
+        Some(None) => path.extend(rest),
+        None => {}
+    }
+    path
 }
 
-/// Generate the prompt string
-fn generate_prompt(style: &str) -> String {
-    enable_colors_for_bash();
+/// A copy of `config` with one key restored to its default value. Returns
+/// None for unknown keys.
+fn unset_config_key(config: &Config, key: &str) -> Option<Config> {
+    let mut current = toml::Value::try_from(config).ok()?;
+    let defaults = toml::Value::try_from(Config::default()).ok()?;
+    let path = config_key_path(key);
+    let (field, parents) = path.split_last()?;
 
-    // Load configuration to get user-defined colors
-    let config = load_config();
+    let default_value = path
+        .iter()
+        .try_fold(&defaults, |value, name| value.get(name))
+        .cloned();
+    let table = parents
+        .iter()
+        .try_fold(&mut current, |value, name| value.get_mut(name))?
+        .as_table_mut()?;
+    match default_value {
+        Some(default_value) => {
+            table.insert(field.to_string(), default_value);
+        }
+        // Map entries like a remote symbol may have no default at all
+        None => {
+            table.remove(*field)?;
+        }
+    }
 
-    let current_time = Local::now().format("%H:%M:%S").to_string();
-    let username = get_username();
-    let hostname = get_hostname();
-    let current_dir = get_shortened_dir();
-
-    // Map color names to ANSI color codes
-    let color_map = |color_name: &str| -> &str {
-        match color_name {
-            "black" => "\x1b[30m",
-            "red" => "\x1b[31m",
-            "green" => "\x1b[32m",
-            "yellow" => "\x1b[33m",
-            "blue" => "\x1b[34m",
-            "purple" | "magenta" => "\x1b[35m",
-            "cyan" => "\x1b[36m",
-            "white" => "\x1b[37m",
-            "bright_black" | "gray" => "\x1b[90m",
-            "bright_red" => "\x1b[91m",
-            "bright_green" => "\x1b[92m",
-            "bright_yellow" => "\x1b[93m",
-            "bright_blue" => "\x1b[94m",
-            "bright_magenta" | "bright_purple" => "\x1b[95m",
-            "bright_cyan" => "\x1b[96m",
-            "bright_white" => "\x1b[97m",
-            // Bold variants
-            "bold_black" => "\x1b[1;30m",
-            "bold_red" => "\x1b[1;31m",
-            "bold_green" => "\x1b[1;32m",
-            "bold_yellow" => "\x1b[1;33m",
-            "bold_blue" => "\x1b[1;34m",
-            "bold_magenta" | "bold_purple" => "\x1b[1;35m",
-            "bold_cyan" => "\x1b[1;36m",
-            "bold_white" => "\x1b[1;37m",
-            // Default to bold green if not recognized
-            _ => "\x1b[1;32m",
-        }
-    };
-
-    // Create ANSI color sequences with bash prompt escaping based on user configuration
-    let username_color = bash_color(color_map(&config.colors.username));
-    let hostname_color = bash_color(color_map(&config.colors.hostname));
-    let dir_color = bash_color(color_map(&config.colors.directory));
-    let time_color = bash_color(color_map(&config.colors.time));
-    let reset = bash_color("\x1b[0m");
+    current.try_into().ok()
+}
 
-    // Format colored text segments
-    let username_fmt = format!("{}{}{}", username_color, username, reset);
-    let hostname_fmt = format!("{}{}{}", hostname_color, hostname, reset);
-    let dir_fmt = format!("{}{}{}", dir_color, current_dir, reset);
-    let time_fmt = format!("{}{}{}", time_color, current_time, reset);
+/// Problems in a config file: syntax and type errors, unknown keys and unrecognized colors
+fn validate_config(content: &str, format: ConfigFormat) -> Vec<String> {
+    let mut value = match format.parse_value(content) {
+        Ok(value) => value,
+        Err(e) => return vec![e.trim_end().to_string()],
+    };
+    // Keys an older format version used are upgraded on load, so they're fine
+    if let toml::Value::Table(table) = &mut value {
+        migrate_config(table, MIGRATIONS);
+    }
+    // TOML errors point at the offending line, so show them in full
+    let typed = match format {
+        ConfigFormat::Toml => toml::from_str::<Config>(content)
+            .map(drop)
+            .map_err(|e| e.to_string()),
+        _ => format.parse(content).map(drop),
+    };
+    if let Err(e) = typed {
+        return vec![e.trim_end().to_string()];
+    }
 
-    // Only get git info if it's needed for the selected style
-    let git_info = if style != "minimal" && config.show_git {
-        get_git_info()
-            .map(|status| {
-                let branch_color = bash_color(color_map(&config.colors.git_branch));
-                let dirty_color = bash_color(color_map(&config.colors.git_dirty));
-                let ahead_color = bash_color("\x1b[01;33m"); // Bold Yellow
-                let behind_color = bash_color("\x1b[01;35m"); // Bold Purple
-
-                let branch_info = match style {
-                    "emoji" => format!(" 🔖 {}", status.branch),
-                    _ => {
-                        let colored_branch = format!("{}{}{}", branch_color, status.branch, reset);
-                        format!(" ({})", colored_branch)
-                    }
-                };
+    let mut problems = Vec::new();
+    let Ok(defaults) = toml::Value::try_from(Config::default()) else {
+        return problems;
+    };
+    unknown_config_keys(&value, &defaults, "", &mut problems);
 
-                // Add ahead/behind indicators
-                let mut ahead_behind = String::new();
-                if status.ahead > 0 {
-                    ahead_behind.push_str(&match style {
-                        "emoji" => format!(" ↑{}", status.ahead),
-                        _ => format!(" {}↑{}{}", ahead_color, status.ahead, reset),
-                    });
-                }
-                if status.behind > 0 {
-                    ahead_behind.push_str(&match style {
-                        "emoji" => format!(" ↓{}", status.behind),
-                        _ => format!(" {}↓{}{}", behind_color, status.behind, reset),
-                    });
-                }
+    let colors = value.get("colors").and_then(toml::Value::as_table);
+    for (name, color) in colors.into_iter().flatten() {
+        let unrecognized = color.as_str().filter(|color| !color::is_valid(color));
+        if let Some(color) = unrecognized {
+            problems.push(format!(
+                "Unrecognized color for colors.{}: {:?} (falls back to bold green)",
+                name, color
+            ));
+        }
+    }
 
-                let dirty_info = if status.dirty {
-                    match style {
-                        "emoji" => " 🔴".to_string(),
-                        _ => format!("{}*{}", dirty_color, reset),
-                    }
-                } else {
-                    String::new()
-                };
+    problems
+}
 
-                format!("{}{}{}", branch_info, ahead_behind, dirty_info)
-            })
-            .unwrap_or_default()
-    } else {
-        String::new()
+/// Collect keys in `value` that the default config doesn't have. Tables that
+/// are maps of user-chosen keys, like git.remote_symbols, are not descended into.
+fn unknown_config_keys(
+    value: &toml::Value,
+    defaults: &toml::Value,
+    prefix: &str,
+    problems: &mut Vec<String>,
+) {
+    let (Some(table), Some(default_table)) = (value.as_table(), defaults.as_table()) else {
+        return;
     };
+    for (key, child) in table {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match default_table.get(key) {
+            None => problems.push(format!("Unknown key: {}", path)),
+            Some(_) if MAP_CONFIG_TABLES.contains(&path.as_str()) => {}
+            Some(default_child) => unknown_config_keys(child, default_child, &path, problems),
+        }
+    }
+}
 
-    // Avoid string allocations where possible by using match with direct format calls
-    match style {
-        "minimal" => String::from("$ "),
-        "info" => format!(
-            "[{}] {}@{}: {}{} $ ",
-            time_fmt, username_fmt, hostname_fmt, dir_fmt, git_info
-        ),
-        "emoji" => format!(
-            "🕒 {} 👤 {} 🖥️  {} 📁 {}{} ➡️  ",
-            current_time, username, hostname, current_dir, git_info
-        ),
-        _ => format!(
-            "{}@{}:{}{} $ ",
-            username_fmt, hostname_fmt, dir_fmt, git_info
-        ),
+/// Keys that only accept a fixed set of values, for the config schema
+const CONFIG_KEY_VALUES: &[(&str, &[&str])] = &[
+    ("style", format::STYLES),
+    ("icons", &["default", "nerd-font"]),
+    (
+        "shell",
+        &[
+            "auto",
+            "bash",
+            "zsh",
+            "fish",
+            "powershell",
+            "pwsh",
+            "nu",
+            "nushell",
+            "xonsh",
+        ],
+    ),
+    ("git.backend", &["git", "gix"]),
+    ("git.truncate_position", &["end", "middle"]),
+    ("clipboard.backend", &["auto", "osc52"]),
+    ("segments.order", format::SEGMENTS),
+    ("segments.disabled", format::SEGMENTS),
+];
+
+/// JSON Schema of the config file, derived from the defaults so every key,
+/// its type and its default are covered
+fn config_schema() -> serde_json::Value {
+    let defaults =
+        toml::Value::try_from(Config::default()).unwrap_or(toml::Value::Table(toml::Table::new()));
+    let mut schema = value_schema(&defaults, "");
+    if let Some(schema) = schema.as_object_mut() {
+        schema.insert(
+            "$schema".to_string(),
+            "https://json-schema.org/draft/2020-12/schema".into(),
+        );
+        schema.insert("title".to_string(), "loco-pilot config".into());
     }
+    schema
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::test_utils::tests::create_mock_config;
+/// Schema for one config value at `path`, with its default
+fn value_schema(value: &toml::Value, path: &str) -> serde_json::Value {
+    let allowed = CONFIG_KEY_VALUES
+        .iter()
+        .find(|(key, _)| *key == path)
+        .map(|(_, values)| *values);
+    let mut schema = serde_json::Map::new();
 
-    #[test]
-    fn test_config_default() {
-        // Test default configuration values
-        let config = Config::default();
-        assert_eq!(config.style, "default");
-        assert_eq!(config.show_git, true);
-        assert_eq!(config.colors.username, "green");
-        assert_eq!(config.colors.hostname, "yellow");
-        assert_eq!(config.colors.directory, "cyan");
-        assert_eq!(config.colors.git_branch, "green");
-        assert_eq!(config.colors.git_dirty, "red");
-        assert_eq!(config.colors.time, "blue");
+    match value {
+        toml::Value::Table(_) if MAP_CONFIG_TABLES.contains(&path) => {
+            schema.insert("type".to_string(), "object".into());
+            schema.insert(
+                "additionalProperties".to_string(),
+                serde_json::json!({ "type": "string" }),
+            );
+        }
+        toml::Value::Table(table) => {
+            let properties: serde_json::Map<String, serde_json::Value> = table
+                .iter()
+                .map(|(key, child)| {
+                    let child_path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", path, key)
+                    };
+                    (key.clone(), value_schema(child, &child_path))
+                })
+                .collect();
+            schema.insert("type".to_string(), "object".into());
+            schema.insert("properties".to_string(), properties.into());
+            schema.insert("additionalProperties".to_string(), false.into());
+            return schema.into();
+        }
+        toml::Value::Array(items) => {
+            let mut item_schema = serde_json::Map::new();
+            let item_type = items.first().map_or("string", json_type);
+            item_schema.insert("type".to_string(), item_type.into());
+            if let Some(allowed) = allowed {
+                item_schema.insert("enum".to_string(), allowed.into());
+            }
+            schema.insert("type".to_string(), "array".into());
+            schema.insert("items".to_string(), item_schema.into());
+        }
+        _ => {
+            schema.insert("type".to_string(), json_type(value).into());
+            if let Some(allowed) = allowed {
+                schema.insert("enum".to_string(), allowed.into());
+            }
+        }
+    }
+
+    if let Ok(default) = serde_json::to_value(value) {
+        schema.insert("default".to_string(), default);
+    }
+    schema.into()
+}
+
+/// JSON Schema type name of a TOML value
+fn json_type(value: &toml::Value) -> &'static str {
+    match value {
+        toml::Value::String(_) | toml::Value::Datetime(_) => "string",
+        toml::Value::Integer(_) => "integer",
+        toml::Value::Float(_) => "number",
+        toml::Value::Boolean(_) => "boolean",
+        toml::Value::Array(_) => "array",
+        toml::Value::Table(_) => "object",
+    }
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no
+fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Turn colors on or off regardless of whether stdout is a terminal, since the
+/// shell captures the prompt through a pipe
+#[inline]
+fn set_colors(enabled: bool) {
+    colored::control::set_override(enabled);
+}
+
+/// Whether colors were turned off by the flag, the config or a non-empty
+/// NO_COLOR (https://no-color.org)
+fn colors_disabled(flag: bool, config: &Config, env: &Env) -> bool {
+    flag || config.no_color || env.is_set("NO_COLOR")
+}
+
+/// A customizable bash prompt application
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The style of prompt to display
+    #[arg(short, long, default_value = "default")]
+    style: String,
+
+    /// The shell to generate prompt escapes for (auto, bash, zsh, fish, powershell, nu, xonsh)
+    #[arg(long)]
+    shell: Option<String>,
+
+    /// Emit the prompt data as JSON instead of a formatted prompt
+    #[arg(long)]
+    json: bool,
+
+    /// Exit status of the previous command, passed in by the shell hook
+    #[arg(long, allow_hyphen_values = true)]
+    status: Option<i32>,
+
+    /// Duration of the previous command in milliseconds, passed in by the shell hook
+    #[arg(long)]
+    cmd_duration: Option<u64>,
+
+    /// Space separated exit codes of each pipeline stage, e.g. "${PIPESTATUS[*]}"
+    #[arg(long)]
+    pipestatus: Option<String>,
+
+    /// Current vi keymap, e.g. $KEYMAP in zsh or $fish_bind_mode in fish
+    #[arg(long)]
+    keymap: Option<String>,
+
+    /// Print the right prompt from the right_format setting, e.g. for zsh's RPROMPT
+    #[arg(long)]
+    right: bool,
+
+    /// Terminal width for `$fill` and truncation, passed in by the shell hook
+    #[arg(long, visible_alias = "width")]
+    columns: Option<usize>,
+
+    /// Print the prompt without colors or terminal control sequences
+    #[arg(long)]
+    no_color: bool,
+
+    /// Use this config file instead of the default location
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Only print values from copy commands, without touching the clipboard
+    #[arg(long, global = true)]
+    print_only: bool,
+
+    /// Log spawned commands, cache use and config loading to stderr, or to
+    /// LOCO_PILOT_LOG_FILE (LOCO_PILOT_LOG=debug does the same)
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Refresh the on-disk git cache for the current directory (used internally)
+    #[arg(long, hide = true)]
+    refresh_git_cache: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Configure prompt settings; `config unset <key>` restores one default,
+    /// `config reset` restores them all, `config schema` prints a JSON Schema,
+    /// `config migrate` upgrades a file from an older version
+    Config {
+        /// The key to set
+        key: Option<String>,
+        /// The value to set
+        value: Option<String>,
+        /// Reset without asking for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+
+    /// Display detailed version information
+    Version {
+        /// Print the version, commit, build date, target and features as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the setup snippet for a shell (bash, zsh, fish, powershell, nu, xonsh)
+    Init {
+        /// The shell to print the setup snippet for
+        shell: String,
+    },
+
+    /// Print a tab completion script for a shell (bash, zsh, fish, powershell, elvish)
+    Completions {
+        /// The shell to print the completion script for
+        shell: clap_complete::Shell,
+    },
+
+    /// Print the origin remote URL and copy it to the clipboard
+    GitRemoteUrl {
+        /// Convert SSH remotes to a browsable https URL
+        #[arg(long)]
+        https: bool,
+    },
+
+    /// Open the repository's origin in the default web browser
+    OpenRepo {
+        /// A file to open instead of the repository front page
+        file: Option<PathBuf>,
+        /// Open the current branch instead of the default branch
+        #[arg(long)]
+        branch: bool,
+    },
+
+    /// Pick a worktree of the current repository and print its path
+    Worktrees,
+
+    /// Pick a stash entry and copy its ref, or apply or pop it
+    StashSelect {
+        /// Apply the selected stash instead of copying its ref
+        #[arg(long, conflicts_with = "pop")]
+        apply: bool,
+        /// Pop the selected stash instead of copying its ref
+        #[arg(long)]
+        pop: bool,
+    },
+
+    /// Print the current HEAD commit SHA and copy it to the clipboard
+    GitSha {
+        /// Use the full SHA instead of the short one
+        #[arg(long)]
+        full: bool,
+    },
+
+    /// Manage shareable theme files with colors and symbols
+    Theme {
+        #[command(subcommand)]
+        action: ThemeCommand,
+    },
+
+    /// Render every prompt style in the current directory to compare them
+    Styles {
+        /// Use sample data instead of the current directory and repository
+        #[arg(long)]
+        sample: bool,
+    },
+
+    /// Browse and apply built-in presets
+    Preset {
+        #[command(subcommand)]
+        action: PresetCommand,
+    },
+
+    /// Serve prompts from a long-lived process over a Unix socket, keeping
+    /// config and repositories loaded between prompts
+    Daemon,
+
+    /// Render the prompt once without caches, showing how long each segment
+    /// took and why it was shown or hidden
+    #[command(alias = "timings")]
+    Explain {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Render the prompt, or with `--format json` its segments with their text,
+    /// color and data, e.g. for tmux, polybar or editor status bars
+    Prompt {
+        /// Output format: text or json
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+
+    /// Measure how long the prompt takes to render, overall and per segment
+    Bench {
+        /// Number of prompts to render
+        #[arg(long, short = 'n', default_value_t = 20)]
+        iterations: usize,
+        /// Keep the on-disk caches, measuring the prompts that reuse them
+        #[arg(long)]
+        cached: bool,
+    },
+
+    /// Check git, the terminal, the shell hook, the config, the locale and the
+    /// clipboard, and suggest fixes for anything that's off
+    Doctor,
+
+    /// Replace this binary with the latest GitHub release, after checking its checksum
+    SelfUpdate {
+        /// Only report whether a newer release is available
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PresetCommand {
+    /// List the built-in presets
+    List {
+        /// Render each preset with sample data
+        #[arg(long)]
+        preview: bool,
+    },
+
+    /// Apply a preset on top of the current configuration
+    Use {
+        /// Name of the preset
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ThemeCommand {
+    /// List the installed themes
+    List,
+
+    /// Apply an installed theme to the configuration
+    Use {
+        /// Name of the theme
+        name: String,
+    },
+
+    /// Print the current colors and symbols as a theme, or save them under a name
+    Export {
+        /// Save the theme under this name instead of printing it
+        name: Option<String>,
+    },
+
+    /// Install a theme from a file or an http(s) URL
+    Import {
+        /// Path or URL of the theme file
+        source: String,
+        /// Name to install the theme under, defaults to the file name
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+/// Returns the current working directory, with home directory replaced by ~
+fn get_current_dir(env: &Env) -> String {
+    let mut path_cache = lock(&PATH_CACHE);
+    let (current_dir_cache, home_dir_cache, _) = &*path_cache;
+
+    // Check if we have a cached current directory that's still fresh
+    if let Some((cached_dir, timestamp)) = current_dir_cache {
+        if timestamp.elapsed() < Duration::from_secs(PATH_CACHE_TTL_SECS) {
+            return cached_dir.clone();
+        }
+    }
+
+    let current_dir = env::current_dir().unwrap_or_default();
+    let current_path = current_dir.display().to_string();
+
+    // Check if we have a cached home directory
+    let home_path = if let Some((cached_home, timestamp)) = home_dir_cache {
+        if timestamp.elapsed() < Duration::from_secs(PATH_CACHE_TTL_SECS) {
+            cached_home.clone()
+        } else if let Some(home_dir) = env.home_dir() {
+            let home_path = home_dir.display().to_string();
+            path_cache.1 = Some((home_path.clone(), Instant::now()));
+            home_path
+        } else {
+            String::new()
+        }
+    } else if let Some(home_dir) = env.home_dir() {
+        let home_path = home_dir.display().to_string();
+        path_cache.1 = Some((home_path.clone(), Instant::now()));
+        home_path
+    } else {
+        String::new()
+    };
+
+    let result = if !home_path.is_empty() && current_path.starts_with(&home_path) {
+        current_path.replacen(&home_path, "~", 1)
+    } else {
+        current_path
+    };
+
+    // Update the cache
+    path_cache.0 = Some((result.clone(), Instant::now()));
+
+    result
+}
+
+/// Returns a shortened version of the current directory path if it's longer than 15 characters
+#[inline]
+fn get_shortened_dir(wsl_windows_paths: bool, directory: &DirectoryConfig, env: &Env) -> String {
+    let current_dir = get_current_dir(env);
+    // Windows drives under WSL read better as drive letters than as /mnt mounts
+    let windows_dir = wsl_windows_paths
+        .then(|| segments::wsl::windows_path(&current_dir))
+        .flatten();
+    let display_dir = windows_dir.as_deref().unwrap_or(&current_dir);
+
+    // A configured label for the path wins over the repository-relative path
+    let home = env
+        .home_dir()
+        .map(|home| home.display().to_string())
+        .unwrap_or_default();
+    if let Some(substituted) = substitute_path(display_dir, &directory.substitutions, &home) {
+        return shorten_path(&substituted, directory);
+    }
+
+    let repo_dir = directory
+        .truncate_to_repo
+        .then(|| {
+            let current_dir = env::current_dir().ok()?;
+            repo_relative_dir(&current_dir, env.home_dir().as_deref(), &git_ceilings(env))
+        })
+        .flatten();
+    if let Some(repo_dir) = repo_dir {
+        return shorten_path(&repo_dir, directory);
+    }
+
+    shorten_path(display_dir, directory)
+}
+
+/// Replace the longest configured prefix of `path` with its label. Prefixes
+/// may be written with `~` or the full home directory and only match whole
+/// path components, so `~/work` doesn't match `~/workshop`.
+fn substitute_path(
+    path: &str,
+    substitutions: &BTreeMap<String, String>,
+    home: &str,
+) -> Option<String> {
+    substitutions
+        .iter()
+        .filter_map(|(prefix, label)| {
+            let prefix = prefix.trim_end_matches(['/', '\\']);
+            let prefix = match prefix.strip_prefix(home) {
+                Some(rest) if !home.is_empty() => format!("~{}", rest),
+                _ => prefix.to_string(),
+            };
+            let rest = path.strip_prefix(prefix.as_str())?;
+            let whole_components = rest.is_empty() || rest.starts_with(['/', '\\']);
+            (!prefix.is_empty() && whole_components)
+                .then(|| (prefix.len(), format!("{}{}", label, rest)))
+        })
+        .max_by_key(|(prefix_len, _)| *prefix_len)
+        .map(|(_, substituted)| substituted)
+}
+
+/// The path of `dir` starting at the root of its git repository, e.g.
+/// `repo/src/lib`. A repository at the home directory itself, as some dotfile
+/// setups use, is ignored so that home paths keep their `~` form.
+fn repo_relative_dir(dir: &Path, home: Option<&Path>, ceilings: &[PathBuf]) -> Option<String> {
+    let root = repo_root(dir, ceilings)?;
+    if home == Some(root.as_path()) {
+        return None;
+    }
+    let name = Path::new(root.file_name()?);
+    let relative = dir.strip_prefix(&root).ok()?;
+    if relative.as_os_str().is_empty() {
+        return Some(name.display().to_string());
+    }
+    Some(name.join(relative).display().to_string())
+}
+
+/// Shortens a path to its first and last few components, handling both `/` and `\` separators
+fn shorten_path(full_path: &str, directory: &DirectoryConfig) -> String {
+    // If the path is short enough, return it as is
+    if directory.max_length == 0 || full_path.chars().count() <= directory.max_length {
+        return full_path.to_string();
+    }
+
+    // Windows paths use backslashes, everything else uses forward slashes
+    let separator = if full_path.contains('\\') && !full_path.contains('/') {
+        '\\'
+    } else {
+        '/'
+    };
+
+    // Split the path by separator
+    let components: Vec<&str> = full_path.split(separator).collect();
+
+    // If nothing would be left out, just return the full path
+    let keep = directory.keep_components;
+    if components.len() <= keep + 1 {
+        return full_path.to_string();
+    }
+
+    // Get the first component (usually ~, root or a drive letter)
+    let first = components.first().unwrap_or(&"");
+    let symbol = &directory.truncation_symbol;
+
+    // Get the last components
+    let last = components[components.len() - keep..].join(&separator.to_string());
+
+    // Format with the truncation symbol
+    if last.is_empty() {
+        format!("{first}{separator}{symbol}")
+    } else {
+        format!("{first}{separator}{symbol}{separator}{last}")
+    }
+}
+
+/// Get the hostname of the machine with caching
+fn get_hostname(env: &Env) -> String {
+    if test_mode::enabled(env) {
+        return test_mode::HOSTNAME.to_string();
+    }
+    let mut path_cache = lock(&PATH_CACHE);
+    let (_, _, hostname_cache) = &*path_cache;
+
+    // Check if we have a cached hostname that's still fresh
+    if let Some((cached_hostname, timestamp)) = hostname_cache {
+        if timestamp.elapsed() < Duration::from_secs(PATH_CACHE_TTL_SECS) {
+            return cached_hostname.clone();
+        }
+    }
+
+    // Try multiple ways to get the hostname, preferring environment variables
+    // so we only spawn a subprocess as a last resort
+    let hostname = if let Some(hostname) = env.var("HOSTNAME") {
+        hostname
+    } else if let Some(hostname) = env.var("HOST") {
+        hostname
+    } else if let Some(hostname) = env.var("COMPUTERNAME") {
+        // Windows sets COMPUTERNAME rather than HOSTNAME
+        hostname
+    } else {
+        hostname_from_command(env).unwrap_or_else(|| "localhost".to_string())
+    };
+
+    // Update the cache
+    path_cache.2 = Some((hostname.clone(), Instant::now()));
+
+    hostname
+}
+
+/// The output of the `hostname` command, cached on disk since it rarely changes
+fn hostname_from_command(env: &Env) -> Option<String> {
+    let cache_path = disk_cache::entry_path("hostname", &"hostname", env);
+    let ttl = Duration::from_secs(HOSTNAME_CACHE_TTL_SECS);
+    if let Some(hostname) = cache_path
+        .as_deref()
+        .and_then(|path| disk_cache::read_fresh(path, ttl))
+    {
+        return Some(hostname);
+    }
+
+    debug!("running hostname");
+    let output = Command::new("hostname").output().ok()?;
+    let hostname = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    if let Some(path) = cache_path {
+        let _ = disk_cache::write(&path, &hostname);
+    }
+    Some(hostname)
+}
+
+/// Git repository status information
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct GitStatus {
+    branch: String,
+    upstream: Option<String>,
+    #[serde(flatten)]
+    files: FileCounts,
+    ahead: usize,
+    behind: usize,
+    stash: usize,
+    operation: Option<String>,
+    tag: Option<String>,
+    remote_host: Option<String>,
+    commit_time: Option<i64>,
+    email: Option<String>,
+    sparse: bool,
+    fetch_time: Option<i64>,
+}
+
+/// Number of changed files in each category of `git status`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct FileCounts {
+    staged: usize,
+    modified: usize,
+    deleted: usize,
+    untracked: usize,
+}
+
+/// Count staged, modified, deleted and untracked files in `git status --porcelain=v2` output
+fn parse_file_counts(status_output: &str) -> FileCounts {
+    let mut counts = FileCounts::default();
+
+    for line in status_output.lines() {
+        let mut fields = line.split(' ');
+        match fields.next() {
+            Some("?") => counts.untracked += 1,
+            // Ordinary, renamed/copied and unmerged entries all start with the XY status
+            Some("1" | "2" | "u") => {
+                let mut xy = fields.next().unwrap_or("..").chars();
+                let index = xy.next().unwrap_or('.');
+                let worktree = xy.next().unwrap_or('.');
+                if index != '.' {
+                    counts.staged += 1;
+                }
+                match worktree {
+                    'D' => counts.deleted += 1,
+                    '.' => {}
+                    _ => counts.modified += 1,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    counts
+}
+
+// Repositories opened with gix, kept across prompts by the daemon, with the
+// modification time of their config when they were opened. gix is built
+// without thread safety, so the cache is per thread.
+type RepoCache = HashMap<PathBuf, (gix::Repository, Option<SystemTime>)>;
+thread_local! {
+    static REPO_CACHE: RefCell<RepoCache> = RefCell::new(HashMap::new());
+}
+
+/// Open the repository at `git_dir` with gix, reusing an earlier handle until
+/// the repository's config changes
+fn open_repo(git_dir: &Path) -> Option<gix::Repository> {
+    let config_mtime = fs::metadata(git_dir.join("config"))
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    REPO_CACHE.with_borrow_mut(|cache| {
+        if let Some((repo, mtime)) = cache.get(git_dir)
+            && *mtime == config_mtime
+        {
+            return Some(repo.clone());
+        }
+
+        let repo = gix::open(git_dir).ok()?;
+        cache.insert(git_dir.to_path_buf(), (repo.clone(), config_mtime));
+        Some(repo)
+    })
+}
+
+/// Get git branch information if in a git repository
+/// This is a highly optimized version that reduces the number of git command executions
+fn get_git_info(git_config: &GitConfig, env: &Env) -> Option<GitStatus> {
+    let current_dir = match env::current_dir() {
+        Ok(dir) => dir,
+        Err(_) => return None,
+    };
+
+    // Quick check if this is a git repository
+    // This avoids expensive operations if we're not in a git repo
+    let git_dir = discover_git_dir(&current_dir, &git_ceilings(env))?;
+
+    // Huge repositories can opt out of the status walk, or of git info entirely
+    if repo_path_matches(&current_dir, &git_config.disabled_repos) {
+        return None;
+    }
+
+    if test_mode::enabled(env) {
+        return Some(test_git_status());
+    }
+    if git_config.async_refresh {
+        cached_git_status(&current_dir, &git_dir, git_config, env)
+    } else {
+        recent_git_status(&current_dir, &git_dir, git_config, env)
+    }
+}
+
+/// The status every repository has in test mode: a branch one commit ahead of
+/// its upstream, with one modified and one untracked file
+fn test_git_status() -> GitStatus {
+    GitStatus {
+        branch: "main".to_string(),
+        upstream: Some("origin/main".to_string()),
+        files: FileCounts {
+            modified: 1,
+            untracked: 1,
+            ..FileCounts::default()
+        },
+        ahead: 1,
+        ..GitStatus::default()
+    }
+}
+
+/// Where the status of `current_dir` is cached between prompts
+fn git_status_cache_path(current_dir: &Path, git_config: &GitConfig, env: &Env) -> Option<PathBuf> {
+    // The settings decide what gets collected, so they are part of the key
+    let settings = serde_json::to_string(git_config).unwrap_or_default();
+    disk_cache::entry_path("status", &(current_dir, settings), env)
+}
+
+/// A git status cached on disk along with the repository state it was collected in
+#[derive(Serialize, Deserialize)]
+struct RecentGitStatus {
+    fingerprint: Vec<Option<SystemTime>>,
+    status: GitStatus,
+}
+
+/// Modification times of the files that staging, commits, checkouts, fetches and
+/// stashes write to
+fn git_fingerprint(git_dir: &Path) -> Vec<Option<SystemTime>> {
+    let common_dir = common_git_dir(git_dir);
+    [
+        git_dir.join("index"),
+        git_dir.join("HEAD"),
+        git_dir.join("logs").join("HEAD"),
+        common_dir.join("FETCH_HEAD"),
+        common_dir.join("logs").join("refs").join("stash"),
+    ]
+    .iter()
+    .map(|path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    })
+    .collect()
+}
+
+/// Reuse the status an earlier prompt collected moments ago, so back-to-back
+/// prompts (left and right, or pressing enter repeatedly) share one `git status`.
+/// Changes to the repository state are picked up at once, edits to worktree
+/// files after GIT_CACHE_TTL_SECS.
+fn recent_git_status(
+    current_dir: &Path,
+    git_dir: &Path,
+    git_config: &GitConfig,
+    env: &Env,
+) -> Option<GitStatus> {
+    let cache_path = git_status_cache_path(current_dir, git_config, env);
+    let fingerprint = git_fingerprint(git_dir);
+
+    // Out of time, any earlier status beats just the branch
+    if past_deadline() {
+        debug!("past the prompt deadline, using the last git status");
+        let cached = cache_path
+            .as_deref()
+            .and_then(disk_cache::read::<RecentGitStatus>);
+        return match cached {
+            Some((cached, _)) => Some(cached.status),
+            None => head_branch(git_dir),
+        };
+    }
+
+    let cached = cache_path.as_deref().and_then(|path| {
+        disk_cache::read_fresh::<RecentGitStatus>(path, Duration::from_secs(GIT_CACHE_TTL_SECS))
+    });
+    match cached {
+        Some(cached) if cached.fingerprint == fingerprint => {
+            debug!("git status cache hit");
+            return Some(cached.status);
+        }
+        Some(_) => debug!("git status cache miss: the repository changed"),
+        None => debug!("git status cache miss"),
+    }
+
+    let status = collect_git_status(current_dir, git_dir, git_config, env)?;
+    if let Some(path) = cache_path {
+        let _ = disk_cache::write(
+            &path,
+            &RecentGitStatus {
+                fingerprint,
+                status: status.clone(),
+            },
+        );
+    }
+    Some(status)
+}
+
+/// Gather the full git status of the repository at `git_dir`
+fn collect_git_status(
+    current_dir: &Path,
+    git_dir: &Path,
+    git_config: &GitConfig,
+    env: &Env,
+) -> Option<GitStatus> {
+    let common_dir = common_git_dir(git_dir);
+    let branch_only = repo_path_matches(current_dir, &git_config.branch_only_repos);
+
+    // A slow `git status` (cold NFS mount, huge repo) falls back to just the branch
+    let timeout = deadline_timeout(
+        (git_config.timeout_ms > 0).then(|| Duration::from_millis(git_config.timeout_ms)),
+    );
+    let branch_status = match git_config.backend.as_str() {
+        _ if branch_only => head_branch(git_dir)?,
+        "gix" => gix_status(git_dir)?,
+        _ => git_cli_status(current_dir, timeout, env).or_else(|| head_branch(git_dir))?,
+    };
+
+    Some(GitStatus {
+        stash: count_stashes(&common_dir),
+        operation: git_operation(git_dir),
+        tag: match git_config.backend.as_str() {
+            _ if !git_config.show_tag => None,
+            "gix" => gix_head_tag(git_dir),
+            _ => head_tag(current_dir, timeout, env),
+        },
+        remote_host: fs::read_to_string(common_dir.join("config"))
+            .ok()
+            .and_then(|git_config| remote_url(&git_config, "origin"))
+            .and_then(|url| remote_host(&url)),
+        commit_time: git_config
+            .show_commit_age
+            .then(|| head_commit_time(git_dir))
+            .flatten(),
+        email: (!git_config.expected_email.is_empty())
+            .then(|| user_email(git_dir))
+            .flatten(),
+        sparse: git_config.show_sparse && is_sparse_checkout(git_dir),
+        fetch_time: last_fetch_time(&common_dir),
+        ..branch_status
+    })
+}
+
+/// Stale-while-revalidate: answer from the on-disk cache right away and refresh it
+/// in a detached background process once it's older than `git.cache_ttl`
+fn cached_git_status(
+    current_dir: &Path,
+    git_dir: &Path,
+    git_config: &GitConfig,
+    env: &Env,
+) -> Option<GitStatus> {
+    let Some(cache_path) = git_cache_path(git_dir, env) else {
+        return collect_git_status(current_dir, git_dir, git_config, env);
+    };
+
+    match disk_cache::read(&cache_path) {
+        Some((status, age)) if age <= Duration::from_secs(git_config.cache_ttl) => Some(status),
+        Some((status, _)) => {
+            spawn_git_refresh(&cache_path, env);
+            Some(status)
+        }
+        // Nothing cached yet, so this prompt has to pay for the status once
+        None => {
+            let status = collect_git_status(current_dir, git_dir, git_config, env)?;
+            let _ = disk_cache::write(&cache_path, &status);
+            Some(status)
+        }
+    }
+}
+
+/// Refresh the on-disk git cache for the current directory, run by `--refresh-git-cache`
+fn refresh_git_cache(git_config: &GitConfig) {
+    let Ok(current_dir) = env::current_dir() else {
+        return;
+    };
+    let env = Env::default();
+    let Some(git_dir) = discover_git_dir(&current_dir, &git_ceilings(&env)) else {
+        return;
+    };
+    let Some(cache_path) = git_cache_path(&git_dir, &env) else {
+        return;
+    };
+
+    if let Some(status) = collect_git_status(&current_dir, &git_dir, git_config, &env) {
+        let _ = disk_cache::write(&cache_path, &status);
+    }
+    let _ = fs::remove_file(cache_path.with_extension("lock"));
+}
+
+/// Location of the cached status for a repository, keyed on its git directory
+fn git_cache_path(git_dir: &Path, env: &Env) -> Option<PathBuf> {
+    disk_cache::entry_path("git", &git_dir, env)
+}
+
+/// Start a detached `loco-pilot --refresh-git-cache`, unless one is already running
+fn spawn_git_refresh(cache_path: &Path, env: &Env) {
+    // The lock file keeps every prompt from spawning its own refresh; one left
+    // behind by a crashed refresh expires after a while
+    let lock_path = cache_path.with_extension("lock");
+    let lock_is_fresh = fs::metadata(&lock_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < Duration::from_secs(30));
+    if lock_is_fresh {
+        return;
+    }
+    if fs::write(&lock_path, std::process::id().to_string()).is_err() {
+        return;
+    }
+
+    let Ok(exe) = env::current_exe() else {
+        return;
+    };
+    let mut command = env.command(exe);
+    if let Some(config_path) = lock(&CONFIG_PATH_OVERRIDE).as_ref() {
+        command.arg("--config").arg(config_path);
+    }
+    debug!("starting a background git cache refresh");
+    let _ = command
+        .arg("--refresh-git-cache")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+}
+
+/// Shorten a branch name to `max_len` characters, cutting at the end or in the middle
+fn truncate_branch(branch: &str, max_len: usize, symbol: &str, position: &str) -> String {
+    let chars: Vec<char> = branch.chars().collect();
+    if max_len == 0 || chars.len() <= max_len {
+        return branch.to_string();
+    }
+
+    // Leave the symbol out when there's no room for it next to the name
+    let symbol_len = symbol.chars().count();
+    if symbol_len >= max_len {
+        return chars[..max_len].iter().collect();
+    }
+    let keep = max_len - symbol_len;
+    match position {
+        "middle" => {
+            // Keep the prefix (e.g. feature/PROJ-1234) slightly favored over the tail
+            let head = keep.div_ceil(2);
+            let tail = keep - head;
+            let head: String = chars[..head].iter().collect();
+            let tail: String = chars[chars.len() - tail..].iter().collect();
+            format!("{}{}{}", head, symbol, tail)
+        }
+        _ => {
+            let head: String = chars[..keep].iter().collect();
+            format!("{}{}", head, symbol)
+        }
+    }
+}
+
+/// When the prompt being rendered has to be done by, from `prompt_timeout`
+static PROMPT_DEADLINE: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+/// Sets the prompt deadline while alive and lifts it when dropped, so commands
+/// that run segments outside a prompt (explain, bench) aren't cut short
+struct PromptDeadline;
+
+impl PromptDeadline {
+    fn start(timeout_ms: u64) -> Self {
+        *lock(&PROMPT_DEADLINE) =
+            (timeout_ms > 0).then(|| Instant::now() + Duration::from_millis(timeout_ms));
+        PromptDeadline
+    }
+}
+
+impl Drop for PromptDeadline {
+    fn drop(&mut self) {
+        *lock(&PROMPT_DEADLINE) = None;
+    }
+}
+
+/// Whether the prompt has run out of time, so slow segments should fall back
+/// to a cached value or the placeholder
+fn past_deadline() -> bool {
+    lock(&PROMPT_DEADLINE).is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// A segment's own `timeout`, shortened to what is left before the prompt deadline
+fn deadline_timeout(timeout: Option<Duration>) -> Option<Duration> {
+    bounded_timeout(timeout, *lock(&PROMPT_DEADLINE), Instant::now())
+}
+
+fn bounded_timeout(
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    now: Instant,
+) -> Option<Duration> {
+    let left = deadline.map(|deadline| deadline.saturating_duration_since(now));
+    match (timeout, left) {
+        (Some(timeout), Some(left)) => Some(timeout.min(left)),
+        (timeout, left) => timeout.or(left),
+    }
+}
+
+/// Run a command and collect its stdout, killing it if it runs past `timeout`
+fn output_with_timeout(command: &mut Command, timeout: Option<Duration>) -> Option<Output> {
+    match timeout {
+        Some(timeout) => output_with_input(command, None, Some(timeout)),
+        None => {
+            debug!("running {:?}", command);
+            command.output().ok()
+        }
+    }
+}
+
+/// Run a command with `input` on its stdin and collect its stdout, killing it
+/// if it runs past `timeout`
+fn output_with_input(
+    command: &mut Command,
+    input: Option<&[u8]>,
+    timeout: Option<Duration>,
+) -> Option<Output> {
+    debug!("running {:?} with a timeout of {:?}", command, timeout);
+    if input.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    let started = Instant::now();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // A child that exits without reading its input is fine
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        let _ = stdin.write_all(input);
+    }
+
+    // Drain stdout on another thread so a chatty child can't block on a full pipe
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        stdout.read_to_end(&mut buffer).map(|_| buffer)
+    });
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let status = loop {
+        match child.try_wait().ok()? {
+            Some(status) => break status,
+            None if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                debug!(
+                    "killing {:?}, it ran past its timeout",
+                    command.get_program()
+                );
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            None => std::thread::sleep(Duration::from_millis(5)),
+        }
+    };
+    debug!(
+        "{:?} exited with {} after {:?}",
+        command.get_program(),
+        status,
+        started.elapsed()
+    );
+
+    Some(Output {
+        status,
+        stdout: reader.join().ok()?.ok()?,
+        stderr: Vec::new(),
+    })
+}
+
+/// Whether `dir` or one of its parents matches a path or glob pattern from the config
+fn repo_path_matches(dir: &Path, patterns: &[String]) -> bool {
+    if patterns.is_empty() {
+        return false;
+    }
+
+    let home = dirs::home_dir().map(|home| home.display().to_string());
+    let patterns: Vec<String> = patterns
+        .iter()
+        .map(|pattern| match (pattern.strip_prefix('~'), &home) {
+            (Some(rest), Some(home)) => format!("{}{}", home, rest),
+            _ => pattern.clone(),
+        })
+        .map(|pattern| pattern.trim_end_matches('/').to_string())
+        .collect();
+
+    dir.ancestors().any(|ancestor| {
+        let ancestor = ancestor.display().to_string();
+        patterns
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), ancestor.as_bytes()))
+    })
+}
+
+/// Match `text` against a glob where `*` matches any run of characters and `?` a single one
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], text) || (!text.is_empty() && glob_match(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &text[1..]),
+        (Some(expected), Some(actual)) if expected == actual => {
+            glob_match(&pattern[1..], &text[1..])
+        }
+        _ => false,
+    }
+}
+
+/// Just the branch (or detached commit) read straight from HEAD, with no status walk
+fn head_branch(git_dir: &Path) -> Option<GitStatus> {
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let branch = match head.strip_prefix("ref: ") {
+        Some(reference) => reference
+            .strip_prefix("refs/heads/")
+            .unwrap_or(reference)
+            .to_string(),
+        None => format!("detached@{}", head.get(..7)?),
+    };
+
+    Some(GitStatus {
+        branch,
+        ..GitStatus::default()
+    })
+}
+
+/// Branch, upstream, file counts and ahead/behind from a single `git status` subprocess
+fn git_cli_status(current_dir: &Path, timeout: Option<Duration>, env: &Env) -> Option<GitStatus> {
+    // Use a single git command to get branch and status information
+    // This is much faster than multiple separate calls
+    let output = output_with_timeout(
+        env.command("git")
+            .args(["status", "--branch", "--porcelain=v2"])
+            .current_dir(current_dir),
+        timeout,
+    )?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let status_output = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = status_output.lines().collect();
+
+    // Parse branch information from the output
+    let mut branch = String::from("unknown");
+    let mut upstream = None;
+    let mut ahead = 0;
+    let mut behind = 0;
+
+    for line in &lines {
+        if let Some(branch_name) = line.strip_prefix("# branch.head ") {
+            branch = branch_name.to_string();
+        } else if let Some(upstream_name) = line.strip_prefix("# branch.upstream ") {
+            upstream = Some(upstream_name.to_string());
+        } else if let Some(branch_ab_info) = line.strip_prefix("# branch.ab ") {
+            let parts: Vec<&str> = branch_ab_info.split_whitespace().collect();
+            // The line reads "+<ahead> -<behind>"
+            if parts.len() == 2 {
+                ahead = parts[0].parse::<i32>().unwrap_or(0).unsigned_abs() as usize;
+                behind = parts[1].parse::<i32>().unwrap_or(0).unsigned_abs() as usize;
+            }
+        }
+    }
+
+    // Porcelain v2 reports a detached HEAD as "(detached)" - get commit hash
+    if (branch == "HEAD" || branch == "(detached)")
+        && let Ok(commit_output) = env
+            .command("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .current_dir(current_dir)
+            .output()
+        && commit_output.status.success()
+        && let Ok(commit_hash) = String::from_utf8(commit_output.stdout)
+    {
+        branch = format!("detached@{}", commit_hash.trim());
+    }
+
+    Some(GitStatus {
+        branch,
+        upstream,
+        files: parse_file_counts(&status_output),
+        ahead,
+        behind,
+        ..GitStatus::default()
+    })
+}
+
+/// Branch, upstream, file counts and ahead/behind computed in-process with gix, for
+/// systems where spawning git is slow or git isn't installed at all
+fn gix_status(git_dir: &Path) -> Option<GitStatus> {
+    let repo = open_repo(git_dir)?;
+
+    let head_name = repo.head_name().ok()?;
+    let head_id = repo.head_id().ok().map(|id| id.detach());
+    let branch = match (&head_name, head_id) {
+        (Some(name), _) => name.shorten().to_string(),
+        (None, Some(id)) => format!("detached@{}", id.to_hex_with_len(7)),
+        (None, None) => return None,
+    };
+
+    // Ahead/behind against the upstream configured for the current branch
+    let upstream_ref = head_name.as_ref().and_then(|name| {
+        let direction = gix::remote::Direction::Fetch;
+        // A remote of "." tracks a local branch, which has no remote-tracking ref
+        let local_upstream = repo
+            .config_snapshot()
+            .string(format!("branch.{}.remote", name.shorten()).as_str())
+            .is_some_and(|remote| remote.as_ref() == ".");
+        if local_upstream {
+            repo.branch_remote_ref_name(name.as_ref(), direction)?
+                .ok()
+                .map(|upstream| upstream.into_owned())
+        } else {
+            repo.branch_remote_tracking_ref_name(name.as_ref(), direction)?
+                .ok()
+                .map(|upstream| upstream.into_owned())
+        }
+    });
+    let upstream_id = upstream_ref
+        .as_ref()
+        .and_then(|upstream| repo.find_reference(upstream.as_ref()).ok())
+        .and_then(|mut reference| reference.peel_to_id_in_place().ok())
+        .map(|id| id.detach());
+    let (ahead, behind) = match (head_id, upstream_id) {
+        (Some(local), Some(upstream)) if local != upstream => {
+            // Commits reachable from one tip but not the other, like `git
+            // rev-list --count upstream..local`, which also holds across merges
+            let count_only_in = |tip: gix::ObjectId, other: gix::ObjectId| -> usize {
+                repo.rev_walk([tip])
+                    .with_hidden([other])
+                    .all()
+                    .map(|walk| walk.filter_map(Result::ok).count())
+                    .unwrap_or_default()
+            };
+            (
+                count_only_in(local, upstream),
+                count_only_in(upstream, local),
+            )
+        }
+        _ => (0, 0),
+    };
+
+    Some(GitStatus {
+        branch,
+        upstream: upstream_ref.map(|upstream| upstream.as_ref().shorten().to_string()),
+        files: gix_file_counts(&repo, head_id).unwrap_or_default(),
+        ahead,
+        behind,
+        ..GitStatus::default()
+    })
+}
+
+/// Count changed files by comparing HEAD with the index and the index with the worktree
+fn gix_file_counts(repo: &gix::Repository, head_id: Option<gix::ObjectId>) -> Option<FileCounts> {
+    let mut counts = FileCounts::default();
+    let index = repo.index_or_empty().ok()?;
+
+    // Staged changes are index entries that differ from the HEAD tree
+    let mut head_entries = HashMap::new();
+    if let Some(tree) = head_id
+        .and_then(|id| repo.find_object(id).ok())
+        .and_then(|object| object.peel_to_tree().ok())
+    {
+        let mut recorder = gix::traverse::tree::Recorder::default();
+        tree.traverse().breadthfirst(&mut recorder).ok()?;
+        head_entries = recorder
+            .records
+            .into_iter()
+            .filter(|entry| !entry.mode.is_tree())
+            .map(|entry| (entry.filepath, entry.oid))
+            .collect();
+    }
+    for entry in index.entries() {
+        let path = entry.path(&index);
+        if head_entries.remove(path) != Some(entry.id) {
+            counts.staged += 1;
+        }
+    }
+    // Whatever is left in HEAD was removed from the index
+    counts.staged += head_entries.len();
+
+    let statuses = repo
+        .status(gix::progress::Discard)
+        .ok()?
+        .index_worktree_rewrites(None)
+        .into_index_worktree_iter(Vec::new())
+        .ok()?;
+    for item in statuses.filter_map(Result::ok) {
+        use gix::status::index_worktree::iter::Summary;
+        match item.summary() {
+            Some(Summary::Added) => counts.untracked += 1,
+            Some(Summary::Removed) => counts.deleted += 1,
+            Some(_) => counts.modified += 1,
+            None => {}
+        }
+    }
+
+    Some(counts)
+}
+
+/// The effective `user.email`, resolved through repository, global and system config
+fn user_email(git_dir: &Path) -> Option<String> {
+    let repo = open_repo(git_dir)?;
+    let email = repo.config_snapshot().string("user.email")?.to_string();
+    Some(email).filter(|email| !email.is_empty())
+}
+
+/// Whether sparse-checkout is active, leaving part of the tree out of the worktree
+fn is_sparse_checkout(git_dir: &Path) -> bool {
+    // Without a patterns file git checks everything out even with the setting on
+    if !git_dir.join("info").join("sparse-checkout").is_file() {
+        return false;
+    }
+    open_repo(git_dir)
+        .and_then(|repo| repo.config_snapshot().boolean("core.sparseCheckout"))
+        .unwrap_or(false)
+}
+
+/// When the repository last fetched, from the FETCH_HEAD modification time
+fn last_fetch_time(common_dir: &Path) -> Option<i64> {
+    let modified = fs::metadata(common_dir.join("FETCH_HEAD"))
+        .ok()?
+        .modified()
+        .ok()?;
+    let seconds = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    i64::try_from(seconds).ok()
+}
+
+/// Committer timestamp of HEAD in seconds since the epoch
+fn head_commit_time(git_dir: &Path) -> Option<i64> {
+    let repo = open_repo(git_dir)?;
+    let time = repo.head_commit().ok()?.time().ok()?;
+    Some(time.seconds)
+}
+
+/// Name of a tag pointing exactly at HEAD, looked up with gix
+fn gix_head_tag(git_dir: &Path) -> Option<String> {
+    let repo = open_repo(git_dir)?;
+    let head_id = repo.head_id().ok()?.detach();
+    let references = repo.references().ok()?;
+    let tags = references.tags().ok()?;
+
+    tags.filter_map(Result::ok)
+        .filter_map(|mut tag| {
+            let name = tag.name().shorten().to_string();
+            let id = tag.peel_to_id_in_place().ok()?.detach();
+            (id == head_id).then_some(name)
+        })
+        .max()
+}
+
+/// Resolve `.git` to the real git directory, following the `gitdir:` file that
+/// linked worktrees and submodules use instead of a directory
+fn resolve_git_dir(dot_git: &Path) -> Option<PathBuf> {
+    if dot_git.is_dir() {
+        return Some(dot_git.to_path_buf());
+    }
+
+    let contents = fs::read_to_string(dot_git).ok()?;
+    let target = contents
+        .lines()
+        .find_map(|line| line.strip_prefix("gitdir:"))?
+        .trim();
+    // Relative paths are relative to the directory holding the .git file
+    let git_dir = dot_git.parent()?.join(target);
+    git_dir.is_dir().then_some(git_dir)
+}
+
+/// Find the git directory for `start` or its closest parent, without walking
+/// above any of the ceiling directories (as in GIT_CEILING_DIRECTORIES)
+fn discover_git_dir(start: &Path, ceilings: &[PathBuf]) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .take_while(|dir| *dir == start || !ceilings.iter().any(|ceiling| ceiling == dir))
+        .find_map(|dir| resolve_git_dir(&dir.join(".git")))
+}
+
+/// The directory holding config and refs shared by all worktrees of a repository
+fn common_git_dir(git_dir: &Path) -> PathBuf {
+    fs::read_to_string(git_dir.join("commondir"))
+        .map(|common| git_dir.join(common.trim()))
+        .unwrap_or_else(|_| git_dir.to_path_buf())
+}
+
+/// Count stash entries, one per line of the stash reflog
+fn count_stashes(git_dir: &Path) -> usize {
+    fs::read_to_string(git_dir.join("logs").join("refs").join("stash"))
+        .map(|reflog| reflog.lines().filter(|line| !line.is_empty()).count())
+        .unwrap_or(0)
+}
+
+/// URL of the named remote from the contents of `.git/config`
+fn remote_url(git_config: &str, remote: &str) -> Option<String> {
+    let section = format!("[remote \"{}\"]", remote);
+    let mut in_section = false;
+
+    for line in git_config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_section = line == section;
+            continue;
+        }
+        let url = in_section
+            .then(|| line.split_once('='))
+            .flatten()
+            .filter(|(key, _)| key.trim() == "url");
+        if let Some((_, url)) = url {
+            return Some(url.trim().to_string());
+        }
+    }
+
+    None
+}
+
+/// Host name of a remote URL, for both `https://host/repo` and `git@host:repo` forms
+fn remote_host(url: &str) -> Option<String> {
+    let without_scheme = match url.split_once("://") {
+        Some((_, rest)) => rest,
+        // scp-like syntax has no scheme and separates the path with a colon
+        None => url.split_once(':')?.0,
+    };
+    let authority = without_scheme.split('/').next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?.to_lowercase();
+    (!host.is_empty()).then_some(host)
+}
+
+/// Browsable https form of a remote URL, e.g. `git@host:owner/repo.git` to
+/// `https://host/owner/repo`. Local paths are returned unchanged.
+fn browsable_url(url: &str) -> String {
+    let Some(host) = remote_host(url) else {
+        return url.to_string();
+    };
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+        None => url.split_once(':').map_or("", |(_, path)| path),
+    };
+    let path = path.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    format!("https://{}/{}", host, path)
+}
+
+/// Web page for a branch, or a file on it, on the forge hosting `base`
+fn forge_url(base: &str, branch: &str, file: Option<&str>) -> String {
+    let host = remote_host(base).unwrap_or_default();
+    let (tree, blob) = if host.contains("gitlab") {
+        ("-/tree", "-/blob")
+    } else if host.contains("bitbucket") {
+        ("src", "src")
+    } else {
+        ("tree", "blob")
+    };
+
+    match file {
+        Some(file) => format!("{}/{}/{}/{}", base, blob, branch, file),
+        None => format!("{}/{}/{}", base, tree, branch),
+    }
+}
+
+/// Open a URL with the platform's default handler
+fn open_in_browser(url: &str) -> bool {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Directories that looking for a repository stops at, from GIT_CEILING_DIRECTORIES
+fn git_ceilings(env: &Env) -> Vec<PathBuf> {
+    env.var_os("GIT_CEILING_DIRECTORIES")
+        .map(|dirs| env::split_paths(&dirs).collect())
+        .unwrap_or_default()
+}
+
+/// Working tree root of the repository containing `start`, not looking above
+/// any of the ceiling directories
+fn repo_root(start: &Path, ceilings: &[PathBuf]) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .take_while(|dir| *dir == start || !ceilings.iter().any(|ceiling| ceiling == dir))
+        .find(|dir| resolve_git_dir(&dir.join(".git")).is_some())
+        .map(Path::to_path_buf)
+}
+
+/// URL of the `origin` remote for a git directory
+fn origin_url(git_dir: &Path) -> Option<String> {
+    fs::read_to_string(common_git_dir(git_dir).join("config"))
+        .ok()
+        .and_then(|git_config| remote_url(&git_config, "origin"))
+}
+
+/// Name of a tag pointing exactly at HEAD, preferring the most recently created one
+fn head_tag(repo_dir: &Path, timeout: Option<Duration>, env: &Env) -> Option<String> {
+    let output = output_with_timeout(
+        env.command("git")
+            .args(["tag", "--points-at", "HEAD", "--sort=-creatordate"])
+            .current_dir(repo_dir),
+        timeout,
+    )?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+}
+
+/// Describe an in-progress merge, rebase, cherry-pick, revert or bisect, e.g. `REBASE 2/5`
+fn git_operation(git_dir: &Path) -> Option<String> {
+    let read_number =
+        |path: PathBuf| -> Option<usize> { fs::read_to_string(path).ok()?.trim().parse().ok() };
+    let with_progress = |label: &str, step: Option<usize>, total: Option<usize>| match (step, total)
+    {
+        (Some(step), Some(total)) => format!("{} {}/{}", label, step, total),
+        _ => label.to_string(),
+    };
+
+    let rebase_merge = git_dir.join("rebase-merge");
+    if rebase_merge.is_dir() {
+        return Some(with_progress(
+            "REBASE",
+            read_number(rebase_merge.join("msgnum")),
+            read_number(rebase_merge.join("end")),
+        ));
+    }
+
+    let rebase_apply = git_dir.join("rebase-apply");
+    if rebase_apply.is_dir() {
+        // `git am` shares the rebase-apply directory and marks it with an applying file
+        let label = if rebase_apply.join("applying").exists() {
+            "AM"
+        } else {
+            "REBASE"
+        };
+        return Some(with_progress(
+            label,
+            read_number(rebase_apply.join("next")),
+            read_number(rebase_apply.join("last")),
+        ));
+    }
+
+    [
+        ("MERGE_HEAD", "MERGE"),
+        ("CHERRY_PICK_HEAD", "CHERRY-PICK"),
+        ("REVERT_HEAD", "REVERT"),
+        ("BISECT_LOG", "BISECT"),
+    ]
+    .iter()
+    .find(|(file, _)| git_dir.join(file).exists())
+    .map(|(_, label)| label.to_string())
+}
+
+/// Get the commit SHA of the repository in the current directory, abbreviated
+/// unless `full` is set
+fn get_git_commit_sha(full: bool) -> Option<String> {
+    let current_dir = env::current_dir().ok()?;
+
+    // Try to get the commit hash using git command
+    let args = if full {
+        ["rev-parse", "HEAD"].as_slice()
+    } else {
+        ["rev-parse", "--short", "HEAD"].as_slice()
+    };
+    if let Ok(output) = Command::new("git")
+        .args(args)
+        .current_dir(&current_dir)
+        .output()
+    {
+        if output.status.success() {
+            if let Ok(sha) = String::from_utf8(output.stdout) {
+                return Some(sha.trim().to_string());
+            }
+        }
+    }
+
+    // Fallback to gix if git command fails
+    match gix::open(&current_dir) {
+        Ok(repo) => {
+            if let Ok(head) = repo.head() {
+                // Different approach to get the commit id from gix
+                if let Some(id) = head.id() {
+                    if full {
+                        return Some(id.to_string());
+                    }
+                    // Get short SHA (7 characters)
+                    let short_id = id.to_string()[..7].to_string();
+                    return Some(short_id);
+                }
+            }
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/// Commit this binary was built from, recorded by build.rs; None when it was
+/// built outside a git checkout
+fn build_commit_sha() -> Option<&'static str> {
+    Some(env!("LOCO_PILOT_GIT_SHA")).filter(|sha| !sha.is_empty())
+}
+
+/// Cargo features this binary was built with
+fn enabled_features() -> Vec<&'static str> {
+    [("wasm-plugins", cfg!(feature = "wasm-plugins"))]
+        .into_iter()
+        .filter_map(|(feature, enabled)| enabled.then_some(feature))
+        .collect()
+}
+
+/// Get the full version string including the commit it was built from
+fn get_full_version() -> String {
+    // Get the crate version from Cargo.toml via env
+    let version = env!("CARGO_PKG_VERSION");
+
+    // Append the git SHA if available
+    if let Some(sha) = build_commit_sha() {
+        format!("{} ({})", version, sha)
+    } else {
+        version.to_string()
+    }
+}
+
+/// Build metadata for `version --json`, e.g. to paste into a bug report
+fn version_json() -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": build_commit_sha(),
+        "build_date": env!("LOCO_PILOT_BUILD_DATE"),
+        "target": env!("LOCO_PILOT_TARGET"),
+        "features": enabled_features(),
+    })
+}
+
+/// Find an executable on PATH
+fn find_in_path(env: &Env, name: &str) -> Option<PathBuf> {
+    let path = env.var_os("PATH")?;
+    env::split_paths(&path).find_map(|dir| {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        let candidate = dir.join(format!("{}.exe", name));
+        candidate.is_file().then_some(candidate)
+    })
+}
+
+/// Clipboard tools suited to the current environment, in order of preference
+fn clipboard_tools() -> Vec<(&'static str, &'static [&'static str])> {
+    let mut tools: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+    if cfg!(target_os = "macos") {
+        tools.push(("pbcopy", &[]));
+    }
+    if cfg!(windows) {
+        tools.push(("clip", &[]));
+    }
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(("wl-copy", &[]));
+    }
+    if env::var_os("DISPLAY").is_some() {
+        tools.push(("xclip", &["-selection", "clipboard"]));
+        tools.push(("xsel", &["--clipboard", "--input"]));
+    }
+    if segments::wsl::is_wsl(&Env::default()) {
+        tools.push(("clip.exe", &[]));
+    }
+    tools
+}
+
+/// Ask the terminal to set the clipboard with an OSC 52 escape sequence, which
+/// works over SSH since the terminal emulator does the copying
+fn copy_with_osc52(text: &str) -> bool {
+    let sequence = format!("\x1b]52;c;{}\x07", BASE64_STANDARD.encode(text));
+    // tmux only forwards escape sequences wrapped in a DCS passthrough
+    let sequence = if env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    };
+
+    // Write to the terminal directly so stdout stays clean for command substitution
+    fs::OpenOptions::new()
+        .write(true)
+        .open("/dev/tty")
+        .and_then(|mut tty| tty.write_all(sequence.as_bytes()))
+        .or_else(|_| io::stderr().write_all(sequence.as_bytes()))
+        .is_ok()
+}
+
+/// Copy text to the system clipboard with the configured backend
+fn copy_to_clipboard(text: &str, clipboard_config: &ClipboardConfig) -> bool {
+    match clipboard_config.backend.as_str() {
+        "osc52" => copy_with_osc52(text),
+        _ => {
+            copy_with_clipboard_tool(text)
+                || (segments::ssh::is_ssh_session(&Env::default()) && copy_with_osc52(text))
+        }
+    }
+}
+
+/// Copy text using the first local clipboard tool that works
+fn copy_with_clipboard_tool(text: &str) -> bool {
+    clipboard_tools().iter().any(|(program, args)| {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            return false;
+        };
+        let written = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        child.wait().is_ok_and(|status| status.success()) && written
+    })
+}
+
+/// Print a value from a copy command and put it on the clipboard unless `print_only`
+fn print_and_copy(text: &str, print_only: bool) {
+    println!("{}", text);
+    if !print_only && !copy_to_clipboard(text, &load_config().clipboard) {
+        eprintln!("No clipboard tool found; printed only (use --print-only to silence this)");
+    }
+}
+
+/// Let the user pick one of `items` from a numbered menu. The menu goes to
+/// stderr so stdout stays clean for command substitution.
+fn select_item(title: &str, items: &[String]) -> Option<usize> {
+    if items.is_empty() {
+        return None;
+    }
+    if items.len() == 1 {
+        return Some(0);
+    }
+
+    eprintln!("{}", title);
+    for (index, item) in items.iter().enumerate() {
+        eprintln!("  {:>2}) {}", index + 1, item);
+    }
+    eprint!("Select [1-{}]: ", items.len());
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).ok()?;
+    answer
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|choice| (1..=items.len()).contains(choice))
+        .map(|choice| choice - 1)
+}
+
+/// A `git worktree list --porcelain` entry: its path and what it has checked out
+#[derive(Debug, PartialEq)]
+struct Worktree {
+    path: String,
+    label: String,
+}
+
+/// Parse `git worktree list --porcelain` output
+fn parse_worktrees(output: &str) -> Vec<Worktree> {
+    output
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut path = None;
+            let mut label = String::new();
+            for line in block.lines() {
+                if let Some(worktree) = line.strip_prefix("worktree ") {
+                    path = Some(worktree.to_string());
+                } else if let Some(branch) = line.strip_prefix("branch ") {
+                    label = branch
+                        .strip_prefix("refs/heads/")
+                        .unwrap_or(branch)
+                        .to_string();
+                } else if let Some(head) = line.strip_prefix("HEAD ") {
+                    if label.is_empty() {
+                        label = format!("detached@{}", head.get(..7).unwrap_or(head));
+                    }
+                } else if line == "bare" {
+                    label = "bare".to_string();
+                }
+            }
+            path.map(|path| Worktree { path, label })
+        })
+        .collect()
+}
+
+/// A stash entry: its ref, relative age and message
+#[derive(Debug, PartialEq)]
+struct StashEntry {
+    reference: String,
+    age: String,
+    message: String,
+}
+
+/// Parse `git stash list --format=%gd%x09%cr%x09%gs` output
+fn parse_stash_list(output: &str) -> Vec<StashEntry> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some(StashEntry {
+                reference: fields.next()?.to_string(),
+                age: fields.next()?.to_string(),
+                message: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Cache for username
+static USERNAME_CACHE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// Get username with caching
+#[inline]
+fn get_username(env: &Env) -> String {
+    if test_mode::enabled(env) {
+        return test_mode::USERNAME.to_string();
+    }
+    let mut cache = lock(&USERNAME_CACHE);
+    if let Some(username) = &*cache {
+        return username.clone();
+    }
+
+    // Windows uses USERNAME instead of USER
+    let username = env
+        .var("USER")
+        .or_else(|| env.var("USERNAME"))
+        .unwrap_or_else(|| "user".to_string());
+    *cache = Some(username.clone());
+    username
+}
+
+/// Resolve the configured time format into a strftime string
+fn time_format(time_config: &TimeConfig) -> String {
+    let format = match time_config.format.as_str() {
+        "24h" => "%H:%M:%S",
+        "12h" => "%I:%M:%S %p",
+        custom => custom,
+    };
+
+    // chrono fails to render invalid specifiers, so fall back to the default
+    let invalid = StrftimeItems::new(format).any(|item| matches!(item, Item::Error));
+    let format = if invalid { "%H:%M:%S" } else { format };
+
+    if time_config.show_seconds {
+        format.to_string()
+    } else {
+        without_seconds(format)
+    }
+}
+
+/// A strftime format with the seconds left out, along with the colon before
+/// them. Specifiers that include the seconds get a shorter equivalent.
+fn without_seconds(format: &str) -> String {
+    let mut output = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        // Padding and width modifiers come between the % and the specifier
+        let mut spec = String::from('%');
+        let mut next = chars.next();
+        while let Some(modifier @ ('-' | '_' | '.' | ':' | '#' | '0'..='9')) = next {
+            spec.push(modifier);
+            next = chars.next();
+        }
+        match next {
+            Some('T' | 'X') => output.push_str("%H:%M"),
+            Some('r') => output.push_str("%I:%M %p"),
+            Some('S') => {
+                if output.ends_with(':') {
+                    output.pop();
+                }
+            }
+            // Covers %%, so an escaped % followed by S stays as is
+            Some(specifier) => {
+                output.push_str(&spec);
+                output.push(specifier);
+            }
+            None => output.push_str(&spec),
+        }
+    }
+    output
+}
+
+/// Format the current local time, followed by any configured timezones
+fn format_current_time(time_config: &TimeConfig, env: &Env) -> String {
+    let format = time_format(time_config);
+    let now = test_mode::now(env);
+    // The local timezone is the machine's, so test mode shows UTC
+    let mut time = if test_mode::enabled(env) {
+        now.format(&format).to_string()
+    } else {
+        now.with_timezone(&Local).format(&format).to_string()
+    };
+
+    // Unknown timezone names are skipped rather than breaking the prompt
+    for tz in time_config
+        .timezones
+        .iter()
+        .filter_map(|name| name.trim().parse::<Tz>().ok())
+    {
+        let zoned = now.with_timezone(&tz);
+        time.push_str(&format!(
+            " | {} {}",
+            zoned.format("%Z"),
+            zoned.format(&format)
+        ));
+    }
+
+    time
+}
+
+/// Per-invocation state passed in by the shell hook
+#[derive(Debug, Default)]
+struct PromptContext {
+    /// Exit status of the previous command
+    status: Option<i32>,
+    /// Duration of the previous command in milliseconds
+    cmd_duration: Option<u64>,
+    /// Exit codes of each stage of the previous pipeline
+    pipestatus: Vec<i32>,
+    /// Current vi editing mode
+    vi_mode: Option<ViMode>,
+    /// Whether the right prompt is being generated
+    right: bool,
+    /// Terminal width, for `$fill`
+    columns: Option<usize>,
+    /// Rendering a preview rather than the shell's prompt: lay it out by its
+    /// style alone and leave out terminal control sequences
+    preview: bool,
+    /// Colors were turned off with `--no-color`
+    no_color: bool,
+    /// Environment variables to render with, which are the shell's rather than
+    /// the daemon's when it renders the prompt
+    env: Env,
+}
+
+/// Parse a space separated list of exit codes, ignoring anything that isn't a number
+fn parse_pipestatus(pipestatus: &str) -> Vec<i32> {
+    pipestatus
+        .split_whitespace()
+        .filter_map(|code| code.parse().ok())
+        .collect()
+}
+
+/// Build the exit status text, or None if the previous command succeeded
+fn format_status(
+    status: Option<i32>,
+    pipestatus: &[i32],
+    pipestatus_format: &str,
+) -> Option<String> {
+    // A multi-stage pipeline can fail in the middle even when $? is zero
+    if pipestatus.len() > 1 {
+        if pipestatus.iter().all(|code| *code == 0) {
+            return None;
+        }
+        return Some(match pipestatus_format {
+            // Like pipefail, report the rightmost failing stage
+            "collapse" => pipestatus
+                .iter()
+                .rev()
+                .find(|code| **code != 0)
+                .map(|code| code.to_string())
+                .unwrap_or_default(),
+            _ => pipestatus
+                .iter()
+                .map(|code| code.to_string())
+                .collect::<Vec<_>>()
+                .join("|"),
+        });
+    }
+
+    match status {
+        Some(code) if code != 0 => Some(code.to_string()),
+        _ => None,
+    }
+}
+
+/// Format a duration in milliseconds as a short human-readable string
+fn format_duration(millis: u64) -> String {
+    if millis < 1000 {
+        format!("{}ms", millis)
+    } else if millis < 60_000 {
+        format!("{:.1}s", millis as f64 / 1000.0)
+    } else if millis < 3_600_000 {
+        format!("{}m {}s", millis / 60_000, (millis % 60_000) / 1000)
+    } else {
+        format!("{}h {}m", millis / 3_600_000, (millis % 3_600_000) / 60_000)
+    }
+}
+
+/// Format an age in seconds with its largest unit, e.g. 45s, 3d or 2y
+fn format_age(seconds: u64) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+    const WEEK: u64 = 7 * DAY;
+    const MONTH: u64 = 30 * DAY;
+    const YEAR: u64 = 365 * DAY;
+
+    match seconds {
+        s if s < MINUTE => format!("{}s", s),
+        s if s < HOUR => format!("{}m", s / MINUTE),
+        s if s < DAY => format!("{}h", s / HOUR),
+        s if s < WEEK => format!("{}d", s / DAY),
+        s if s < MONTH => format!("{}w", s / WEEK),
+        s if s < YEAR => format!("{}mo", s / MONTH),
+        s => format!("{}y", s / YEAR),
+    }
+}
+
+/// Generate the prompt string
+fn generate_prompt(style: &str, shell: ShellKind, context: &PromptContext) -> String {
+    generate_prompt_segments(style, shell, context).0
+}
+
+/// One segment of a rendered prompt, for status bars and editors that show
+/// loco-pilot's information their own way
+#[derive(Debug, Serialize)]
+struct PromptSegment {
+    /// The variable the segment is shown for, e.g. "git"
+    name: String,
+    /// What the segment shows, without color codes
+    text: String,
+    /// Color name from the config, empty when the segment mixes colors
+    style: String,
+    /// The data behind the text, when there's more to it
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    metadata: serde_json::Value,
+}
+
+/// Generate the prompt along with the segments it's made of
+fn generate_prompt_segments(
+    style: &str,
+    shell: ShellKind,
+    context: &PromptContext,
+) -> (String, Vec<PromptSegment>) {
+    // Load configuration to get user-defined colors
+    let env = &context.env;
+    let mut config = load_config_in(env);
+    if context.right && config.right_format.is_empty() {
+        return (String::new(), Vec::new());
+    }
+    let no_color = colors_disabled(context.no_color, &config, env);
+    set_colors(!no_color);
+    let _deadline = PromptDeadline::start(config.prompt_timeout);
+    let ascii = config.ascii || !utf8_locale(env);
+    let nerd_font = !ascii && config.icons == "nerd-font";
+    if ascii {
+        apply_ascii_symbols(&mut config);
+    } else if nerd_font {
+        apply_nerd_font_icons(&mut config);
+    }
+
+    let current_time = format_current_time(&config.time, env);
+    let username = get_username(env);
+    let hostname = get_hostname(env);
+    let is_wsl = segments::wsl::is_wsl(env);
+    let current_dir = get_shortened_dir(is_wsl && config.wsl.windows_paths, &config.directory, env);
+
+    // Map color names, palette indices and hex values to ANSI color codes
+    let color_map = |color_name: &str| color::ansi_code(color_name, env);
+
+    // Colors are plain ANSI codes until the prompt is escaped for the shell
+    let shell_color = |ansi_code: &str| {
+        if no_color {
+            String::new()
+        } else {
+            ansi_code.to_string()
+        }
+    };
+
+    // Remote sessions can hide or recolor the hostname so they stand out
+    let ssh_session = segments::ssh::is_ssh_session(env);
+    let in_multiplexer = segments::multiplexer::in_multiplexer(env);
+    let show_hostname = (ssh_session || !config.hostname.ssh_only)
+        && !(in_multiplexer && config.tmux.hide_hostname);
+    let hostname_color_name = if ssh_session {
+        &config.colors.hostname_ssh
+    } else {
+        &config.colors.hostname
+    };
+
+    // Make it obvious when commands run with root privileges
+    let is_root = segments::root::is_root(env);
+    let username_color_name = if is_root {
+        &config.colors.username_root
+    } else {
+        &config.colors.username
+    };
+
+    // The prompt character reflects root, the last exit status and the style
+    let failed = context.status.is_some_and(|code| code != 0);
+    let prompt_char = match (is_root, failed, style) {
+        (true, _, _) => config.root.symbol.as_str(),
+        (false, true, "emoji") => config.character.emoji_error_symbol.as_str(),
+        (false, false, "emoji") => config.character.emoji_success_symbol.as_str(),
+        (false, true, _) => config.character.error_symbol.as_str(),
+        (false, false, _) => config.character.success_symbol.as_str(),
+    };
+    let prompt_char_color_name = if failed {
+        &config.colors.character_error
+    } else if is_root {
+        &config.colors.character_root
+    } else {
+        &config.colors.character_success
+    };
+
+    // Create ANSI color sequences with shell prompt escaping based on user configuration
+    let username_color = shell_color(&color_map(username_color_name));
+    let hostname_color = shell_color(&color_map(hostname_color_name));
+    let dir_color = shell_color(&color_map(&config.colors.directory));
+    let time_color = shell_color(&color_map(&config.colors.time));
+    let reset = shell_color("\x1b[0m");
+
+    // Format colored text segments
+    let (user_icon, dir_icon) = match nerd_font {
+        true => (icons::os_symbol(), icons::FOLDER),
+        false => ("", ""),
+    };
+    let username_fmt = format!("{}{}{}{}", username_color, user_icon, username, reset);
+    let hostname_fmt = format!("{}{}{}", hostname_color, hostname, reset);
+    let dir_fmt = format!("{}{}{}{}", dir_color, dir_icon, current_dir, reset);
+    // Narrow terminals can get away with just the last component
+    let short_dir = current_dir
+        .rsplit(['/', '\\'])
+        .find(|component| !component.is_empty())
+        .unwrap_or(&current_dir);
+    let short_dir_fmt = format!("{}{}{}{}", dir_color, dir_icon, short_dir, reset);
+    let time_fmt = format!("{}{}{}", time_color, current_time, reset);
+
+    // Format an environment segment for the current style
+    let env_segment = |color_name: &str, symbol: &str, text: &str| -> String {
+        match style {
+            "emoji" => format!(" {}{}", symbol, text),
+            _ => format!(
+                " {}{}{}{}",
+                shell_color(&color_map(color_name)),
+                symbol,
+                text,
+                reset
+            ),
+        }
+    };
+
+    // The right prompt only computes the segments its template uses
+    let segment_enabled = |name: &str| {
+        let in_layout = !context.right || config.right_format.contains(&format!("${}", name));
+        in_layout
+            && !config
+                .segments
+                .disabled
+                .iter()
+                .any(|disabled| disabled == name)
+    };
+    let show_details = context.right || style != "minimal";
+
+    // Environment segments sit between the directory and git information
+    let show_env = show_details && segment_enabled("env");
+    let mut env_info = String::new();
+
+    let python_env = (show_env && config.python.show)
+        .then(|| segments::python::virtualenv(env))
+        .flatten();
+    if let Some(venv) = python_env {
+        env_info.push_str(&env_segment(
+            &config.colors.python,
+            &config.python.symbol,
+            &venv,
+        ));
+    }
+
+    let conda_env = (show_env && config.conda.show)
+        .then(|| segments::conda::environment(env, config.conda.hide_base))
+        .flatten();
+    if let Some(conda_env) = conda_env {
+        env_info.push_str(&env_segment(
+            &config.colors.conda,
+            &config.conda.symbol,
+            &conda_env,
+        ));
+    }
+
+    let node_version = match show_env && config.node.show {
+        false => None,
+        // Out of time, show what an earlier prompt found instead of running node
+        true if past_deadline() => env::current_dir()
+            .ok()
+            .filter(|dir| segments::node::is_node_project(dir))
+            .map(|_| {
+                segments::node::cached_version(env)
+                    .unwrap_or_else(|| config.timeout_placeholder.clone())
+            }),
+        true => segments::node::version(env, Duration::from_secs(config.node.cache_ttl)),
+    };
+    if let Some(node_version) = node_version {
+        env_info.push_str(&env_segment(
+            &config.colors.node,
+            &config.node.symbol,
+            &node_version,
+        ));
+    }
+
+    let gcp_context = (show_env && config.gcp.show)
+        .then(|| segments::gcp::context(env))
+        .flatten();
+    if let Some(gcp_context) = gcp_context {
+        let project = gcp_context.project.unwrap_or_default();
+        let text = match gcp_context.account {
+            Some(account) if config.gcp.show_account => {
+                // Only the local part of the account keeps the segment short
+                let account = account.split('@').next().unwrap_or_default().to_string();
+                if project.is_empty() {
+                    account
+                } else {
+                    format!("{}@{}", account, project)
+                }
+            }
+            _ => project,
+        };
+        if !text.is_empty() {
+            env_info.push_str(&env_segment(&config.colors.gcp, &config.gcp.symbol, &text));
+        }
+    }
+
+    let nix_shell = (show_env && config.nix.show)
+        .then(|| segments::nix::shell(env))
+        .flatten();
+    if let Some(nix_shell) = nix_shell {
+        env_info.push_str(&env_segment(
+            &config.colors.nix,
+            &config.nix.symbol,
+            &nix_shell,
+        ));
+    }
+
+    let load_average = (show_env && config.load.show)
+        .then(|| segments::load::load_average(env))
+        .flatten();
+    if let Some(load_average) = load_average {
+        // Color the load relative to how many CPUs share it
+        let load_color = match segments::load::load_level(load_average, segments::load::cpu_count())
+        {
+            segments::load::LoadLevel::Low => "green",
+            segments::load::LoadLevel::Medium => "yellow",
+            segments::load::LoadLevel::High => "red",
+        };
+        env_info.push_str(&env_segment(
+            load_color,
+            &config.load.symbol,
+            &format!("{:.2}", load_average),
+        ));
+    }
+
+    let container = (show_env && config.container.show)
+        .then(|| segments::container::detect(env))
+        .flatten();
+    if let Some(container) = container {
+        let text = match container.name {
+            Some(name) => format!("{}:{}", container.engine, name),
+            None => container.engine,
+        };
+        env_info.push_str(&env_segment(
+            &config.colors.container,
+            &config.container.symbol,
+            &text,
+        ));
+    }
+
+    if show_env && is_wsl && config.wsl.show {
+        env_info.push_str(&env_segment(
+            &config.colors.wsl,
+            &config.wsl.symbol,
+            &segments::wsl::distro_name(env),
+        ));
+    }
+
+    let session_name = (show_env && in_multiplexer && config.tmux.show)
+        .then(|| segments::multiplexer::session_name(env))
+        .flatten();
+    if let Some(session_name) = session_name {
+        env_info.push_str(&env_segment(
+            &config.colors.tmux,
+            &config.tmux.symbol,
+            &session_name,
+        ));
+    }
+
+    let env_var_value = show_env
+        .then(|| {
+            segments::env_var::value(
+                env,
+                &config.env_var.name,
+                &config.env_var.prefix,
+                &config.env_var.suffix,
+                &config.env_var.fallback,
+            )
+        })
+        .flatten();
+    if let Some(env_var_value) = env_var_value {
+        env_info.push_str(&env_segment(&config.colors.env_var, "", &env_var_value));
+    }
+
+    // Only get git info if it's needed for the selected style
+    let git_status = (show_details && config.show_git && segment_enabled("git"))
+        .then(|| get_git_info(&config.git, env))
+        .flatten();
+    let git_info = git_status
+        .clone()
+        .map(|status| {
+            let branch_color = shell_color(&color_map(&config.colors.git_branch));
+            // Ahead/behind counts are only as fresh as the last fetch
+            let fetch_age = status
+                .fetch_time
+                .map(|fetch_time| (chrono::Utc::now().timestamp() - fetch_time).max(0) as u64);
+            let stale = config.git.stale_fetch_age > 0
+                && fetch_age.is_some_and(|age| age > config.git.stale_fetch_age);
+            let (ahead_color, behind_color) = if stale {
+                let stale_color = shell_color(&color_map(&config.colors.git_stale));
+                (stale_color.clone(), stale_color)
+            } else {
+                (
+                    shell_color("\x1b[01;33m"), // Bold Yellow
+                    shell_color("\x1b[01;35m"), // Bold Purple
+                )
+            };
+
+            // A tag on HEAD replaces the detached commit hash, or follows the branch name
+            let detached = status.branch.starts_with("detached@");
+            let tag_info = status
+                .tag
+                .as_ref()
+                .map(|tag| match style {
+                    "emoji" => format!("{}{}", config.git.tag_symbol, tag),
+                    _ => format!(
+                        "{}{}{}{}",
+                        shell_color(&color_map(&config.colors.git_tag)),
+                        config.git.tag_symbol,
+                        tag,
+                        reset
+                    ),
+                })
+                .unwrap_or_default();
+
+            // Provider icon for the origin remote, matching subdomains of configured hosts
+            let remote_info = status
+                .remote_host
+                .as_ref()
+                .filter(|_| config.git.show_remote)
+                .map(|host| {
+                    config
+                        .git
+                        .remote_symbols
+                        .iter()
+                        .find(|(domain, _)| {
+                            host == *domain || host.ends_with(&format!(".{}", domain))
+                        })
+                        .map(|(_, symbol)| symbol.as_str())
+                        .unwrap_or(&config.git.remote_default_symbol)
+                })
+                .filter(|symbol| !symbol.is_empty())
+                .map(|symbol| format!(" {}", symbol))
+                .unwrap_or_default();
+
+            // The tracked upstream follows the branch, e.g. main → origin/main
+            let arrow = if ascii { "->" } else { "→" };
+            let upstream_info = status
+                .upstream
+                .as_ref()
+                .filter(|_| config.git.show_upstream)
+                .map(|upstream| match style {
+                    "emoji" => format!(" {} {}", arrow, upstream),
+                    _ => format!(
+                        " {} {}{}{}",
+                        arrow,
+                        shell_color(&color_map(&config.colors.git_upstream)),
+                        upstream,
+                        reset
+                    ),
+                })
+                .unwrap_or_default();
+
+            let branch_name = truncate_branch(
+                &status.branch,
+                config.git.truncate_branch,
+                &config.git.truncate_symbol,
+                &config.git.truncate_position,
+            );
+
+            let emoji_branch_symbol = &config.git.emoji_branch_symbol;
+            let branch_info = match style {
+                "emoji" if detached && !tag_info.is_empty() => format!(" {}", tag_info),
+                "emoji" if !tag_info.is_empty() => format!(
+                    " {}{}{} {}",
+                    emoji_branch_symbol, branch_name, upstream_info, tag_info
+                ),
+                "emoji" => format!(" {}{}{}", emoji_branch_symbol, branch_name, upstream_info),
+                _ if detached && !tag_info.is_empty() => format!(" ({})", tag_info),
+                _ => {
+                    let colored_branch = format!(
+                        "{}{}{}{}{}",
+                        branch_color, config.git.branch_symbol, branch_name, reset, upstream_info
+                    );
+                    if tag_info.is_empty() {
+                        format!(" ({})", colored_branch)
+                    } else {
+                        format!(" ({}) {}", colored_branch, tag_info)
+                    }
+                }
+            };
+
+            // Call out merges, rebases and the like before anything else
+            let operation_info = match &status.operation {
+                Some(operation) if config.git.show_operation => match style {
+                    "emoji" if nerd_font => format!(" {}{}", icons::OPERATION, operation),
+                    "emoji" if ascii => format!(" {}", operation),
+                    "emoji" => format!(" 🚧 {}", operation),
+                    _ => format!(
+                        " {}{}{}",
+                        shell_color(&color_map(&config.colors.git_operation)),
+                        operation,
+                        reset
+                    ),
+                },
+                _ => String::new(),
+            };
+
+            // Add ahead/behind indicators
+            let mut ahead_behind = String::new();
+            if status.ahead > 0 {
+                ahead_behind.push_str(&match style {
+                    "emoji" => format!(" {}{}", config.git.ahead_symbol, status.ahead),
+                    _ => format!(
+                        " {}{}{}{}",
+                        ahead_color, config.git.ahead_symbol, status.ahead, reset
+                    ),
+                });
+            }
+            if status.behind > 0 {
+                ahead_behind.push_str(&match style {
+                    "emoji" => format!(" {}{}", config.git.behind_symbol, status.behind),
+                    _ => format!(
+                        " {}{}{}{}",
+                        behind_color, config.git.behind_symbol, status.behind, reset
+                    ),
+                });
+            }
+
+            // Per-category file counts, e.g. +2 ~1 -1 ?3
+            let mut dirty_info = String::new();
+            let file_counts = [
+                (
+                    config.git.show_staged,
+                    &config.git.staged_symbol,
+                    status.files.staged,
+                    &config.colors.git_staged,
+                ),
+                (
+                    config.git.show_modified,
+                    &config.git.modified_symbol,
+                    status.files.modified,
+                    &config.colors.git_dirty,
+                ),
+                (
+                    config.git.show_deleted,
+                    &config.git.deleted_symbol,
+                    status.files.deleted,
+                    &config.colors.git_deleted,
+                ),
+                (
+                    config.git.show_untracked,
+                    &config.git.untracked_symbol,
+                    status.files.untracked,
+                    &config.colors.git_untracked,
+                ),
+            ];
+            for (show, symbol, count, color_name) in file_counts {
+                if !show || count == 0 {
+                    continue;
+                }
+                dirty_info.push_str(&match style {
+                    "emoji" => format!(" {}{}", symbol, count),
+                    _ => format!(
+                        " {}{}{}{}",
+                        shell_color(&color_map(color_name)),
+                        symbol,
+                        count,
+                        reset
+                    ),
+                });
+            }
+
+            let stash_info = if config.git.show_stash && status.stash > 0 {
+                match style {
+                    "emoji" => format!(" {}{}", config.git.stash_symbol, status.stash),
+                    _ => {
+                        let stash_color = shell_color(&color_map(&config.colors.git_stash));
+                        format!(
+                            " {}{}{}{}",
+                            stash_color, config.git.stash_symbol, status.stash, reset
+                        )
+                    }
+                }
+            } else {
+                String::new()
+            };
+
+            // Remind that files missing from a sparse checkout aren't deleted
+            let sparse_info = match style {
+                _ if !status.sparse => String::new(),
+                "emoji" => format!(" {}", config.git.sparse_symbol),
+                _ => format!(
+                    " {}{}{}",
+                    shell_color(&color_map(&config.colors.git_sparse)),
+                    config.git.sparse_symbol,
+                    reset
+                ),
+            };
+
+            // Warn before committing with the wrong identity
+            let expected_email = &config.git.expected_email;
+            let email_info = match status.email.as_deref() {
+                _ if expected_email.is_empty() => String::new(),
+                Some(email) if email.eq_ignore_ascii_case(expected_email) => String::new(),
+                email => {
+                    let email = email.unwrap_or("no user.email");
+                    match style {
+                        "emoji" => format!(" {}{}", config.git.email_symbol, email),
+                        _ => format!(
+                            " {}{}{}{}",
+                            shell_color(&color_map(&config.colors.git_email)),
+                            config.git.email_symbol,
+                            email,
+                            reset
+                        ),
+                    }
+                }
+            };
+
+            let fetch_age_info = match fetch_age {
+                Some(age) if config.git.show_fetch_age => match style {
+                    "emoji" => format!(" {}{}", config.git.fetch_age_symbol, format_age(age)),
+                    _ => format!(
+                        " {}{}{}{}",
+                        shell_color(&color_map(&config.colors.git_stale)),
+                        config.git.fetch_age_symbol,
+                        format_age(age),
+                        reset
+                    ),
+                },
+                _ => String::new(),
+            };
+
+            // How long ago HEAD was committed, so stale checkouts stand out
+            let commit_age_info = match status.commit_time {
+                Some(commit_time) if config.git.show_commit_age => {
+                    let age =
+                        format_age((chrono::Utc::now().timestamp() - commit_time).max(0) as u64);
+                    match style {
+                        "emoji" => format!(" {}{}", config.git.commit_age_symbol, age),
+                        _ => format!(
+                            " {}{}{}{}",
+                            shell_color(&color_map(&config.colors.git_commit_age)),
+                            config.git.commit_age_symbol,
+                            age,
+                            reset
+                        ),
+                    }
+                }
+                _ => String::new(),
+            };
+
+            format!(
+                "{}{}{}{}{}{}{}{}{}{}",
+                remote_info,
+                branch_info,
+                sparse_info,
+                operation_info,
+                ahead_behind,
+                fetch_age_info,
+                dirty_info,
+                stash_info,
+                commit_age_info,
+                email_info
+            )
+        })
+        .unwrap_or_default();
+
+    // Only show the command duration when it crossed the configured threshold
+    let duration_info = match context.cmd_duration {
+        Some(millis) if millis >= config.duration.threshold => match style {
+            "emoji" if nerd_font => format!(" {}{}", icons::CLOCK, format_duration(millis)),
+            "emoji" if ascii => format!(" took {}", format_duration(millis)),
+            "emoji" => format!(" ⏱️ {}", format_duration(millis)),
+            _ => {
+                let duration_color = shell_color(&color_map(&config.colors.duration));
+                format!(
+                    " {}took {}{}",
+                    duration_color,
+                    format_duration(millis),
+                    reset
+                )
+            }
+        },
+        _ => String::new(),
+    };
+
+    // Only show the exit status when the previous command failed
+    let status_info = match format_status(
+        context.status,
+        &context.pipestatus,
+        &config.status.pipestatus_format,
+    ) {
+        Some(code) => match style {
+            "emoji" => format!(" {} {}", config.status.symbol, code),
+            _ => {
+                let status_color = shell_color(&color_map(&config.colors.status));
+                format!(
+                    " {}{} {}{}",
+                    status_color, config.status.symbol, code, reset
+                )
+            }
+        },
+        None => String::new(),
+    };
+
+    // Details about the previous command share the same spot in every style
+    let last_command_info = format!("{}{}", duration_info, status_info);
+
+    // Vi mode users get an indicator and a prompt character colored by mode
+    let (keymap_info, prompt_char_fmt) = match context.vi_mode {
+        Some(vi_mode) => {
+            let (symbol, color_name) = match vi_mode {
+                ViMode::Normal => (&config.keymap.normal_symbol, &config.colors.keymap_normal),
+                ViMode::Insert => (&config.keymap.insert_symbol, &config.colors.keymap_insert),
+                ViMode::Visual => (&config.keymap.visual_symbol, &config.colors.keymap_visual),
+                ViMode::Replace => (&config.keymap.replace_symbol, &config.colors.keymap_replace),
+            };
+            let mode_color = shell_color(&color_map(color_name));
+            (
+                format!(" {}{}{}", mode_color, symbol, reset),
+                format!("{}{}{}", mode_color, prompt_char, reset),
+            )
+        }
+        None => match color_map(prompt_char_color_name).as_str() {
+            "" => (String::new(), prompt_char.to_string()),
+            ansi_code => (
+                String::new(),
+                format!("{}{}{}", shell_color(ansi_code), prompt_char, reset),
+            ),
+        },
+    };
+    let last_command_info = format!("{}{}", last_command_info, keymap_info);
+
+    // Lay the segments out with the configured template or the style's preset.
+    // The emoji style shows plain text, the others colored segments.
+    let emoji = style == "emoji";
+    let segment_value = |name: &str| -> Option<String> {
+        let value = match name {
+            _ if !segment_enabled(name) => String::new(),
+            "time" if emoji => current_time.clone(),
+            "time" => time_fmt.clone(),
+            "user" if emoji => format!("{}{}", user_icon, username),
+            "user" => username_fmt.clone(),
+            "host" if !show_hostname => String::new(),
+            "host" if emoji => hostname.clone(),
+            "host" => hostname_fmt.clone(),
+            "dir" if emoji => format!("{}{}", dir_icon, current_dir),
+            "dir" => dir_fmt.clone(),
+            "env" => env_info.trim_start().to_string(),
+            "git" => git_info.trim_start().to_string(),
+            "cmd" => last_command_info.trim_start().to_string(),
+            "char" if emoji => prompt_char.to_string(),
+            "char" => prompt_char_fmt.clone(),
+            "fill" => format::FILL.to_string(),
+            name => {
+                let plugin_context = segments::plugin::PluginContext {
+                    name,
+                    cwd: env::current_dir().unwrap_or_default(),
+                    shell: shell.name(),
+                    style,
+                    columns: context.columns,
+                    status: context.status,
+                    right: context.right,
+                    git: git_status.as_ref(),
+                    env,
+                };
+                let text = plugin_segment(
+                    &config.plugins,
+                    &plugin_context,
+                    &config.timeout_placeholder,
+                )?;
+                if no_color {
+                    format::wrap_escapes(&text, &|_| String::new())
+                } else {
+                    text
+                }
+            }
+        };
+        Some(value)
+    };
+    // Rendering again to fit the terminal reuses the values, so plugins run once
+    let values: RefCell<HashMap<String, Option<String>>> = RefCell::new(HashMap::new());
+    let lookup_without = |name: &str, dropped: &[&str]| -> Option<String> {
+        if dropped.contains(&name) {
+            return Some(match name {
+                "dir" if !segment_enabled(name) => String::new(),
+                "dir" if emoji => format!("{}{}", dir_icon, short_dir),
+                "dir" => short_dir_fmt.clone(),
+                _ => String::new(),
+            });
+        }
+        if let Some(value) = values.borrow().get(name) {
+            return value.clone();
+        }
+        let value = segment_value(name);
+        values.borrow_mut().insert(name.to_string(), value.clone());
+        value
+    };
+    // Previews of a style ignore the layout the user configured
+    let custom_format = !context.preview && !config.format.is_empty();
+    let segment_order = !context.preview && !config.segments.order.is_empty();
+    let template = if custom_format {
+        config.format.as_str()
+    } else if nerd_font && emoji {
+        icons::EMOJI_LAYOUT
+    } else if ascii && emoji {
+        format::preset("info")
+    } else {
+        format::preset(style)
+    };
+    let render = |dropped: &[&str]| {
+        let lookup = |name: &str| lookup_without(name, dropped);
+        if context.right {
+            format::render(&config.right_format, &lookup)
+        } else if !custom_format && segment_order {
+            format::render_order(&config.segments.order, &lookup)
+        } else {
+            format::render(template, &lookup)
+        }
+    };
+
+    // Low-priority segments make way before the prompt would wrap, when the
+    // width of the terminal is known
+    let columns = context.columns.or_else(|| terminal_columns(env));
+    let (prompt, dropped) = match columns {
+        Some(width) if config.truncation.max_width > 0.0 => {
+            let max_width = (width as f64 * config.truncation.max_width) as usize;
+            format::fit_width(max_width, &config.truncation.drop_order, &render)
+        }
+        _ => (render(&[]), Vec::new()),
+    };
+
+    // The same segments one by one, in the order the layout shows them
+    let names = if context.right {
+        format::variables(&config.right_format)
+    } else if !custom_format && segment_order {
+        let mut names = config.segments.order.clone();
+        names.push("char".to_string());
+        names
+    } else {
+        format::variables(template)
+    };
+    let segments = names
+        .iter()
+        .filter(|name| *name != "fill")
+        .filter_map(|name| {
+            let value = lookup_without(name, &dropped)?;
+            let text = format::wrap_escapes(&value, &|_| String::new());
+            let style = match name.as_str() {
+                "time" => &config.colors.time,
+                "user" => username_color_name,
+                "host" => hostname_color_name,
+                "dir" => &config.colors.directory,
+                "git" => &config.colors.git_branch,
+                "cmd" if failed => &config.colors.status,
+                "cmd" => &config.colors.duration,
+                "char" => prompt_char_color_name,
+                _ => "",
+            };
+            let metadata = match name.as_str() {
+                "user" => serde_json::json!({ "root": is_root }),
+                "host" => serde_json::json!({ "ssh": ssh_session }),
+                "dir" => serde_json::json!({ "path": env::current_dir().unwrap_or_default() }),
+                "git" => serde_json::to_value(&git_status).unwrap_or_default(),
+                "cmd" => serde_json::json!({
+                    "status": context.status,
+                    "pipestatus": context.pipestatus,
+                    "duration_ms": context.cmd_duration,
+                }),
+                _ => serde_json::Value::Null,
+            };
+            (!text.trim().is_empty()).then(|| PromptSegment {
+                name: name.clone(),
+                text: text.trim().to_string(),
+                style: style.to_string(),
+                metadata,
+            })
+        })
+        .collect();
+
+    // Fills can only be sized once the rest of the line is known
+    let prompt = if prompt.contains(format::FILL) {
+        let (fill_color, fill_reset) = match color_map(&config.colors.fill).as_str() {
+            _ if emoji => (String::new(), String::new()),
+            "" => (String::new(), String::new()),
+            ansi_code => (shell_color(ansi_code), reset.clone()),
+        };
+        format::expand_fill(
+            &prompt,
+            columns.unwrap_or(80),
+            &config.fill.symbol,
+            &fill_color,
+            &fill_reset,
+        )
+    } else {
+        prompt
+    };
+    // Directory and branch names end up in the prompt, so the shell must show
+    // them rather than expand them
+    let prompt = shell.escape_prompt(&prompt);
+    if context.right || context.preview || no_color {
+        return (prompt, segments);
+    }
+
+    // Control sequences for the terminal travel with the left prompt
+    let mut before = String::new();
+    let mut after = String::new();
+    if config.terminal.semantic_prompt {
+        before.push_str(&shell.non_printing(&terminal::prompt_start(context.status)));
+        after.push_str(&shell.non_printing(&terminal::prompt_end()));
+    }
+    if config.terminal.report_directory {
+        let current_dir = env::current_dir().unwrap_or_default();
+        before.push_str(&shell.non_printing(&terminal::working_directory(
+            &get_hostname(env),
+            &current_dir.display().to_string(),
+        )));
+    }
+    if !config.terminal.title.is_empty() {
+        let title = format::render(&config.terminal.title, &|name| match name {
+            "user" => Some(username.clone()),
+            "host" => Some(hostname.clone()),
+            "dir" => Some(current_dir.clone()),
+            _ => None,
+        });
+        before.push_str(&shell.non_printing(&terminal::title(&title)));
+    }
+    (format!("{}{}{}", before, prompt, after), segments)
+}
+
+/// Replace `symbol` when it is still at its default, so symbols the user
+/// picked are left alone
+fn swap_default(symbol: &mut String, default: &str, replacement: &str) {
+    if symbol == default {
+        *symbol = replacement.to_string();
+    }
+}
+
+/// Printed instead of a prompt that failed to render, so the shell stays usable
+const FALLBACK_PROMPT: &str = "$ ";
+
+/// Render the prompt, falling back to FALLBACK_PROMPT (or no right prompt) when
+/// anything in it panics
+fn render_prompt_or_fallback(args: &Args, env: &Env) -> String {
+    let fallback = if args.right { "" } else { FALLBACK_PROMPT };
+    or_fallback(|| render_prompt(args, env), fallback)
+}
+
+/// Run `render`, returning `fallback` if it panics. The panic is logged rather
+/// than printed, since a message on stderr would land in the middle of the prompt.
+fn or_fallback(render: impl FnOnce() -> String, fallback: &str) -> String {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|info| {
+        error!("rendering the prompt failed: {}", info)
+    }));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(render));
+    std::panic::set_hook(default_hook);
+    result.unwrap_or_else(|_| fallback.to_string())
+}
+
+/// The prompt, or its data as JSON, for the given command line
+fn render_prompt(args: &Args, env: &Env) -> String {
+    if args.json {
+        return format!("{}\n", generate_prompt_json(env));
+    }
+
+    // Only load config if needed for the shell information
+    let shell = args
+        .shell
+        .clone()
+        .unwrap_or_else(|| load_config_in(env).shell);
+    generate_prompt(
+        &prompt_style(args, env),
+        ShellKind::from_name(&shell),
+        &prompt_context(args, env.clone()),
+    )
+}
+
+/// The segments of the prompt as a JSON array, for `prompt --format json`
+fn prompt_segments_json(args: &Args) -> String {
+    let env = Env::default();
+    // Fish gets plain ANSI codes, with no shell markers to strip
+    let (_, segments) = generate_prompt_segments(
+        &prompt_style(args, &env),
+        ShellKind::Fish,
+        &prompt_context(args, env),
+    );
+    serde_json::to_string(&segments).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// The style from the arguments, or the config's when they leave it at the default
+fn prompt_style(args: &Args, env: &Env) -> String {
+    if args.style != "default" {
+        args.style.clone()
+    } else {
+        load_config_in(env).style
+    }
+}
+
+/// What the shell hook passed in about the previous command and the terminal
+fn prompt_context(args: &Args, env: Env) -> PromptContext {
+    PromptContext {
+        status: args.status,
+        cmd_duration: args.cmd_duration,
+        pipestatus: args
+            .pipestatus
+            .as_deref()
+            .map(parse_pipestatus)
+            .unwrap_or_default(),
+        vi_mode: args.keymap.as_deref().and_then(ViMode::from_keymap),
+        right: args.right,
+        columns: args.columns,
+        preview: false,
+        no_color: args.no_color,
+        env,
+    }
+}
+
+/// Each built-in style, then the configured layout when there is one, rendered
+/// in the current directory or from sample data
+fn style_previews(config: &Config, sample: bool) -> Vec<(String, String)> {
+    let render = |style: &str, preview: bool| {
+        if sample {
+            let mut config = config.clone();
+            config.style = style.to_string();
+            if preview {
+                config.format.clear();
+            }
+            return preview_prompt(&config);
+        }
+        let context = PromptContext {
+            preview,
+            ..PromptContext::default()
+        };
+        // Fish takes plain ANSI codes, which the terminal shows as is
+        generate_prompt(style, ShellKind::Fish, &context)
+    };
+    let mut previews: Vec<(String, String)> = format::STYLES
+        .iter()
+        .map(|style| (style.to_string(), render(style, true)))
+        .collect();
+    if !config.format.is_empty() || !config.segments.order.is_empty() {
+        previews.push(("custom".to_string(), render(&config.style, false)));
+    }
+    previews
+}
+
+/// Run the plugin for `$name`, or None when there is no such plugin. A plugin
+/// that fails or runs past `plugins.timeout_ms` shows nothing, one that runs
+/// into the prompt deadline shows `placeholder`.
+fn plugin_segment(
+    plugins: &PluginsConfig,
+    context: &segments::plugin::PluginContext,
+    placeholder: &str,
+) -> Option<String> {
+    let input = serde_json::to_vec(context).unwrap_or_default();
+    #[cfg(feature = "wasm-plugins")]
+    if let Some(text) = wasm_plugin_segment(context, &input, placeholder) {
+        return Some(text);
+    }
+    let path = segments::plugin::find(context.env, context.name, &plugins.dirs)?;
+    if past_deadline() {
+        return Some(placeholder.to_string());
+    }
+    let timeout = deadline_timeout(
+        (plugins.timeout_ms > 0).then(|| Duration::from_millis(plugins.timeout_ms)),
+    );
+    let output = output_with_input(
+        context.env.command(path).current_dir(&context.cwd),
+        Some(&input),
+        timeout,
+    );
+    // Killed at the prompt deadline rather than by its own timeout
+    if output.is_none() && past_deadline() {
+        return Some(placeholder.to_string());
+    }
+    let text = output
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    Some(text.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Run `<config dir>/plugins/<name>.wasm`, which is preferred over executables
+/// since it runs in-process. A module that traps or runs out of fuel shows nothing.
+#[cfg(feature = "wasm-plugins")]
+fn wasm_plugin_segment(
+    context: &segments::plugin::PluginContext,
+    input: &[u8],
+    placeholder: &str,
+) -> Option<String> {
+    let config_path = config_path_in(context.env)?;
+    let path = segments::wasm::find(context.name, config_path.parent()?)?;
+    if past_deadline() {
+        return Some(placeholder.to_string());
+    }
+    let text = segments::wasm::run(&path, &context.cwd, context.env, input).unwrap_or_else(|e| {
+        debug!("wasm plugin {} failed: {}", path.display(), e);
+        String::new()
+    });
+    Some(text.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Ask a running daemon for the prompt, sending along this shell's arguments,
+/// directory and environment
+#[cfg(unix)]
+fn query_daemon() -> Option<String> {
+    let request = daemon::Request {
+        args: env::args_os()
+            .skip(1)
+            .map(|arg| arg.into_string().ok())
+            .collect::<Option<_>>()?,
+        cwd: env::current_dir().ok()?,
+        env: env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+            .collect(),
+    };
+    daemon::query(&daemon::socket_path()?, &request)
+}
+
+/// Render a prompt in the daemon for the shell that sent `request`, in its
+/// directory and environment
+#[cfg(unix)]
+fn serve_prompt(request: daemon::Request) -> Option<String> {
+    let args =
+        Args::try_parse_from(std::iter::once("loco-pilot".to_string()).chain(request.args)).ok()?;
+    if args.command.is_some() || args.refresh_git_cache {
+        return None;
+    }
+    debug!("serving a prompt for {}", request.cwd.display());
+    env::set_current_dir(&request.cwd).ok()?;
+    *lock(&CONFIG_PATH_OVERRIDE) = args.config.clone();
+
+    reset_client_caches();
+    Some(render_prompt_or_fallback(
+        &args,
+        &Env::captured(request.env),
+    ))
+}
+
+/// One of the lookups behind the prompt, for `bench` and `explain`
+struct SegmentProbe<'a> {
+    /// Name of the lookup, e.g. "git" or "node"
+    name: &'static str,
+    /// Prompt variable the lookup feeds, e.g. "env" for "node"
+    variable: &'static str,
+    /// Why the config leaves it out, if it does
+    off: Option<&'static str>,
+    /// Text of the segment, or None when it doesn't apply here
+    lookup: Box<dyn Fn() -> Option<String> + 'a>,
+}
+
+impl<'a> SegmentProbe<'a> {
+    fn new(
+        name: &'static str,
+        variable: &'static str,
+        off: Option<&'static str>,
+        lookup: impl Fn() -> Option<String> + 'a,
+    ) -> Self {
+        SegmentProbe {
+            name,
+            variable,
+            off,
+            lookup: Box::new(lookup),
+        }
+    }
+}
+
+/// The lookups behind each segment, in prompt order
+fn segment_probes(config: &Config) -> Vec<SegmentProbe<'_>> {
+    // `explain` and `bench` run in the shell, so this process's environment is the shell's
+    let env = &Env::default();
+    let is_wsl = segments::wsl::is_wsl(env);
+    let in_multiplexer = segments::multiplexer::in_multiplexer(env);
+    let host_off = if config.hostname.ssh_only && !segments::ssh::is_ssh_session(env) {
+        Some("hostname.ssh_only is on outside SSH")
+    } else if in_multiplexer && config.tmux.hide_hostname {
+        Some("tmux.hide_hostname is on inside tmux/screen")
+    } else {
+        None
+    };
+    let node_ttl = Duration::from_secs(config.node.cache_ttl);
+
+    vec![
+        SegmentProbe::new("config", "", None, || {
+            let _ = load_config();
+            get_config_path().map(|path| path.display().to_string())
+        }),
+        SegmentProbe::new("time", "time", None, || {
+            Some(format_current_time(&config.time, &Env::default()))
+        }),
+        SegmentProbe::new("user", "user", None, || Some(get_username(&Env::default()))),
+        SegmentProbe::new("host", "host", host_off, || {
+            Some(get_hostname(&Env::default()))
+        }),
+        SegmentProbe::new("dir", "dir", None, move || {
+            Some(get_shortened_dir(
+                is_wsl && config.wsl.windows_paths,
+                &config.directory,
+                &Env::default(),
+            ))
+        }),
+        SegmentProbe::new(
+            "python",
+            "env",
+            (!config.python.show).then_some("python.show is off"),
+            || segments::python::virtualenv(&Env::default()),
+        ),
+        SegmentProbe::new(
+            "conda",
+            "env",
+            (!config.conda.show).then_some("conda.show is off"),
+            || segments::conda::environment(&Env::default(), config.conda.hide_base),
+        ),
+        SegmentProbe::new(
+            "node",
+            "env",
+            (!config.node.show).then_some("node.show is off"),
+            move || segments::node::version(&Env::default(), node_ttl),
+        ),
+        SegmentProbe::new(
+            "gcp",
+            "env",
+            (!config.gcp.show).then_some("gcp.show is off"),
+            || segments::gcp::context(&Env::default()).and_then(|context| context.project),
+        ),
+        SegmentProbe::new(
+            "nix",
+            "env",
+            (!config.nix.show).then_some("nix.show is off"),
+            || segments::nix::shell(&Env::default()),
+        ),
+        SegmentProbe::new(
+            "load",
+            "env",
+            (!config.load.show).then_some("load.show is off"),
+            || segments::load::load_average(&Env::default()).map(|load| format!("{:.2}", load)),
+        ),
+        SegmentProbe::new(
+            "container",
+            "env",
+            (!config.container.show).then_some("container.show is off"),
+            || segments::container::detect(&Env::default()).map(|container| container.engine),
+        ),
+        SegmentProbe::new(
+            "wsl",
+            "env",
+            (!config.wsl.show).then_some("wsl.show is off"),
+            move || is_wsl.then(|| segments::wsl::distro_name(&Env::default())),
+        ),
+        SegmentProbe::new(
+            "tmux",
+            "env",
+            (!config.tmux.show).then_some("tmux.show is off"),
+            move || {
+                in_multiplexer
+                    .then(|| segments::multiplexer::session_name(&Env::default()))
+                    .flatten()
+            },
+        ),
+        SegmentProbe::new(
+            "git",
+            "git",
+            (!config.show_git).then_some("show_git is off"),
+            || get_git_info(&config.git, &Env::default()).map(|status| status.branch),
+        ),
+    ]
+}
+
+/// How one lookup fared when `explain` rendered the prompt
+#[derive(Debug, Serialize)]
+struct SegmentReport {
+    name: &'static str,
+    shown: bool,
+    /// Why the segment was hidden, or a note on how it was shown
+    reason: Option<String>,
+    value: Option<String>,
+    /// Time the lookup took, absent when it didn't run
+    millis: Option<f64>,
+}
+
+/// Check each lookup the way the prompt would, timing the ones that run
+fn explain_segments(config: &Config, layout: &str) -> Vec<SegmentReport> {
+    segment_probes(config)
+        .iter()
+        .map(|probe| {
+            let hidden = |reason: &str| SegmentReport {
+                name: probe.name,
+                shown: false,
+                reason: Some(reason.to_string()),
+                value: None,
+                millis: None,
+            };
+            if !probe.variable.is_empty() {
+                if segment_disabled(config, probe.variable) {
+                    return hidden("listed in segments.disabled");
+                }
+                if !layout.contains(&format!("${}", probe.variable)) {
+                    return hidden("not in the prompt layout");
+                }
+            }
+            if let Some(off) = probe.off {
+                return hidden(off);
+            }
+
+            reset_process_caches();
+            let start = Instant::now();
+            let value = (probe.lookup)();
+            let elapsed = start.elapsed();
+            let timeout = Duration::from_millis(config.git.timeout_ms);
+            let reason = match &value {
+                None => Some("doesn't apply here".to_string()),
+                Some(_) if probe.name == "git" && !timeout.is_zero() && elapsed >= timeout => {
+                    Some("git status hit git.timeout_ms, so only the branch is shown".to_string())
+                }
+                Some(_) => None,
+            };
+            SegmentReport {
+                name: probe.name,
+                shown: value.is_some(),
+                reason,
+                value,
+                millis: Some(elapsed.as_secs_f64() * 1000.0),
+            }
+        })
+        .collect()
+}
+
+/// Render the prompt once and report each segment, as a table or JSON. Caches
+/// are left out so every lookup shows its real cost.
+fn explain_prompt(json: bool) {
+    disk_cache::disable();
+    let config = load_config();
+    let layout = if !config.format.is_empty() {
+        config.format.clone()
+    } else if !config.segments.order.is_empty() {
+        config
+            .segments
+            .order
+            .iter()
+            .map(|name| format!("${}", name))
+            .collect()
+    } else {
+        format::preset(&config.style).to_string()
+    };
+
+    reset_process_caches();
+    let start = Instant::now();
+    let _ = generate_prompt(
+        &config.style,
+        ShellKind::from_name(&config.shell),
+        &PromptContext::default(),
+    );
+    let total = start.elapsed();
+    let segments = explain_segments(&config, &layout);
+
+    if json {
+        let report = serde_json::json!({
+            "total_ms": total.as_secs_f64() * 1000.0,
+            "segments": segments,
+        });
+        match serde_json::to_string_pretty(&report) {
+            Ok(report) => println!("{}", report),
+            Err(e) => eprintln!("Failed to generate report: {}", e),
+        }
+        return;
+    }
+
+    println!("{:<10} {:>9}  status", "segment", "time");
+    for segment in &segments {
+        let time = segment
+            .millis
+            .map(|millis| format!("{:.2} ms", millis))
+            .unwrap_or_default();
+        let status = match (segment.shown, &segment.value, &segment.reason) {
+            (true, Some(value), Some(reason)) => format!("shown: {} ({})", value, reason),
+            (true, Some(value), None) => format!("shown: {}", value),
+            (_, _, Some(reason)) => format!("hidden: {}", reason),
+            _ => String::new(),
+        };
+        println!("{:<10} {:>9}  {}", segment.name, time, status);
+    }
+    println!();
+    println!("Whole prompt rendered in {}", format_latency(total));
+}
+
+/// How one `doctor` check came out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Health {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// The result of one `doctor` check, with a suggested fix when it isn't ok
+#[derive(Debug)]
+struct Diagnosis {
+    name: &'static str,
+    health: Health,
+    detail: String,
+    fix: Option<String>,
+}
+
+impl Diagnosis {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Diagnosis {
+            name,
+            health: Health::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        health: Health,
+        detail: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Diagnosis {
+            name,
+            health,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Oldest git with `git status --porcelain=v2`
+const MIN_GIT_VERSION: (u32, u32) = (2, 11);
+
+/// The major and minor version from `git --version` output, e.g. "git version 2.43.0"
+fn parse_git_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.split_whitespace().nth(2)?;
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+    Some((parts.next()??, parts.next()??))
+}
+
+/// Check that git is installed and new enough, given the output of `git --version`
+fn check_git(version_output: Option<&str>, backend: &str) -> Diagnosis {
+    let Some(output) = version_output else {
+        // The gix backend reads repositories itself
+        let health = if backend == "gix" {
+            Health::Warn
+        } else {
+            Health::Fail
+        };
+        return Diagnosis::problem(
+            "git",
+            health,
+            "git isn't on PATH",
+            "install git, or set git.backend to \"gix\" to read repositories without it",
+        );
+    };
+    let output = output.trim();
+    match parse_git_version(output) {
+        Some(version) if version < MIN_GIT_VERSION => Diagnosis::problem(
+            "git",
+            Health::Warn,
+            output,
+            format!(
+                "upgrade to git {}.{} or newer for file counts, or set git.backend to \"gix\"",
+                MIN_GIT_VERSION.0, MIN_GIT_VERSION.1
+            ),
+        ),
+        _ => Diagnosis::ok("git", output),
+    }
+}
+
+/// Check the color support TERM and COLORTERM advertise
+fn check_terminal_colors(term: Option<&str>, colorterm: Option<&str>, no_color: bool) -> Diagnosis {
+    if no_color {
+        return Diagnosis::ok("colors", "turned off by NO_COLOR or no_color");
+    }
+    match term.unwrap_or_default() {
+        "" | "dumb" => Diagnosis::problem(
+            "colors",
+            Health::Warn,
+            format!(
+                "TERM is {:?}, which can't show colors",
+                term.unwrap_or_default()
+            ),
+            "set TERM to your terminal's type, e.g. xterm-256color, or run `loco-pilot config no_color true`",
+        ),
+        _ if matches!(colorterm, Some("truecolor" | "24bit")) => {
+            Diagnosis::ok("colors", "24-bit color")
+        }
+        term if term.contains("256color") => Diagnosis::ok("colors", "256 colors"),
+        term => Diagnosis::problem(
+            "colors",
+            Health::Warn,
+            format!("TERM is {:?}, which only promises 8 colors", term),
+            "palette and hex colors may look off; set TERM to e.g. xterm-256color if your terminal supports it",
+        ),
+    }
+}
+
+/// Check the startup files of `shell` under `home` for the setup snippet
+fn check_shell_hook(shell: Option<ShellKind>, home: &Path) -> Diagnosis {
+    let Some(shell) = shell else {
+        return Diagnosis::problem(
+            "shell hook",
+            Health::Warn,
+            "couldn't tell which shell this is",
+            "run `loco-pilot doctor` from your interactive shell",
+        );
+    };
+    let files = shell.rc_files();
+    let hooked = files.iter().find(|file| {
+        fs::read_to_string(home.join(file)).is_ok_and(|contents| contents.contains("loco-pilot"))
+    });
+    match hooked {
+        Some(file) => Diagnosis::ok(
+            "shell hook",
+            format!("set up in ~/{} for {}", file, shell.name()),
+        ),
+        None => Diagnosis::problem(
+            "shell hook",
+            Health::Fail,
+            format!("no loco-pilot setup in ~/{}", files[0]),
+            format!("add `{}` to ~/{}", shell.setup_line(), files[0]),
+        ),
+    }
+}
+
+/// Check that the config file, if there is one, loads without problems
+fn check_config() -> Diagnosis {
+    let Some(path) = get_config_path() else {
+        return Diagnosis::problem(
+            "config",
+            Health::Warn,
+            "couldn't determine the config directory",
+            "set HOME, or pass --config",
+        );
+    };
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Diagnosis::ok(
+            "config",
+            format!("no file at {}, using defaults", path.display()),
+        );
+    };
+    match validate_config(&content, ConfigFormat::from_path(&path)).as_slice() {
+        [] => Diagnosis::ok("config", format!("{} is valid", path.display())),
+        [problem, ..] => Diagnosis::problem(
+            "config",
+            Health::Fail,
+            // TOML errors quote the offending line, so keep the location and the message
+            format!(
+                "{}: {}",
+                path.display(),
+                match (problem.lines().next(), problem.lines().last()) {
+                    (Some(first), Some(last)) if first != last => format!("{}: {}", first, last),
+                    _ => problem.clone(),
+                }
+            ),
+            "run `loco-pilot config validate` for every problem",
+        ),
+    }
+}
+
+/// Check that the locale can show the prompt's symbols
+fn check_locale() -> Diagnosis {
+    if utf8_locale(&Env::default()) {
+        return Diagnosis::ok("locale", "UTF-8");
+    }
+    Diagnosis::problem(
+        "locale",
+        Health::Warn,
+        "the locale isn't UTF-8, so ASCII-only mode is on",
+        "set LANG to a UTF-8 locale such as en_US.UTF-8 to get the full symbols",
+    )
+}
+
+/// Check that copy commands have a way to reach the clipboard
+fn check_clipboard(clipboard_config: &ClipboardConfig) -> Diagnosis {
+    if clipboard_config.backend == "osc52" {
+        return Diagnosis::ok("clipboard", "OSC 52, through the terminal");
+    }
+    let installed = clipboard_tools()
+        .into_iter()
+        .find(|(program, _)| find_in_path(&Env::default(), program).is_some());
+    match installed {
+        Some((program, _)) => Diagnosis::ok("clipboard", program),
+        None if segments::ssh::is_ssh_session(&Env::default()) => {
+            Diagnosis::ok("clipboard", "OSC 52, through the terminal over SSH")
+        }
+        None => Diagnosis::problem(
+            "clipboard",
+            Health::Warn,
+            "no clipboard tool found",
+            "install wl-clipboard (Wayland) or xclip (X11), or set clipboard.backend to \"osc52\"",
+        ),
+    }
+}
+
+/// Run every `doctor` check and print the results, exiting with 1 on failures
+fn run_doctor() {
+    let config = load_config();
+    let git_version = Command::new("git")
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned());
+    // zsh reads its startup files from ZDOTDIR when it's set
+    let shell = ShellKind::detect();
+    let home = match shell {
+        Some(ShellKind::Zsh) => env::var_os("ZDOTDIR").map(PathBuf::from),
+        _ => None,
+    }
+    .or_else(dirs::home_dir)
+    .unwrap_or_default();
+
+    let diagnoses = [
+        check_git(git_version.as_deref(), &config.git.backend),
+        check_terminal_colors(
+            env::var("TERM").ok().as_deref(),
+            env::var("COLORTERM").ok().as_deref(),
+            colors_disabled(false, &config, &Env::default()),
+        ),
+        check_shell_hook(shell, &home),
+        check_config(),
+        check_locale(),
+        check_clipboard(&config.clipboard),
+    ];
+    for diagnosis in &diagnoses {
+        let label = match diagnosis.health {
+            Health::Ok => "[ok]  ",
+            Health::Warn => "[warn]",
+            Health::Fail => "[fail]",
+        };
+        println!("{} {:<11} {}", label, diagnosis.name, diagnosis.detail);
+        if let Some(fix) = &diagnosis.fix {
+            println!("       {:<11} fix: {}", "", fix);
+        }
+    }
+    if diagnoses
+        .iter()
+        .any(|diagnosis| diagnosis.health == Health::Fail)
+    {
+        std::process::exit(1);
+    }
+}
+
+/// Whether `variable` is listed in `segments.disabled`
+fn segment_disabled(config: &Config, variable: &str) -> bool {
+    config
+        .segments
+        .disabled
+        .iter()
+        .any(|disabled| disabled == variable)
+}
+
+/// Render `iterations` prompts in fresh processes, the way a shell does, then
+/// time the lookups behind each segment in this one
+fn run_bench(iterations: usize, cached: bool) {
+    let iterations = iterations.max(1);
+    let Ok(exe) = env::current_exe() else {
+        eprintln!("Could not find the loco-pilot executable");
+        std::process::exit(1);
+    };
+    if !cached {
+        disk_cache::disable();
+    }
+    let config_path = lock(&CONFIG_PATH_OVERRIDE).clone();
+
+    let mut samples = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let mut command = Command::new(&exe);
+        if let Some(config_path) = &config_path {
+            command.arg("--config").arg(config_path);
+        }
+        if !cached {
+            command.env(disk_cache::DISABLE_VAR, "1");
+        }
+        let start = Instant::now();
+        let rendered = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !rendered {
+            eprintln!("The prompt failed to render");
+            std::process::exit(1);
+        }
+        samples.push(start.elapsed());
+    }
+    samples.sort();
+
+    let caches = if cached {
+        "with caches"
+    } else {
+        "without caches"
+    };
+    println!("{} prompts, {}:", iterations, caches);
+    println!("  min     {}", format_latency(samples[0]));
+    println!("  median  {}", format_latency(percentile(&samples, 50)));
+    println!("  p95     {}", format_latency(percentile(&samples, 95)));
+
+    // Each lookup starts from cold in-process caches, like a new prompt
+    let config = load_config();
+    let probes: Vec<SegmentProbe> = segment_probes(&config)
+        .into_iter()
+        .filter(|probe| probe.off.is_none() && !segment_disabled(&config, probe.variable))
+        .collect();
+
+    println!();
+    println!("Segment lookups, median:");
+    for probe in &probes {
+        let mut samples: Vec<Duration> = (0..iterations)
+            .map(|_| {
+                reset_process_caches();
+                let start = Instant::now();
+                let _ = (probe.lookup)();
+                start.elapsed()
+            })
+            .collect();
+        samples.sort();
+        println!(
+            "  {:<10} {}",
+            probe.name,
+            format_latency(percentile(&samples, 50))
+        );
+    }
+}
+
+/// Forget what this process cached, so the next lookups start cold
+fn reset_process_caches() {
+    *lock(&CONFIG_CACHE) = None;
+    reset_client_caches();
+}
+
+/// Forget what was looked up for the last shell the daemon served, since the
+/// next one can have another environment. The config cache is keyed on the
+/// file, so it's kept.
+fn reset_client_caches() {
+    *lock(&PATH_CACHE) = (None, None, None);
+    *lock(&USERNAME_CACHE) = None;
+    segments::gcp::reset_cache();
+}
+
+/// The nearest-rank percentile of sorted samples
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// A latency in milliseconds with two decimals
+fn format_latency(latency: Duration) -> String {
+    format!("{:.2} ms", latency.as_secs_f64() * 1000.0)
+}
+
+/// Swap every symbol still at its default for its Nerd Font glyph
+fn apply_nerd_font_icons(config: &mut Config) {
+    let defaults = Config::default();
+    let git = &mut config.git;
+    swap_default(
+        &mut git.branch_symbol,
+        &defaults.git.branch_symbol,
+        icons::BRANCH,
+    );
+    swap_default(
+        &mut git.emoji_branch_symbol,
+        &defaults.git.emoji_branch_symbol,
+        icons::BRANCH,
+    );
+    swap_default(&mut git.tag_symbol, &defaults.git.tag_symbol, icons::TAG);
+    swap_default(
+        &mut git.stash_symbol,
+        &defaults.git.stash_symbol,
+        icons::STASH,
+    );
+    swap_default(
+        &mut git.ahead_symbol,
+        &defaults.git.ahead_symbol,
+        icons::AHEAD,
+    );
+    swap_default(
+        &mut git.behind_symbol,
+        &defaults.git.behind_symbol,
+        icons::BEHIND,
+    );
+    swap_default(
+        &mut git.fetch_age_symbol,
+        &defaults.git.fetch_age_symbol,
+        icons::FETCH_AGE,
+    );
+    swap_default(
+        &mut git.email_symbol,
+        &defaults.git.email_symbol,
+        icons::EMAIL,
+    );
+    swap_default(
+        &mut git.commit_age_symbol,
+        &defaults.git.commit_age_symbol,
+        icons::CLOCK,
+    );
+    swap_default(
+        &mut git.remote_default_symbol,
+        &defaults.git.remote_default_symbol,
+        icons::HOME,
+    );
+    swap_default(
+        &mut config.status.symbol,
+        &defaults.status.symbol,
+        icons::ERROR,
+    );
+    swap_default(
+        &mut config.python.symbol,
+        &defaults.python.symbol,
+        icons::PYTHON,
+    );
+    swap_default(
+        &mut config.conda.symbol,
+        &defaults.conda.symbol,
+        icons::PYTHON,
+    );
+    swap_default(&mut config.node.symbol, &defaults.node.symbol, icons::NODE);
+    swap_default(&mut config.gcp.symbol, &defaults.gcp.symbol, icons::GCP);
+    swap_default(&mut config.nix.symbol, &defaults.nix.symbol, icons::NIX);
+    swap_default(&mut config.wsl.symbol, &defaults.wsl.symbol, icons::LINUX);
+    swap_default(
+        &mut config.container.symbol,
+        &defaults.container.symbol,
+        icons::CONTAINER,
+    );
+    swap_default(&mut config.load.symbol, &defaults.load.symbol, icons::LOAD);
+    swap_default(&mut config.tmux.symbol, &defaults.tmux.symbol, icons::TMUX);
+    let character = &mut config.character;
+    swap_default(
+        &mut character.emoji_success_symbol,
+        &defaults.character.emoji_success_symbol,
+        icons::PROMPT,
+    );
+    swap_default(
+        &mut character.emoji_error_symbol,
+        &defaults.character.emoji_error_symbol,
+        icons::PROMPT,
+    );
+}
+
+/// Swap every symbol still at its default for an ASCII equivalent
+fn apply_ascii_symbols(config: &mut Config) {
+    let defaults = Config::default();
+    let git = &mut config.git;
+    swap_default(
+        &mut git.emoji_branch_symbol,
+        &defaults.git.emoji_branch_symbol,
+        "",
+    );
+    swap_default(&mut git.tag_symbol, &defaults.git.tag_symbol, "tag:");
+    swap_default(&mut git.stash_symbol, &defaults.git.stash_symbol, "s");
+    swap_default(&mut git.ahead_symbol, &defaults.git.ahead_symbol, "^");
+    swap_default(&mut git.behind_symbol, &defaults.git.behind_symbol, "v");
+    swap_default(
+        &mut git.fetch_age_symbol,
+        &defaults.git.fetch_age_symbol,
+        "fetched ",
+    );
+    swap_default(&mut git.email_symbol, &defaults.git.email_symbol, "");
+    swap_default(
+        &mut git.commit_age_symbol,
+        &defaults.git.commit_age_symbol,
+        "",
+    );
+    swap_default(
+        &mut git.truncate_symbol,
+        &defaults.git.truncate_symbol,
+        "...",
+    );
+    swap_default(
+        &mut git.remote_default_symbol,
+        &defaults.git.remote_default_symbol,
+        "git",
+    );
+    if git.remote_symbols == defaults.git.remote_symbols {
+        git.remote_symbols = BTreeMap::from([
+            ("bitbucket.org".to_string(), "bb".to_string()),
+            ("github.com".to_string(), "gh".to_string()),
+            ("gitlab.com".to_string(), "gl".to_string()),
+        ]);
+    }
+    swap_default(
+        &mut config.timeout_placeholder,
+        &defaults.timeout_placeholder,
+        "...",
+    );
+    swap_default(&mut config.status.symbol, &defaults.status.symbol, "x");
+    swap_default(&mut config.python.symbol, &defaults.python.symbol, "py:");
+    swap_default(&mut config.conda.symbol, &defaults.conda.symbol, "conda:");
+    swap_default(&mut config.node.symbol, &defaults.node.symbol, "node:");
+    swap_default(&mut config.gcp.symbol, &defaults.gcp.symbol, "gcp:");
+    swap_default(&mut config.nix.symbol, &defaults.nix.symbol, "nix:");
+    swap_default(&mut config.wsl.symbol, &defaults.wsl.symbol, "wsl:");
+    swap_default(
+        &mut config.container.symbol,
+        &defaults.container.symbol,
+        "ctr:",
+    );
+    swap_default(&mut config.tmux.symbol, &defaults.tmux.symbol, "tmux:");
+    let character = &mut config.character;
+    swap_default(
+        &mut character.emoji_success_symbol,
+        &defaults.character.emoji_success_symbol,
+        ">",
+    );
+    swap_default(
+        &mut character.emoji_error_symbol,
+        &defaults.character.emoji_error_symbol,
+        ">",
+    );
+}
+
+/// Whether the locale's character set is UTF-8. Unix without any locale set
+/// falls back to POSIX, which is ASCII; Windows doesn't use these variables.
+fn utf8_locale(env: &Env) -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env.var(name))
+        .find(|value| !value.is_empty());
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_uppercase();
+            locale.contains("UTF-8") || locale.contains("UTF8")
+        }
+        None => cfg!(windows),
+    }
+}
+
+/// Width of the terminal from COLUMNS, when the shell exports it. The terminal
+/// itself isn't asked: that would cost a process on every prompt, and a
+/// daemon's terminal isn't the shell's.
+fn terminal_columns(env: &Env) -> Option<usize> {
+    env.var("COLUMNS")
+        .and_then(|columns| columns.parse().ok())
+        .filter(|columns| *columns > 0)
+}
+
+/// Raw prompt data for shells that format the prompt themselves
+#[derive(Debug, Serialize)]
+struct PromptData {
+    time: String,
+    username: String,
+    hostname: String,
+    directory: String,
+    git: Option<GitStatus>,
+}
+
+/// Generate the prompt data as a JSON object, e.g. for a nushell closure
+fn generate_prompt_json(env: &Env) -> String {
+    let config = load_config_in(env);
+
+    let data = PromptData {
+        time: format_current_time(&config.time, env),
+        username: get_username(env),
+        hostname: get_hostname(env),
+        directory: get_shortened_dir(
+            config.wsl.windows_paths && segments::wsl::is_wsl(env),
+            &config.directory,
+            env,
+        ),
+        git: if config.show_git {
+            get_git_info(&config.git, env)
+        } else {
+            None
+        },
+    };
+
+    serde_json::to_string(&data).unwrap_or_else(|_| "{}".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::{bash_color, zsh_color};
+    use crate::test_utils::tests::create_mock_config;
+
+    #[test]
+    fn test_config_default() {
+        // Test default configuration values
+        let config = Config::default();
+        assert_eq!(config.style, "default");
+        assert_eq!(config.show_git, true);
+        assert_eq!(config.colors.username, "green");
+        assert_eq!(config.colors.hostname, "yellow");
+        assert_eq!(config.colors.directory, "cyan");
+        assert_eq!(config.colors.git_branch, "green");
+        assert_eq!(config.colors.git_dirty, "red");
+        assert_eq!(config.colors.time, "blue");
+        assert_eq!(config.colors.status, "red");
+        assert_eq!(config.status.symbol, "✘");
+        assert_eq!(config.colors.duration, "yellow");
+        assert_eq!(config.duration.threshold, 2000);
+    }
+
+    #[test]
+    fn test_format_status() {
+        assert_eq!(format_status(Some(0), &[], "full"), None);
+        assert_eq!(
+            format_status(Some(127), &[], "full"),
+            Some("127".to_string())
+        );
+        assert_eq!(
+            format_status(Some(0), &parse_pipestatus("0 1 0"), "full"),
+            Some("0|1|0".to_string())
+        );
+        assert_eq!(
+            format_status(Some(0), &parse_pipestatus("2 1 0"), "collapse"),
+            Some("1".to_string())
+        );
+        assert_eq!(
+            format_status(Some(0), &parse_pipestatus("0 0"), "full"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_venv_name() {
+        use crate::segments::python::venv_name;
+        assert_eq!(
+            venv_name("/home/user/.virtualenvs/data-tools"),
+            "data-tools"
+        );
+        assert_eq!(venv_name("/home/user/projects/api/.venv"), "api");
+        assert_eq!(venv_name("/home/user/projects/api/venv/"), "api");
+    }
+
+    #[test]
+    fn test_conda_env_display_name() {
+        use crate::segments::conda::env_display_name;
+        assert_eq!(env_display_name("base"), "base");
+        assert_eq!(env_display_name("/opt/conda/envs/ml/"), "ml");
+    }
+
+    #[test]
+    fn test_parse_file_counts() {
+        let status_output = "# branch.oid 1234567890\n\
+# branch.head main\n\
+1 M. N... 100644 100644 100644 aaaa bbbb src/staged.rs\n\
+1 .M N... 100644 100644 100644 aaaa bbbb src/modified.rs\n\
+1 MM N... 100644 100644 100644 aaaa bbbb src/both.rs\n\
+1 .D N... 100644 100644 000000 aaaa aaaa src/deleted.rs\n\
+2 R. N... 100644 100644 100644 aaaa aaaa R100 new.rs\told.rs\n\
+? notes.txt\n\
+? scratch/\n";
+        assert_eq!(
+            parse_file_counts(status_output),
+            FileCounts {
+                staged: 3,
+                modified: 2,
+                deleted: 1,
+                untracked: 2,
+            }
+        );
+        assert_eq!(
+            parse_file_counts("# branch.head main\n"),
+            FileCounts::default()
+        );
+    }
+
+    #[test]
+    fn test_remote_host() {
+        let git_config = "[core]\n\tbare = false\n[remote \"upstream\"]\n\turl = https://gitlab.com/a/b.git\n[remote \"origin\"]\n\turl = git@github.com:asudarsanan/loco-pilot.git\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n";
+        assert_eq!(
+            remote_url(git_config, "origin").as_deref(),
+            Some("git@github.com:asudarsanan/loco-pilot.git")
+        );
+        assert_eq!(remote_url(git_config, "missing"), None);
+
+        assert_eq!(
+            remote_host("git@github.com:asudarsanan/loco-pilot.git").as_deref(),
+            Some("github.com")
+        );
+        assert_eq!(
+            remote_host("https://user@GitLab.com/group/project.git").as_deref(),
+            Some("gitlab.com")
+        );
+        assert_eq!(
+            remote_host("ssh://git@git.example.com:2222/team/repo.git").as_deref(),
+            Some("git.example.com")
+        );
+        assert_eq!(remote_host("/srv/git/repo.git"), None);
+
+        assert_eq!(
+            browsable_url("git@github.com:asudarsanan/loco-pilot.git"),
+            "https://github.com/asudarsanan/loco-pilot"
+        );
+        assert_eq!(
+            browsable_url("ssh://git@git.example.com:2222/team/repo.git"),
+            "https://git.example.com/team/repo"
+        );
+        assert_eq!(
+            browsable_url("https://user@GitLab.com/group/project.git"),
+            "https://gitlab.com/group/project"
+        );
+        assert_eq!(browsable_url("/srv/git/repo.git"), "/srv/git/repo.git");
+
+        assert_eq!(
+            forge_url("https://github.com/a/b", "main", Some("src/main.rs")),
+            "https://github.com/a/b/blob/main/src/main.rs"
+        );
+        assert_eq!(
+            forge_url("https://gitlab.com/a/b", "feature", None),
+            "https://gitlab.com/a/b/-/tree/feature"
+        );
+        assert_eq!(
+            forge_url("https://bitbucket.org/a/b", "main", Some("README.md")),
+            "https://bitbucket.org/a/b/src/main/README.md"
+        );
+    }
+
+    #[test]
+    fn test_head_tag() {
+        let repo_dir = env::temp_dir().join(format!("loco-pilot-tag-{}", std::process::id()));
+        fs::create_dir_all(&repo_dir).unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&repo_dir)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        };
+
+        if git(&["init", "-q"]) && git(&["commit", "-q", "--allow-empty", "-m", "initial"]) {
+            assert_eq!(head_tag(&repo_dir, None, &Env::default()), None);
+            assert!(git(&["tag", "v2.1.0"]));
+            assert_eq!(
+                head_tag(&repo_dir, None, &Env::default()).as_deref(),
+                Some("v2.1.0")
+            );
+        }
+
+        let _ = fs::remove_dir_all(&repo_dir);
+    }
+
+    #[test]
+    fn test_gix_backend_matches_git() {
+        let repo_dir = env::temp_dir().join(format!("loco-pilot-gix-{}", std::process::id()));
+        fs::create_dir_all(&repo_dir).unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&repo_dir)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        };
+
+        assert!(git(&["init", "-q", "-b", "main"]));
+        fs::write(repo_dir.join("tracked.txt"), "one\n").unwrap();
+        fs::write(repo_dir.join("removed.txt"), "two\n").unwrap();
+        assert!(git(&["add", "."]));
+        assert!(git(&["commit", "-q", "-m", "initial"]));
+        assert!(git(&["branch", "upstream"]));
+        assert!(git(&["branch", "--set-upstream-to=upstream"]));
+        assert!(git(&["commit", "-q", "--allow-empty", "-m", "ahead"]));
+        assert!(git(&["checkout", "-q", "upstream"]));
+        assert!(git(&["commit", "-q", "--allow-empty", "-m", "behind"]));
+        assert!(git(&["checkout", "-q", "main"]));
+
+        fs::write(repo_dir.join("tracked.txt"), "changed\n").unwrap();
+        fs::remove_file(repo_dir.join("removed.txt")).unwrap();
+        fs::write(repo_dir.join("staged.txt"), "new\n").unwrap();
+        assert!(git(&["add", "staged.txt"]));
+        fs::write(repo_dir.join("untracked.txt"), "?\n").unwrap();
+
+        let expected = git_cli_status(&repo_dir, None, &Env::default()).unwrap();
+        assert_eq!(expected.branch, "main");
+        assert_eq!(expected.upstream.as_deref(), Some("upstream"));
+        assert_eq!((expected.ahead, expected.behind), (1, 1));
+        assert_eq!(gix_status(&repo_dir.join(".git")), Some(expected));
+
+        // Merging the upstream in leaves history below the merge base that
+        // only the local branch's first parent reaches
+        assert!(git(&["stash", "-q", "-u"]));
+        assert!(git(&["merge", "-q", "--no-edit", "upstream"]));
+        assert!(git(&["checkout", "-q", "upstream"]));
+        assert!(git(&[
+            "commit",
+            "-q",
+            "--allow-empty",
+            "-m",
+            "behind again"
+        ]));
+        assert!(git(&["checkout", "-q", "main"]));
+        let expected = git_cli_status(&repo_dir, None, &Env::default()).unwrap();
+        assert_eq!((expected.ahead, expected.behind), (2, 1));
+        assert_eq!(gix_status(&repo_dir.join(".git")), Some(expected));
+
+        let _ = fs::remove_dir_all(&repo_dir);
+    }
+
+    #[test]
+    fn test_git_operation() {
+        let git_dir = env::temp_dir().join(format!("loco-pilot-operation-{}", std::process::id()));
+        fs::create_dir_all(&git_dir).unwrap();
+        assert_eq!(git_operation(&git_dir), None);
+
+        fs::write(git_dir.join("MERGE_HEAD"), "abc1234\n").unwrap();
+        assert_eq!(git_operation(&git_dir).as_deref(), Some("MERGE"));
+
+        let rebase_dir = git_dir.join("rebase-merge");
+        fs::create_dir_all(&rebase_dir).unwrap();
+        fs::write(rebase_dir.join("msgnum"), "2\n").unwrap();
+        fs::write(rebase_dir.join("end"), "5\n").unwrap();
+        assert_eq!(git_operation(&git_dir).as_deref(), Some("REBASE 2/5"));
+
+        let _ = fs::remove_dir_all(&git_dir);
+    }
+
+    #[test]
+    fn test_resolve_git_dir() {
+        let root = env::temp_dir().join(format!("loco-pilot-gitdir-{}", std::process::id()));
+        let main_git_dir = root.join("main").join(".git");
+        let worktree_git_dir = main_git_dir.join("worktrees").join("feature");
+        fs::create_dir_all(&worktree_git_dir).unwrap();
+        fs::create_dir_all(root.join("feature")).unwrap();
+        fs::write(worktree_git_dir.join("commondir"), "../..\n").unwrap();
+        fs::write(
+            root.join("feature").join(".git"),
+            "gitdir: ../main/.git/worktrees/feature\n",
+        )
+        .unwrap();
+
+        assert_eq!(resolve_git_dir(&main_git_dir), Some(main_git_dir.clone()));
+        let resolved = resolve_git_dir(&root.join("feature").join(".git")).unwrap();
+        assert_eq!(
+            resolved.canonicalize().unwrap(),
+            worktree_git_dir.canonicalize().unwrap()
+        );
+        assert_eq!(
+            common_git_dir(&resolved).canonicalize().unwrap(),
+            main_git_dir.canonicalize().unwrap()
+        );
+        assert_eq!(resolve_git_dir(&root.join("missing").join(".git")), None);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_discover_git_dir() {
+        let root = env::temp_dir().join(format!("loco-pilot-discover-{}", std::process::id()));
+        let nested = root.join("repo").join("src").join("segments");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(root.join("repo").join(".git")).unwrap();
+
+        assert_eq!(
+            discover_git_dir(&nested, &[]),
+            Some(root.join("repo").join(".git"))
+        );
+        assert_eq!(
+            discover_git_dir(&root.join("repo"), &[root.join("repo")]),
+            Some(root.join("repo").join(".git"))
+        );
+        assert_eq!(
+            discover_git_dir(&nested, &[root.join("repo").join("src")]),
+            None
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_substitute_path() {
+        let substitutions = BTreeMap::from([
+            (
+                "~/work/really/long/client-project".to_string(),
+                "client".to_string(),
+            ),
+            ("~/work".to_string(), "💼 work".to_string()),
+            ("/home/me/src/".to_string(), "src".to_string()),
+        ]);
+        let substitute = |path| substitute_path(path, &substitutions, "/home/me");
+
+        assert_eq!(
+            substitute("~/work/really/long/client-project/api").as_deref(),
+            Some("client/api")
+        );
+        assert_eq!(substitute("~/work/other").as_deref(), Some("💼 work/other"));
+        assert_eq!(substitute("~/work").as_deref(), Some("💼 work"));
+        assert_eq!(
+            substitute("~/src/loco-pilot").as_deref(),
+            Some("src/loco-pilot")
+        );
+        assert_eq!(substitute("~/workshop"), None);
+        assert_eq!(substitute("/etc"), None);
+    }
+
+    #[test]
+    fn test_repo_relative_dir() {
+        let root = env::temp_dir().join(format!("loco-pilot-repo-dir-{}", std::process::id()));
+        let nested = root.join("project").join("src").join("lib");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(root.join("project").join(".git")).unwrap();
+
+        assert_eq!(
+            repo_relative_dir(&nested, None, &[]),
+            Some(
+                Path::new("project")
+                    .join("src")
+                    .join("lib")
+                    .display()
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            repo_relative_dir(&root.join("project"), None, &[]).as_deref(),
+            Some("project")
+        );
+        // A repository at home leaves home paths alone
+        assert_eq!(
+            repo_relative_dir(&nested, Some(&root.join("project")), &[]),
+            None
+        );
+        assert_eq!(repo_relative_dir(&root, None, &[]), None);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_with_timeout() {
+        let output = output_with_timeout(
+            Command::new("sh").args(["-c", "echo done"]),
+            Some(Duration::from_secs(5)),
+        )
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+
+        let started = Instant::now();
+        assert!(
+            output_with_timeout(
+                Command::new("sh").args(["-c", "sleep 5"]),
+                Some(Duration::from_millis(50)),
+            )
+            .is_none()
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_or_fallback() {
+        assert_eq!(or_fallback(|| "ok> ".to_string(), FALLBACK_PROMPT), "ok> ");
+        assert_eq!(
+            or_fallback(|| panic!("segment blew up"), FALLBACK_PROMPT),
+            FALLBACK_PROMPT
+        );
+
+        // A lock poisoned by that kind of panic still hands out its value
+        let cache = Mutex::new(Some(1));
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = lock(&cache);
+            panic!("poison the lock");
+        });
+        assert!(cache.is_poisoned());
+        assert_eq!(*lock(&cache), Some(1));
+    }
+
+    #[test]
+    fn test_log_level() {
+        use tracing::level_filters::LevelFilter;
+
+        assert_eq!(logging::level(None, false), LevelFilter::OFF);
+        assert_eq!(logging::level(Some(""), false), LevelFilter::OFF);
+        assert_eq!(logging::level(None, true), LevelFilter::DEBUG);
+        assert_eq!(logging::level(Some("trace"), false), LevelFilter::TRACE);
+        assert_eq!(logging::level(Some("WARN"), false), LevelFilter::WARN);
+        // --verbose raises a quieter level to debug, but keeps trace
+        assert_eq!(logging::level(Some("warn"), true), LevelFilter::DEBUG);
+        assert_eq!(logging::level(Some("trace"), true), LevelFilter::TRACE);
+        assert_eq!(logging::level(Some("yes"), false), LevelFilter::DEBUG);
+    }
+
+    #[test]
+    fn test_bounded_timeout() {
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        assert_eq!(bounded_timeout(None, None, now), None);
+        assert_eq!(bounded_timeout(Some(second), None, now), Some(second));
+        // The prompt deadline shortens a segment's own timeout, or stands in for it
+        assert_eq!(
+            bounded_timeout(Some(second), Some(now + second / 4), now),
+            Some(second / 4)
+        );
+        assert_eq!(
+            bounded_timeout(Some(second / 4), Some(now + second), now),
+            Some(second / 4)
+        );
+        assert_eq!(bounded_timeout(None, Some(now + second), now), Some(second));
+        assert_eq!(
+            bounded_timeout(Some(second), Some(now), now + second),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_repo_path_matches() {
+        let patterns = vec!["/work/monorepo".to_string(), "/src/*/vendor/".to_string()];
+        assert!(repo_path_matches(Path::new("/work/monorepo"), &patterns));
+        assert!(repo_path_matches(
+            Path::new("/work/monorepo/services/api"),
+            &patterns
+        ));
+        assert!(repo_path_matches(
+            Path::new("/src/project/vendor/lib"),
+            &patterns
+        ));
+        assert!(!repo_path_matches(Path::new("/work/monorepo2"), &patterns));
+        assert!(!repo_path_matches(Path::new("/work"), &patterns));
+        assert!(!repo_path_matches(Path::new("/work/monorepo"), &[]));
+    }
+
+    #[test]
+    fn test_head_branch() {
+        let git_dir = env::temp_dir().join(format!("loco-pilot-head-{}", std::process::id()));
+        fs::create_dir_all(&git_dir).unwrap();
+
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/feature/login\n").unwrap();
+        assert_eq!(head_branch(&git_dir).unwrap().branch, "feature/login");
+
+        fs::write(
+            git_dir.join("HEAD"),
+            "0123456789abcdef0123456789abcdef01234567\n",
+        )
+        .unwrap();
+        assert_eq!(head_branch(&git_dir).unwrap().branch, "detached@0123456");
+
+        let _ = fs::remove_dir_all(&git_dir);
+    }
+
+    #[test]
+    fn test_disk_cache_entry_path() {
+        // Entries written by one build are found by the next
+        let env = Env::captured(Vec::new());
+        let path = disk_cache::entry_path("hostname", &"hostname", &env).unwrap();
+        assert!(path.ends_with(
+            "loco-pilot/hostname/24b07444318d9c9b2d3dd546a9b726855df29e8d223e6d57506c17273d887437.json"
+        ));
+
+        let env = Env::captured([(disk_cache::DISABLE_VAR.to_string(), "1".to_string())]);
+        assert_eq!(disk_cache::entry_path("hostname", &"hostname", &env), None);
+    }
+
+    #[test]
+    fn test_git_cache_roundtrip() {
+        let cache_path = env::temp_dir()
+            .join(format!("loco-pilot-cache-{}", std::process::id()))
+            .join("status.json");
+        assert!(disk_cache::read::<GitStatus>(&cache_path).is_none());
+
+        let status = GitStatus {
+            branch: "main".to_string(),
+            upstream: Some("origin/main".to_string()),
+            files: FileCounts {
+                staged: 1,
+                modified: 2,
+                deleted: 0,
+                untracked: 3,
+            },
+            ahead: 1,
+            ..GitStatus::default()
+        };
+        disk_cache::write(&cache_path, &status).unwrap();
+        let (cached, age) = disk_cache::read::<GitStatus>(&cache_path).unwrap();
+        assert_eq!(cached, status);
+        assert!(age < Duration::from_secs(60));
+        assert!(
+            disk_cache::read_fresh::<GitStatus>(&cache_path, Duration::from_secs(60)).is_some()
+        );
+
+        let _ = fs::remove_dir_all(cache_path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_truncate_branch() {
+        let branch = "feature/PROJ-1234-really-long-description";
+        assert_eq!(truncate_branch(branch, 0, "…", "end"), branch);
+        assert_eq!(truncate_branch("main", 10, "…", "end"), "main");
+        assert_eq!(
+            truncate_branch(branch, 18, "…", "end"),
+            "feature/PROJ-1234…"
+        );
+        assert_eq!(
+            truncate_branch(branch, 20, "...", "middle"),
+            "feature/P...cription"
+        );
+        assert_eq!(truncate_branch(branch, 20, "...", "middle").len(), 20);
+
+        // The result never exceeds max_len, even without room for the symbol
+        assert_eq!(truncate_branch(branch, 2, "...", "end"), "fe");
+        assert_eq!(truncate_branch(branch, 3, "...", "middle"), "fea");
+        assert_eq!(truncate_branch(branch, 4, "...", "end"), "f...");
+        assert_eq!(truncate_branch(branch, 1, "…", "middle"), "f");
+    }
+
+    #[test]
+    fn test_user_email() {
+        let repo_dir = env::temp_dir().join(format!("loco-pilot-email-{}", std::process::id()));
+        fs::create_dir_all(&repo_dir).unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(&repo_dir)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        };
+
+        if git(&["init", "-q"]) {
+            assert!(git(&["config", "user.email", "me@work.example"]));
+            assert_eq!(
+                user_email(&repo_dir.join(".git")).as_deref(),
+                Some("me@work.example")
+            );
+        }
+
+        let _ = fs::remove_dir_all(&repo_dir);
+    }
+
+    #[test]
+    fn test_is_sparse_checkout() {
+        let repo_dir = env::temp_dir().join(format!("loco-pilot-sparse-{}", std::process::id()));
+        fs::create_dir_all(&repo_dir).unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(&repo_dir)
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        };
+
+        if git(&["init", "-q"]) {
+            let git_dir = repo_dir.join(".git");
+            assert!(!is_sparse_checkout(&git_dir));
+
+            assert!(git(&["config", "core.sparseCheckout", "true"]));
+            fs::create_dir_all(git_dir.join("info")).unwrap();
+            fs::write(git_dir.join("info").join("sparse-checkout"), "/src/\n").unwrap();
+            assert!(is_sparse_checkout(&git_dir));
+        }
+
+        let _ = fs::remove_dir_all(&repo_dir);
+    }
+
+    #[test]
+    fn test_parse_worktrees() {
+        let output = "worktree /src/repo\nHEAD 1234567890abcdef\nbranch refs/heads/main\n\nworktree /src/repo-fix\nHEAD abcdef1234567890\ndetached\n\nworktree /src/bare.git\nbare\n";
+        assert_eq!(
+            parse_worktrees(output),
+            vec![
+                Worktree {
+                    path: "/src/repo".to_string(),
+                    label: "main".to_string(),
+                },
+                Worktree {
+                    path: "/src/repo-fix".to_string(),
+                    label: "detached@abcdef1".to_string(),
+                },
+                Worktree {
+                    path: "/src/bare.git".to_string(),
+                    label: "bare".to_string(),
+                },
+            ]
+        );
+        assert!(parse_worktrees("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_stash_list() {
+        let output = "stash@{0}\t2 hours ago\tWIP on main: 1234567 Fix\tthings\nstash@{1}\t3 days ago\tOn feature: experiment\n";
+        assert_eq!(
+            parse_stash_list(output),
+            vec![
+                StashEntry {
+                    reference: "stash@{0}".to_string(),
+                    age: "2 hours ago".to_string(),
+                    message: "WIP on main: 1234567 Fix\tthings".to_string(),
+                },
+                StashEntry {
+                    reference: "stash@{1}".to_string(),
+                    age: "3 days ago".to_string(),
+                    message: "On feature: experiment".to_string(),
+                },
+            ]
+        );
+        assert!(parse_stash_list("").is_empty());
+    }
+
+    #[test]
+    fn test_last_fetch_time() {
+        let git_dir = env::temp_dir().join(format!("loco-pilot-fetch-{}", std::process::id()));
+        fs::create_dir_all(&git_dir).unwrap();
+        assert_eq!(last_fetch_time(&git_dir), None);
+
+        fs::write(git_dir.join("FETCH_HEAD"), "").unwrap();
+        let fetch_time = last_fetch_time(&git_dir).unwrap();
+        assert!((chrono::Utc::now().timestamp() - fetch_time).abs() < 60);
+
+        let _ = fs::remove_dir_all(&git_dir);
+    }
+
+    #[test]
+    fn test_render_format() {
+        let lookup = |name: &str| -> Option<String> {
+            match name {
+                "user" => Some("alice".to_string()),
+                "host" => Some("box".to_string()),
+                "git" | "cmd" => Some(String::new()),
+                "char" => Some("$".to_string()),
+                _ => None,
+            }
+        };
+
+        assert_eq!(
+            format::render("$user{@$host} $char ", &lookup),
+            "alice@box $ "
+        );
+        // Groups vanish along with their empty variables
+        assert_eq!(
+            format::render("$user{ ($git)}{$cmd }$char", &lookup),
+            "alice$"
+        );
+        // Groups without variables and unknown variables are kept as written
+        assert_eq!(format::render("{>} $nope $$", &lookup), "> $nope $");
+
+        let order = ["git", "host", "user"].map(String::from);
+        assert_eq!(format::render_order(&order, &lookup), "box alice $ ");
+        assert_eq!(format::render_order(&[], &lookup), "$ ");
+
+        assert_eq!(
+            format::variables("$user{@$host} $$5 {$user $git}$char "),
+            ["user", "host", "git", "char"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_daemon_round_trip() {
+        let path = env::temp_dir().join(format!("loco-pilot-test-{}.sock", std::process::id()));
+        let listener = daemon::bind(&path).unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::thread::spawn(move || {
+            daemon::serve(listener, |request| {
+                (request.args.first()? != "--fail").then(|| request.args.join(" "))
+            })
+        });
+        let request = |args: &[&str]| daemon::Request {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            cwd: PathBuf::from("/"),
+            env: Vec::new(),
+        };
+
+        assert_eq!(
+            daemon::query(&path, &request(&["--shell", "zsh"])),
+            Some("--shell zsh".to_string())
+        );
+        // Unanswered requests leave the prompt to the client
+        assert_eq!(daemon::query(&path, &request(&["--fail"])), None);
+        // A second daemon can't take over a live socket
+        assert!(daemon::bind(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_captured_env() {
+        let env = Env::captured([
+            ("HOME".to_string(), "/home/shell".to_string()),
+            ("SSH_TTY".to_string(), "/dev/pts/1".to_string()),
+            ("NO_COLOR".to_string(), String::new()),
+            ("LOCO_PILOT_STYLE".to_string(), "minimal".to_string()),
+            ("COLUMNS".to_string(), "132".to_string()),
+            (
+                "GIT_CEILING_DIRECTORIES".to_string(),
+                "/home/shell".to_string(),
+            ),
+        ]);
+        // The daemon renders with the shell's variables, not its own
+        assert_eq!(env.var("HOME").as_deref(), Some("/home/shell"));
+        assert_eq!(env.home_dir(), Some(PathBuf::from("/home/shell")));
+        assert_eq!(env.var("PATH"), None);
+        assert!(segments::ssh::is_ssh_session(&env));
+        assert!(!colors_disabled(false, &Config::default(), &env));
+        assert_eq!(load_config_in(&env).style, "minimal");
+        assert_eq!(terminal_columns(&env), Some(132));
+        assert_eq!(git_ceilings(&env), [PathBuf::from("/home/shell")]);
+        // Without COLUMNS the width is unknown, so nothing is truncated
+        assert_eq!(terminal_columns(&Env::captured(Vec::new())), None);
+        let test_env = Env::captured([(test_mode::VAR.to_string(), "1".to_string())]);
+        assert!(test_mode::enabled(&test_env));
+        assert_eq!(get_hostname(&test_env), test_mode::HOSTNAME);
+
+        #[cfg(unix)]
+        {
+            let output = env.command("/usr/bin/env").output().unwrap();
+            let vars = String::from_utf8(output.stdout).unwrap();
+            assert!(vars.contains("HOME=/home/shell\n"));
+            assert!(!vars.contains("PATH="));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plugin_segment() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("loco-pilot-plugins-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write_plugin = |name: &str, script: &str| {
+            let path = dir.join(format!("loco-pilot-{}", name));
+            fs::write(&path, script).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        // Echo the shell from the JSON context in red
+        write_plugin(
+            "shellname",
+            "#!/bin/sh\nshell=$(sed -n 's/.*\"shell\":\"\\([a-z]*\\)\".*/\\1/p')\nprintf '\\033[31m%s\\033[0m\\n' \"$shell\"\n",
+        );
+        write_plugin("broken", "#!/bin/sh\necho oops\nexit 1\n");
+        let plugins = PluginsConfig {
+            dirs: vec![dir.display().to_string()],
+            timeout_ms: 5000,
+        };
+        let process_env = Env::default();
+        let context = |name| segments::plugin::PluginContext {
+            name,
+            cwd: env::temp_dir(),
+            shell: "zsh",
+            style: "default",
+            columns: None,
+            status: Some(0),
+            right: false,
+            git: None,
+            env: &process_env,
+        };
+
+        assert_eq!(
+            plugin_segment(&plugins, &context("shellname"), "…").as_deref(),
+            Some("\x1b[31mzsh\x1b[0m")
+        );
+        // Failing plugins hide their segment, missing ones leave the variable alone
+        assert_eq!(
+            plugin_segment(&plugins, &context("broken"), "…").as_deref(),
+            Some("")
+        );
+        assert_eq!(
+            plugin_segment(&plugins, &context("missing-plugin"), "…"),
+            None
+        );
+
+        assert_eq!(
+            format::wrap_escapes("\x1b[31mzsh\x1b[0m!", &|sequence| format!("<{}>", sequence)),
+            "<\x1b[31m>zsh<\x1b[0m>!"
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "wasm-plugins")]
+    #[test]
+    fn test_wasm_plugin_segment() {
+        let dir = env::temp_dir().join(format!("loco-pilot-wasm-{}", std::process::id()));
+        fs::create_dir_all(dir.join("project")).unwrap();
+        fs::write(dir.join("project/VERSION"), "1.2.3").unwrap();
+        fs::write(dir.join("secret"), "hidden").unwrap();
+        let cwd = dir.join("project");
+
+        // Reads a file named by the input into the output buffer at 2048
+        let read_module = wat::parse_str(
+            r#"(module
+                (import "loco_pilot" "read_file" (func $read (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "render") (param i32 i32) (result i64)
+                    (local $len i32)
+                    (local.set $len (call $read (local.get 0) (local.get 1) (i32.const 2048) (i32.const 64)))
+                    (if (i32.lt_s (local.get $len) (i32.const 0))
+                        (then (local.set $len (i32.const 0))))
+                    (i64.or (i64.shl (i64.const 2048) (i64.const 32))
+                        (i64.extend_i32_u (local.get $len)))))"#,
+        )
+        .unwrap();
+        let read_path = dir.join("read.wasm");
+        fs::write(&read_path, read_module).unwrap();
+        assert_eq!(
+            segments::wasm::run(&read_path, &cwd, &Env::default(), b"VERSION").as_deref(),
+            Ok("1.2.3")
+        );
+        // Files outside the prompt's directory can't be read
+        assert_eq!(
+            segments::wasm::run(&read_path, &cwd, &Env::default(), b"../secret").as_deref(),
+            Ok("")
+        );
+
+        let spin_module = wat::parse_str(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 0)
+                (func (export "render") (param i32 i32) (result i64)
+                    (loop $forever (br $forever))
+                    i64.const 0))"#,
+        )
+        .unwrap();
+        let spin_path = dir.join("spin.wasm");
+        fs::write(&spin_path, spin_module).unwrap();
+        assert!(segments::wasm::run(&spin_path, &cwd, &Env::default(), b"{}").is_err());
+
+        // A result past the end of memory is an error, not a 4 GiB allocation
+        let huge_module = wat::parse_str(
+            r#"(module
+                (memory (export "memory") 1)
+                (func (export "alloc") (param i32) (result i32) i32.const 0)
+                (func (export "render") (param i32 i32) (result i64)
+                    i64.const 0xffffffff))"#,
+        )
+        .unwrap();
+        let huge_path = dir.join("huge.wasm");
+        fs::write(&huge_path, huge_module).unwrap();
+        assert!(segments::wasm::run(&huge_path, &cwd, &Env::default(), b"{}").is_err());
+
+        assert_eq!(segments::wasm::find("read", &dir.join("..")), None);
+        fs::create_dir_all(dir.join("plugins")).unwrap();
+        fs::copy(&read_path, dir.join("plugins/read.wasm")).unwrap();
+        assert_eq!(
+            segments::wasm::find("read", &dir),
+            Some(dir.join("plugins/read.wasm"))
+        );
+        assert_eq!(segments::wasm::find("../read", &dir), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_explain_segments() {
+        let config = Config {
+            show_git: false,
+            segments: SegmentsConfig {
+                disabled: vec!["time".to_string()],
+                ..SegmentsConfig::default()
+            },
+            ..Config::default()
+        };
+        let reports = explain_segments(&config, "$time $user $dir $git");
+        let reason = |name: &str| {
+            let report = reports.iter().find(|report| report.name == name).unwrap();
+            (report.shown, report.reason.clone())
+        };
+
+        assert_eq!(
+            reason("time"),
+            (false, Some("listed in segments.disabled".to_string()))
+        );
+        assert_eq!(
+            reason("node"),
+            (false, Some("not in the prompt layout".to_string()))
+        );
+        assert_eq!(reason("git"), (false, Some("show_git is off".to_string())));
+        assert_eq!(reason("user"), (true, None));
+    }
+
+    #[test]
+    fn test_percentile() {
+        let samples: Vec<Duration> = (1..=20).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&samples, 50), Duration::from_millis(10));
+        assert_eq!(percentile(&samples, 95), Duration::from_millis(19));
+        assert_eq!(percentile(&samples[..1], 95), Duration::from_millis(1));
+        assert_eq!(format_latency(Duration::from_micros(12_340)), "12.34 ms");
+    }
+
+    #[test]
+    fn test_nerd_font_icons() {
+        let mut config = Config::default();
+        config.python.symbol = "py ".to_string();
+        apply_nerd_font_icons(&mut config);
+
+        // Defaults take the glyphs, symbols the user set are kept
+        assert_eq!(config.git.branch_symbol, icons::BRANCH);
+        assert_eq!(config.node.symbol, icons::NODE);
+        assert_eq!(config.python.symbol, "py ");
+        assert_eq!(config.git.staged_symbol, "+");
+
+        let mut config = Config::default();
+        config.git.ahead_symbol = "⇡".to_string();
+        apply_ascii_symbols(&mut config);
+        assert_eq!(config.git.behind_symbol, "v");
+        assert_eq!(config.git.truncate_symbol, "...");
+        assert_eq!(config.git.ahead_symbol, "⇡");
+        assert_eq!(config.git.remote_symbols["github.com"], "gh");
+    }
+
+    #[test]
+    fn test_expand_fill() {
+        let fill = format::FILL;
+        assert_eq!(
+            format::expand_fill(&format!("~/src{}12:00\n$ ", fill), 16, ".", "", ""),
+            "~/src......12:00\n$ "
+        );
+        // Escape sequences and shell markers take up no columns
+        assert_eq!(
+            format::display_width("\\[\x1b[1;32m\\]user\\[\x1b[0m\\] %{\x1b[34m%}🕒"),
+            7
+        );
+        // Two fills share the space, and a wide symbol is padded with spaces
+        assert_eq!(
+            format::expand_fill(&format!("a{}b{}c", fill, fill), 8, "-", "<", ">"),
+            "a<--->b<-->c"
+        );
+        assert_eq!(
+            format::expand_fill(&format!("a{}b", fill), 7, "──", "", ""),
+            "a──── b"
+        );
+        // Lines wider than the terminal get no fill
+        assert_eq!(
+            format::expand_fill(&format!("abc{}d", fill), 2, " ", "", ""),
+            "abcd"
+        );
+    }
+
+    #[test]
+    fn test_fit_width() {
+        let render = |dropped: &[&str]| {
+            let lookup = |name: &str| match name {
+                _ if dropped.contains(&name) => Some(String::new()),
+                "time" => Some("12:00".to_string()),
+                "dir" => Some("~/src/project".to_string()),
+                "git" => Some("main".to_string()),
+                _ => None,
+            };
+            format::render("{$time }$dir{ $git} $ ", &lookup)
+        };
+        let order = vec!["time".to_string(), "git".to_string()];
+
+        assert_eq!(
+            format::fit_width(80, &order, &render),
+            ("12:00 ~/src/project main $ ".to_string(), vec![])
+        );
+        // Segments go in order, and only as many as needed
+        assert_eq!(
+            format::fit_width(22, &order, &render),
+            ("~/src/project main $ ".to_string(), vec!["time"])
+        );
+        assert_eq!(format::fit_width(16, &order, &render).0, "~/src/project $ ");
+        // Running out of segments to drop leaves the prompt as short as it gets
+        assert_eq!(
+            format::fit_width(4, &order, &render),
+            ("~/src/project $ ".to_string(), vec!["time", "git"])
+        );
+
+        assert_eq!(
+            format::max_line_width(&format!("ab{}c\nde", format::FILL)),
+            3
+        );
+    }
+
+    #[test]
+    fn test_color_codes() {
+        assert_eq!(color::ansi_code("bright_cyan", &Env::default()), "\x1b[96m");
+        assert_eq!(
+            color::ansi_code("color208", &Env::default()),
+            "\x1b[38;5;208m"
+        );
+        assert_eq!(color::ansi_code("none", &Env::default()), "");
+        // Unknown values keep falling back to bold green
+        assert_eq!(color::ansi_code("#12345", &Env::default()), "\x1b[1;32m");
+        assert_eq!(color::ansi_code("color256", &Env::default()), "\x1b[1;32m");
+
+        // Styles combine attributes with foreground and background colors
+        assert_eq!(
+            color::ansi_code("bold white on blue", &Env::default()),
+            "\x1b[1;37;44m"
+        );
+        assert_eq!(
+            color::ansi_code("fg=cyan bg=color236 underline", &Env::default()),
+            "\x1b[36;48;5;236;4m"
+        );
+        assert_eq!(
+            color::ansi_code("bold sparkly", &Env::default()),
+            "\x1b[1;32m"
+        );
+
+        assert_eq!(color::rgb_to_256(0xff, 0x87, 0x00), 208);
+        assert_eq!(color::rgb_to_256(0, 0, 0), 16);
+        assert_eq!(color::rgb_to_256(0x80, 0x80, 0x80), 244);
+    }
+
+    #[test]
+    fn test_unset_config_key() {
+        let mut config = Config::default();
+        config.time.format = "12h".to_string();
+        config.colors.time = "red".to_string();
+        config
+            .git
+            .remote_symbols
+            .insert("git.example.com".to_string(), "E".to_string());
+
+        let config = unset_config_key(&config, "time.format").unwrap();
+        assert_eq!(config.time.format, Config::default().time.format);
+        assert_eq!(config.colors.time, "red");
+
+        let config = unset_config_key(&config, "color.time").unwrap();
+        assert_eq!(config.colors.time, Config::default().colors.time);
+
+        let config = unset_config_key(&config, "git.remote_symbols.git.example.com").unwrap();
+        assert!(!config.git.remote_symbols.contains_key("git.example.com"));
+        let config = unset_config_key(&config, "git.remote_symbols.github.com").unwrap();
+        assert!(config.git.remote_symbols.contains_key("github.com"));
+
+        assert!(unset_config_key(&config, "time.nope").is_none());
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let validate = |content| validate_config(content, ConfigFormat::Toml);
+        assert!(validate("style = \"info\"\n[colors]\ntime = \"color208\"\n").is_empty());
+        assert!(validate("[git.remote_symbols]\n\"git.example.com\" = \"E\"\n").is_empty());
+
+        assert_eq!(
+            validate("stlye = \"info\"\n[time]\nshow_secs = true\n"),
+            vec!["Unknown key: stlye", "Unknown key: time.show_secs"]
+        );
+        assert_eq!(
+            validate("[colors]\ntime = \"blurple\"\n"),
+            vec!["Unrecognized color for colors.time: \"blurple\" (falls back to bold green)"]
+        );
+        // Malformed TOML and wrong types are reported as they are
+        assert_eq!(validate("style = ").len(), 1);
+        assert_eq!(validate("show_git = \"yes\"").len(), 1);
+
+        assert_eq!(
+            validate_config(
+                "stlye: info\ncolors:\n  time: blurple\n",
+                ConfigFormat::Yaml
+            ),
+            vec![
+                "Unknown key: stlye",
+                "Unrecognized color for colors.time: \"blurple\" (falls back to bold green)"
+            ]
+        );
+        assert_eq!(
+            validate_config("{\"show_git\": \"yes\"}", ConfigFormat::Json).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_migrate_config() {
+        fn drop_legacy(table: &mut toml::Table) {
+            table.remove("legacy");
+        }
+        let migrations = [
+            Migration {
+                from: 1,
+                apply: merge_color_table,
+            },
+            Migration {
+                from: 2,
+                apply: drop_legacy,
+            },
+        ];
+
+        // Unversioned files are version 1 and go through every migration
+        let mut table: toml::Table = "legacy = true\n[color]\ntime = \"red\"\n".parse().unwrap();
+        assert_eq!(migrate_config(&mut table, &migrations), Some(1));
+        assert_eq!(table["colors"]["time"].as_str(), Some("red"));
+        assert!(!table.contains_key("legacy"));
+        assert_eq!(table["version"].as_integer(), Some(3));
+
+        let mut table: toml::Table = "version = 2\nlegacy = true\n".parse().unwrap();
+        assert_eq!(migrate_config(&mut table, &migrations), Some(2));
+        assert!(!table.contains_key("legacy"));
+
+        // Current files are left alone
+        let mut table: toml::Table = "version = 3\n".parse().unwrap();
+        assert_eq!(migrate_config(&mut table, &migrations), None);
+        let mut table: toml::Table = "version = 2\n[color]\ntime = \"red\"\n".parse().unwrap();
+        assert_eq!(migrate_config(&mut table, MIGRATIONS), None);
+
+        // Colors already in [colors] win over the [color] table
+        let config = parse_config(
+            "[color]\ntime = \"red\"\nusername = \"blue\"\n[colors]\nusername = \"magenta\"\n",
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.colors.time, "red");
+        assert_eq!(config.colors.username, "magenta");
+        assert!(validate_config("[color]\ntime = \"red\"\n", ConfigFormat::Toml).is_empty());
+        assert_eq!(
+            validate_config("version = 2\n[color]\ntime = \"red\"\n", ConfigFormat::Toml).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_config_formats() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.yml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.JSON")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("loco-pilot")),
+            ConfigFormat::Toml
+        );
+
+        let config = ConfigFormat::Yaml
+            .parse("style: info\ngit:\n  show_stash: false\n")
+            .unwrap();
+        assert_eq!(config.style, "info");
+        assert!(!config.git.show_stash);
+        assert!(config.show_git);
+
+        // Every format reads back what it writes
+        let mock_config = create_mock_config();
+        for format in [ConfigFormat::Toml, ConfigFormat::Yaml, ConfigFormat::Json] {
+            let content = format.serialize(&mock_config).unwrap();
+            let config = format.parse(&content).unwrap();
+            assert_eq!(config.format, mock_config.format);
+            assert_eq!(config.git.remote_symbols, mock_config.git.remote_symbols);
+        }
+    }
+
+    #[test]
+    fn test_config_schema() {
+        let schema = config_schema();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["show_git"]["type"], "boolean");
+        assert_eq!(schema["properties"]["show_git"]["default"], true);
+        assert_eq!(
+            schema["properties"]["style"]["enum"],
+            serde_json::json!(["default", "minimal", "info", "emoji"])
+        );
+
+        let git = &schema["properties"]["git"];
+        assert_eq!(git["additionalProperties"], false);
+        assert_eq!(git["properties"]["timeout_ms"]["type"], "integer");
+        assert_eq!(
+            git["properties"]["remote_symbols"]["additionalProperties"]["type"],
+            "string"
+        );
+        assert_eq!(
+            schema["properties"]["segments"]["properties"]["order"]["items"]["enum"][0],
+            "time"
+        );
+
+        // Every key the defaults have is described
+        let defaults = toml::Value::try_from(Config::default()).unwrap();
+        let mut paths = Vec::new();
+        config_leaf_paths(&defaults, &mut Vec::new(), &mut paths);
+        for path in paths {
+            let mut node = &schema;
+            for key in &path {
+                node = &node["properties"][key];
+            }
+            assert!(node["type"].is_string(), "{} missing", path.join("."));
+        }
+    }
+
+    #[test]
+    fn test_apply_env_overrides() {
+        let vars = [
+            ("LOCO_PILOT_STYLE", "info"),
+            ("LOCO_PILOT_SHOW_GIT", "false"),
+            ("LOCO_PILOT_COLOR_DIRECTORY", "blue"),
+            ("LOCO_PILOT_GIT_TIMEOUT_MS", "250"),
+            ("LOCO_PILOT_TIME_TIMEZONES", "UTC, Asia/Tokyo"),
+            ("LOCO_PILOT_DURATION_THRESHOLD", "soon"),
+            ("LOCO_PILOT_UNKNOWN", "x"),
+            ("PATH", "/bin"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let config = apply_env_overrides(Config::default(), vars.into_iter());
+        assert_eq!(config.style, "info");
+        assert!(!config.show_git);
+        assert_eq!(config.colors.directory, "blue");
+        assert_eq!(config.git.timeout_ms, 250);
+        assert_eq!(config.time.timezones, vec!["UTC", "Asia/Tokyo"]);
+        // Invalid numbers leave the setting alone
+        assert_eq!(
+            config.duration.threshold,
+            Config::default().duration.threshold
+        );
+    }
+
+    #[test]
+    fn test_import_starship() {
+        assert_eq!(
+            starship::translate_format(
+                "[$username@$hostname](bold) $aws$directory$git_branch$git_status($cmd_duration )\n$character"
+            ),
+            "$user@$host $dir$git{$cmd } $char "
+        );
+
+        let starship: toml::Table = "[directory]\nstyle = \"bold fg:cyan\"\ntruncation_length = 3\nread_only = \" ro\"\n[character]\nsuccess_symbol = \"[➜](bold green)\"\n[cmd_duration]\nmin_time = 500\n[git_status]\nahead = \"⇡${count}\"\n"
+            .parse()
+            .unwrap();
+        let mut config = Config::default();
+        let report = starship::import(&starship, &mut config);
+        assert_eq!(config.colors.directory, "bold fg=cyan");
+        assert_eq!(config.character.success_symbol, "➜");
+        assert_eq!(config.colors.character_success, "bold green");
+        assert_eq!(config.duration.threshold, 500);
+        assert_eq!(config.git.ahead_symbol, "⇡");
+        assert_eq!(config.directory.keep_components, 3);
+        assert!(report.contains(&"skipped directory.read_only (not supported)".to_string()));
+    }
+
+    #[test]
+    fn test_themes() {
+        assert_eq!(
+            theme_name("https://example.com/themes/nord.toml").as_deref(),
+            Some("nord")
+        );
+        assert_eq!(theme_name("./dracula.toml").as_deref(), Some("dracula"));
+        assert_eq!(theme_name("solarized").as_deref(), Some("solarized"));
+        assert_eq!(theme_name("../"), None);
+
+        // Partial themes fill in the defaults and only touch visual settings
+        let theme: Theme = toml::from_str("[colors]\ndirectory = \"blue\"\n").unwrap();
+        let mut config = Config {
+            style: "info".to_string(),
+            ..Config::default()
+        };
+        config.colors.time = "red".to_string();
+        theme.apply(&mut config);
+        assert_eq!(config.colors.directory, "blue");
+        assert_eq!(config.colors.time, Config::default().colors.time);
+        assert_eq!(config.style, "info");
+    }
+
+    #[test]
+    fn test_presets_apply() {
+        let config = Config {
+            style: "info".to_string(),
+            ..Config::default()
+        };
+        for preset in presets::PRESETS {
+            let preset_config = apply_preset(&config, preset).unwrap();
+            assert!(!preset_config.format.is_empty(), "{}", preset.name);
+            // Settings the preset doesn't mention are kept
+            assert_eq!(preset_config.style, "info");
+        }
+
+        let plain = apply_preset(&config, presets::find("plain-ascii").unwrap()).unwrap();
+        assert_eq!(plain.python.symbol, "py:");
+        assert!(preview_prompt(&plain).is_ascii());
+    }
+
+    #[test]
+    fn test_style_previews() {
+        let config = Config::default();
+        let previews = style_previews(&config, true);
+        let names: Vec<&str> = previews.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, format::STYLES);
+        assert!(previews[2].1.contains("12:34:56"));
+
+        // A configured layout is previewed after the styles, which ignore it
+        let config = Config {
+            format: "$dir $char ".to_string(),
+            ..Config::default()
+        };
+        let previews = style_previews(&config, true);
+        assert_eq!(previews.len(), format::STYLES.len() + 1);
+        assert!(previews[0].1.contains("user"));
+        assert_eq!(previews[4].0, "custom");
+        assert!(!previews[4].1.contains("user"));
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut script = Vec::new();
+        clap_complete::generate(
+            clap_complete::Shell::Bash,
+            &mut Args::command(),
+            "loco-pilot",
+            &mut script,
+        );
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("-o default loco-pilot\n"));
+        assert!(script.contains("completions"));
+        assert!(script.contains("--verbose"));
+    }
+
+    #[test]
+    fn test_doctor_checks() {
+        assert_eq!(parse_git_version("git version 2.43.0\n"), Some((2, 43)));
+        assert_eq!(
+            parse_git_version("git version 2.39.3 (Apple Git-146)"),
+            Some((2, 39))
+        );
+        assert_eq!(parse_git_version("not git"), None);
+        assert_eq!(
+            check_git(Some("git version 2.43.0"), "git").health,
+            Health::Ok
+        );
+        assert_eq!(
+            check_git(Some("git version 1.8.3"), "git").health,
+            Health::Warn
+        );
+        assert_eq!(check_git(None, "git").health, Health::Fail);
+        assert_eq!(check_git(None, "gix").health, Health::Warn);
+
+        let colors = |term, colorterm| check_terminal_colors(term, colorterm, false);
+        assert_eq!(colors(Some("xterm-256color"), None).health, Health::Ok);
+        assert_eq!(colors(Some("xterm"), Some("truecolor")).health, Health::Ok);
+        assert_eq!(colors(Some("xterm"), None).health, Health::Warn);
+        assert_eq!(colors(Some("dumb"), None).health, Health::Warn);
+        assert_eq!(colors(None, None).health, Health::Warn);
+        assert_eq!(
+            check_terminal_colors(Some("dumb"), None, true).health,
+            Health::Ok
+        );
+
+        let home = env::temp_dir().join(format!("loco-pilot-doctor-{}", std::process::id()));
+        fs::create_dir_all(home.join(".config").join("fish")).unwrap();
+        let hook = check_shell_hook(Some(ShellKind::Fish), &home);
+        assert_eq!(hook.health, Health::Fail);
+        assert_eq!(
+            hook.fix.as_deref(),
+            Some("add `loco-pilot init fish | source` to ~/.config/fish/config.fish")
+        );
+        fs::write(
+            home.join(".config").join("fish").join("config.fish"),
+            "loco-pilot init fish | source\n",
+        )
+        .unwrap();
+        assert_eq!(
+            check_shell_hook(Some(ShellKind::Fish), &home).health,
+            Health::Ok
+        );
+        assert_eq!(check_shell_hook(None, &home).health, Health::Warn);
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_update_is_newer() {
+        assert!(update::is_newer("v0.2.0", "0.1.0"));
+        assert!(update::is_newer("v0.10.0", "0.9.3"));
+        assert!(!update::is_newer("v0.1.0", "0.1.0"));
+        assert!(!update::is_newer("v0.1.0-rc.1", "0.1.0"));
+    }
+
+    #[test]
+    fn test_update_asset_name() {
+        assert_eq!(
+            update::asset_name("v0.2.0", "linux", "aarch64").as_deref(),
+            Some("loco-pilot-v0.2.0-linux-arm64.gz")
+        );
+        assert_eq!(
+            update::asset_name("v0.2.0", "windows", "x86_64").as_deref(),
+            Some("loco-pilot-v0.2.0-windows-amd64.exe.zip")
+        );
+        assert_eq!(update::asset_name("v0.2.0", "freebsd", "x86_64"), None);
+    }
+
+    #[test]
+    fn test_update_sha256_hex() {
+        // Compared against the first field of a release's .sha256 file
+        assert_eq!(
+            update::sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            update::sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_count_stashes() {
+        let git_dir = env::temp_dir().join(format!("loco-pilot-stash-{}", std::process::id()));
+        fs::create_dir_all(git_dir.join("logs").join("refs")).unwrap();
+        assert_eq!(count_stashes(&git_dir), 0);
+
+        let entry = "0000000000000000000000000000000000000000 1111111111111111111111111111111111111111 Jane <jane@example.com> 1700000000 +0000\tWIP on main: abc1234 message\n";
+        fs::write(
+            git_dir.join("logs").join("refs").join("stash"),
+            entry.repeat(3),
+        )
+        .unwrap();
+        assert_eq!(count_stashes(&git_dir), 3);
+
+        let _ = fs::remove_dir_all(&git_dir);
+    }
+
+    #[test]
+    fn test_is_node_project() {
+        use crate::segments::node::is_node_project;
+        let project_dir = env::temp_dir().join(format!("loco-pilot-node-{}", std::process::id()));
+        fs::create_dir_all(&project_dir).unwrap();
+        assert!(!is_node_project(&project_dir));
+
+        fs::write(project_dir.join("package.json"), "{}").unwrap();
+        assert!(is_node_project(&project_dir));
+
+        let _ = fs::remove_dir_all(&project_dir);
+    }
+
+    #[test]
+    fn test_parse_gcloud_core_section() {
+        use crate::segments::gcp::parse_core_section;
+        let content = "[compute]\nregion = us-east1\n\n[core]\naccount = jane@example.com\nproject = my-project\n";
+        let context = parse_core_section(content);
+        assert_eq!(context.project.as_deref(), Some("my-project"));
+        assert_eq!(context.account.as_deref(), Some("jane@example.com"));
+        assert_eq!(
+            parse_core_section("[compute]\nproject = nope\n").project,
+            None
+        );
+    }
+
+    #[test]
+    fn test_format_nix_shell() {
+        use crate::segments::nix::format_shell;
+        assert_eq!(format_shell("impure", Some("devshell")), "devshell");
+        assert_eq!(format_shell("pure", Some("hello")), "hello (pure)");
+        assert_eq!(format_shell("impure", None), "nix-shell");
+    }
+
+    #[test]
+    fn test_wsl_windows_path() {
+        use crate::segments::wsl::windows_path;
+        assert_eq!(
+            windows_path("/mnt/c/Users/me/projects"),
+            Some("C:/Users/me/projects".to_string())
+        );
+        assert_eq!(windows_path("/mnt/d"), Some("D:/".to_string()));
+        assert_eq!(windows_path("/mnt/wsl/docker"), None);
+        assert_eq!(windows_path("/home/me"), None);
+        assert_eq!(
+            shorten_path(
+                &windows_path("/mnt/c/Users/me/source/repos").unwrap(),
+                &DirectoryConfig::default()
+            ),
+            "C:/.../source/repos"
+        );
+    }
+
+    #[test]
+    fn test_container_detection() {
+        use crate::segments::container::{engine_from_cgroup, parse_containerenv};
+        let containerenv = "engine=\"podman-4.9.4\"\nname=\"fedora-toolbox-40\"\nimage=\"registry.fedoraproject.org/fedora-toolbox:40\"\n";
+        assert_eq!(
+            parse_containerenv(containerenv),
+            Some("fedora-toolbox-40".to_string())
+        );
+        assert_eq!(
+            parse_containerenv("image=\"alpine:3\"\nname=\"\"\n"),
+            Some("alpine:3".to_string())
+        );
+        assert_eq!(parse_containerenv(""), None);
+
+        assert_eq!(
+            engine_from_cgroup("0::/system.slice/docker-0123abcd.scope\n"),
+            Some("docker")
+        );
+        assert_eq!(
+            engine_from_cgroup("12:pids:/kubepods/burstable/pod1234\n"),
+            Some("kubernetes")
+        );
+        assert_eq!(engine_from_cgroup("0::/init.scope\n"), None);
+    }
+
+    #[test]
+    fn test_load_average() {
+        use crate::segments::load::{LoadLevel, load_level, parse_load_average};
+        assert_eq!(
+            parse_load_average("0.52 0.58 0.59 1/467 12345\n"),
+            Some(0.52)
+        );
+        assert_eq!(parse_load_average("{ 1.23 1.10 1.05 }"), Some(1.23));
+        assert_eq!(load_level(1.0, 4), LoadLevel::Low);
+        assert_eq!(load_level(3.0, 4), LoadLevel::Medium);
+        assert_eq!(load_level(4.5, 4), LoadLevel::High);
+    }
+
+    #[test]
+    fn test_time_format() {
+        let mut time_config = TimeConfig::default();
+        assert_eq!(time_format(&time_config), "%H:%M:%S");
+
+        time_config.format = "12h".to_string();
+        assert_eq!(time_format(&time_config), "%I:%M:%S %p");
+
+        time_config.show_seconds = false;
+        assert_eq!(time_format(&time_config), "%I:%M %p");
+
+        time_config.format = "%Y-%m-%d %H:%M:%S".to_string();
+        assert_eq!(time_format(&time_config), "%Y-%m-%d %H:%M");
+
+        // Specifiers that include the seconds get their shorter form
+        time_config.format = "%T".to_string();
+        assert_eq!(time_format(&time_config), "%H:%M");
+        time_config.format = "%a %X".to_string();
+        assert_eq!(time_format(&time_config), "%a %H:%M");
+        time_config.format = "%r".to_string();
+        assert_eq!(time_format(&time_config), "%I:%M %p");
+        time_config.format = "%H:%M:%-S".to_string();
+        assert_eq!(time_format(&time_config), "%H:%M");
+
+        // An escaped % is not a specifier
+        time_config.format = "%H:%M 100%%S".to_string();
+        assert_eq!(time_format(&time_config), "%H:%M 100%%S");
+        time_config.format = "%%T %T".to_string();
+        assert_eq!(time_format(&time_config), "%%T %H:%M");
+
+        // Invalid specifiers fall back to the default format
+        time_config.format = "%Q".to_string();
+        time_config.show_seconds = true;
+        assert_eq!(time_format(&time_config), "%H:%M:%S");
+    }
+
+    #[test]
+    fn test_format_current_time_with_timezones() {
+        let time_config = TimeConfig {
+            timezones: vec!["UTC".to_string(), "Not/AZone".to_string()],
+            ..TimeConfig::default()
+        };
+        let time = format_current_time(&time_config, &Env::default());
+        assert!(time.contains(" | UTC "));
+        assert_eq!(time.matches(" | ").count(), 1);
+    }
+
+    #[test]
+    fn test_screen_session_name() {
+        use crate::segments::multiplexer::screen_session_name;
+        assert_eq!(screen_session_name("12345.pts-0.host"), "pts-0.host");
+        assert_eq!(screen_session_name("12345.work"), "work");
+    }
+
+    #[test]
+    fn test_env_var_format_value() {
+        use crate::segments::env_var::format_value;
+        assert_eq!(
+            format_value(Some("prod"), "[", "]", ""),
+            Some("[prod]".to_string())
+        );
+        assert_eq!(
+            format_value(None, "[", "]", "local"),
+            Some("[local]".to_string())
+        );
+        assert_eq!(format_value(Some(""), "", "", ""), None);
+    }
+
+    #[test]
+    fn test_vi_mode_from_keymap() {
+        assert_eq!(ViMode::from_keymap("vicmd"), Some(ViMode::Normal));
+        assert_eq!(ViMode::from_keymap("viins"), Some(ViMode::Insert));
+        assert_eq!(ViMode::from_keymap("default"), Some(ViMode::Normal));
+        assert_eq!(ViMode::from_keymap("visual"), Some(ViMode::Visual));
+        assert_eq!(ViMode::from_keymap("replace_one"), Some(ViMode::Replace));
+        assert_eq!(ViMode::from_keymap("isearch"), None);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(45), "45s");
+        assert_eq!(format_age(12 * 60), "12m");
+        assert_eq!(format_age(5 * 3600 + 59), "5h");
+        assert_eq!(format_age(3 * 86_400), "3d");
+        assert_eq!(format_age(15 * 86_400), "2w");
+        assert_eq!(format_age(90 * 86_400), "3mo");
+        assert_eq!(format_age(800 * 86_400), "2y");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(850), "850ms");
+        assert_eq!(format_duration(3200), "3.2s");
+        assert_eq!(format_duration(65_000), "1m 5s");
+        assert_eq!(format_duration(3_720_000), "1h 2m");
     }
 
     #[test]
@@ -615,6 +7033,159 @@ mod tests {
         assert_eq!(bash_escaped, "\\[\x1b[32m\\]");
     }
 
+    #[test]
+    fn test_zsh_color() {
+        let color_code = "\x1b[32m"; // Green
+        let zsh_escaped = zsh_color(color_code);
+        assert_eq!(zsh_escaped, "%{\x1b[32m%}");
+    }
+
+    #[test]
+    fn test_zsh_escape_prompt() {
+        assert_eq!(
+            ShellKind::Zsh.escape_prompt("\x1b[36m~/100%Bdone\x1b[0m (50%)"),
+            "%{\x1b[36m%}~/100%%Bdone%{\x1b[0m%} (50%%)"
+        );
+        assert_eq!(
+            ShellKind::Fish.escape_prompt("\x1b[36m~/100%Bdone\x1b[0m"),
+            "\x1b[36m~/100%Bdone\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_shell_kind_color() {
+        let color_code = "\x1b[32m"; // Green
+        assert_eq!(
+            ShellKind::from_name("bash").color(color_code),
+            bash_color(color_code)
+        );
+        assert_eq!(
+            ShellKind::from_name("zsh").color(color_code),
+            zsh_color(color_code)
+        );
+        assert_eq!(ShellKind::from_name("fish").color(color_code), color_code);
+        assert_eq!(ShellKind::from_name("xonsh").color(color_code), color_code);
+        assert_eq!(ShellKind::from_name("unknown"), ShellKind::Bash);
+    }
+
+    #[test]
+    fn test_shell_from_process_name() {
+        assert_eq!(
+            ShellKind::from_process_name("bash\n"),
+            Some(ShellKind::Bash)
+        );
+        assert_eq!(ShellKind::from_process_name("-zsh"), Some(ShellKind::Zsh));
+        assert_eq!(
+            ShellKind::from_process_name("/usr/local/bin/fish"),
+            Some(ShellKind::Fish)
+        );
+        assert_eq!(
+            ShellKind::from_process_name("C:\\Program Files\\PowerShell\\7\\pwsh.exe"),
+            Some(ShellKind::PowerShell)
+        );
+        assert_eq!(ShellKind::from_process_name("sshd"), None);
+    }
+
+    #[test]
+    fn test_init_scripts() {
+        assert!(ShellKind::Bash.init_script().contains("PROMPT_COMMAND"));
+        assert!(ShellKind::Zsh.init_script().contains("add-zsh-hook precmd"));
+        assert!(
+            ShellKind::Fish
+                .init_script()
+                .contains("function fish_prompt")
+        );
+        assert!(ShellKind::Nu.init_script().contains("$env.PROMPT_COMMAND"));
+        assert!(ShellKind::Xonsh.init_script().contains("$PROMPT"));
+        assert!(ShellKind::Zsh.init_script().contains("RPROMPT="));
+        // prompt_subst must not expand the text of the prompt
+        assert!(
+            ShellKind::Zsh
+                .init_script()
+                .contains("PROMPT='${_loco_pilot_prompt}'")
+        );
+        assert!(
+            ShellKind::Fish
+                .init_script()
+                .contains("function fish_right_prompt")
+        );
+        // Every prompt passes its width, so truncation never has to ask the terminal
+        for shell in ["bash", "zsh", "fish", "powershell", "nu", "xonsh"] {
+            let script = ShellKind::from_name(shell).init_script();
+            for line in script.lines().filter(|line| line.contains("loco-pilot --")) {
+                assert!(line.contains("--columns"), "{}: {}", shell, line);
+            }
+        }
+        assert!(ShellKind::Zsh.semantic_prompt_hooks().contains("133;C"));
+        assert_eq!(
+            terminal::working_directory("laptop", "/home/me/my dir"),
+            "\x1b]7;file://laptop/home/me/my%20dir\x07"
+        );
+        assert_eq!(
+            terminal::working_directory("pc", "C:\\Users\\me"),
+            "\x1b]7;file://pc/C%3A/Users/me\x07"
+        );
+        assert_eq!(
+            terminal::title("me@box: ~/a\x07b"),
+            "\x1b]0;me@box: ~/ab\x07"
+        );
+        assert_eq!(
+            ShellKind::Zsh.non_printing("\x1b]7;file:///a%20b\x07"),
+            "%{\x1b]7;file:///a%%20b\x07%}"
+        );
+        assert_eq!(
+            ShellKind::Bash.non_printing("\x1b]2;C:\\$HOME\x07"),
+            "\\[\x1b]2;C:\\\\\\\\\\\\$HOME\x07\\]"
+        );
+        assert_eq!(ShellKind::parse("tcsh"), None);
+    }
+
+    #[test]
+    fn test_shorten_path() {
+        let directory = DirectoryConfig::default();
+        assert_eq!(shorten_path("~/short", &directory), "~/short");
+        assert_eq!(
+            shorten_path("/home/user/deeply/nested/folders/project/src", &directory),
+            "/.../project/src"
+        );
+        assert_eq!(
+            shorten_path("~/deeply/nested/folders/project/src", &directory),
+            "~/.../project/src"
+        );
+        assert_eq!(
+            shorten_path("C:\\Windows\\System32\\drivers\\etc", &directory),
+            "C:\\...\\drivers\\etc"
+        );
+
+        let directory = DirectoryConfig {
+            max_length: 30,
+            keep_components: 3,
+            truncation_symbol: "…".to_string(),
+            ..DirectoryConfig::default()
+        };
+        assert_eq!(
+            shorten_path("~/deeply/nested/folders/project/src", &directory),
+            "~/…/folders/project/src"
+        );
+        assert_eq!(
+            shorten_path("~/work/monorepo/services", &directory),
+            "~/work/monorepo/services"
+        );
+        let directory = DirectoryConfig {
+            keep_components: 0,
+            ..DirectoryConfig::default()
+        };
+        assert_eq!(shorten_path("~/deeply/nested/folders", &directory), "~/...");
+        let directory = DirectoryConfig {
+            max_length: 0,
+            ..DirectoryConfig::default()
+        };
+        assert_eq!(
+            shorten_path("~/deeply/nested/folders/project/src", &directory),
+            "~/deeply/nested/folders/project/src"
+        );
+    }
+
     #[test]
     fn test_mock_config() {
         // This test uses our mock config function from test_utils
@@ -634,19 +7205,181 @@ mod tests {
 
 fn main() {
     let args = Args::parse();
+    logging::init(args.verbose);
+    if let Some(config_path) = &args.config {
+        *lock(&CONFIG_PATH_OVERRIDE) = Some(config_path.clone());
+    }
+
+    if args.refresh_git_cache {
+        refresh_git_cache(&load_config().git);
+        return;
+    }
+
+    // Prompts come from the daemon when one is running
+    #[cfg(unix)]
+    if let Some(prompt) = args.command.is_none().then(query_daemon).flatten() {
+        let _ = io::stdout().write_all(prompt.as_bytes());
+        return;
+    }
 
     match &args.command {
-        Some(Commands::Config { key, value }) => {
+        Some(Commands::Config { key, value, yes }) => {
             // Handle configuration changes
-            // Load configuration
-            let mut config = load_config();
+            if key.as_deref() == Some("path") {
+                match get_config_path() {
+                    Some(path) if path.exists() => println!("{}", path.display()),
+                    Some(path) => println!("{} (not created yet)", path.display()),
+                    None => {
+                        eprintln!("Could not determine config directory");
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+
+            if key.as_deref() == Some("schema") {
+                match serde_json::to_string_pretty(&config_schema()) {
+                    Ok(schema) => println!("{}", schema),
+                    Err(e) => eprintln!("Failed to generate schema: {}", e),
+                }
+                return;
+            }
+
+            if key.as_deref() == Some("validate") {
+                let Some(path) = get_config_path() else {
+                    eprintln!("Could not determine config directory");
+                    std::process::exit(1);
+                };
+                let Ok(content) = fs::read_to_string(&path) else {
+                    println!("No config file at {}, using defaults", path.display());
+                    return;
+                };
+                let problems = validate_config(&content, ConfigFormat::from_path(&path));
+                if problems.is_empty() {
+                    println!("{} is valid", path.display());
+                    return;
+                }
+                println!("{} has problems:", path.display());
+                for problem in problems {
+                    println!("  {}", problem);
+                }
+                std::process::exit(1);
+            }
+
+            if key.as_deref() == Some("migrate") {
+                let Some(path) = get_config_path() else {
+                    eprintln!("Could not determine config directory");
+                    std::process::exit(1);
+                };
+                let version = fs::read_to_string(&path).ok().and_then(|content| {
+                    config_file_version(&content, ConfigFormat::from_path(&path))
+                });
+                match version {
+                    None => println!("No config file to migrate at {}", path.display()),
+                    Some(version) if version >= CONFIG_VERSION => {
+                        println!("{} is already at version {}", path.display(), version)
+                    }
+                    Some(version) => match save_config(&read_config_file()) {
+                        Ok(()) => println!(
+                            "Migrated {} from version {} to {} (backup at {})",
+                            path.display(),
+                            version,
+                            CONFIG_VERSION,
+                            config_backup_path(&path).display()
+                        ),
+                        Err(e) => eprintln!("Failed to save configuration: {}", e),
+                    },
+                }
+                return;
+            }
+
+            if key.as_deref() == Some("reset") {
+                if !*yes && !confirm("Reset all settings to their defaults?") {
+                    println!("Reset cancelled");
+                    return;
+                }
+                match save_config(&Config::default()) {
+                    Ok(()) => println!("Configuration reset to defaults"),
+                    Err(e) => eprintln!("Failed to save configuration: {}", e),
+                }
+                return;
+            }
+
+            // Load configuration, leaving environment overrides out of the file
+            let mut config = read_config_file();
 
             if let (Some(key), Some(value)) = (key, value) {
                 match key.as_str() {
+                    "import-starship" => {
+                        let starship = fs::read_to_string(value)
+                            .map_err(|e| e.to_string())
+                            .and_then(|content| {
+                                content.parse::<toml::Table>().map_err(|e| e.to_string())
+                            });
+                        match starship {
+                            Ok(starship) => {
+                                for line in starship::import(&starship, &mut config) {
+                                    println!("  {}", line);
+                                }
+                            }
+                            Err(e) => {
+                                println!("Could not read {}: {}", value, e);
+                                return;
+                            }
+                        }
+                    }
+                    "unset" => match unset_config_key(&config, value) {
+                        Some(default_config) => {
+                            config = default_config;
+                            println!("{} restored to its default", value);
+                        }
+                        None => {
+                            println!("Unknown configuration key: {}", value);
+                            return;
+                        }
+                    },
                     "style" => {
                         config.style = value.clone();
                         println!("Default style set to: {}", value);
                     }
+                    "shell" => {
+                        config.shell = value.clone();
+                        println!("Default shell set to: {}", value);
+                    }
+                    "format" => {
+                        config.format = value.clone();
+                        println!("Prompt format set to: {:?}", value);
+                    }
+                    "ascii" => {
+                        config.ascii = value.to_lowercase() == "true";
+                        println!("ASCII-only mode: {}", config.ascii);
+                    }
+                    "no_color" => {
+                        config.no_color = value.to_lowercase() == "true";
+                        println!("Colors disabled: {}", config.no_color);
+                    }
+                    "prompt_timeout" => match value.parse::<u64>() {
+                        Ok(prompt_timeout) => {
+                            config.prompt_timeout = prompt_timeout;
+                            println!("Prompt timeout set to: {}ms", prompt_timeout);
+                        }
+                        Err(_) => {
+                            println!("Invalid prompt timeout: {}", value);
+                            return;
+                        }
+                    },
+                    "timeout_placeholder" => {
+                        config.timeout_placeholder = value.clone();
+                        println!("Timeout placeholder set to: {:?}", value);
+                    }
+                    "icons" => {
+                        config.icons = value.clone();
+                        println!("Icons set to: {}", value);
+                    }
+                    "right_format" => {
+                        config.right_format = value.clone();
+                        println!("Right prompt format set to: {:?}", value);
+                    }
                     "show_git" => {
                         config.show_git = value.to_lowercase() == "true";
                         println!("Show git info: {}", config.show_git);
@@ -655,18 +7388,333 @@ fn main() {
                         config.colors.username = value.clone();
                         println!("Username color set to: {}", value);
                     }
+                    "color.username_root" => {
+                        config.colors.username_root = value.clone();
+                        println!("Root username color set to: {}", value);
+                    }
+                    "root.symbol" => {
+                        config.root.symbol = value.clone();
+                        println!("Root prompt character set to: {}", value);
+                    }
                     "color.hostname" => {
                         config.colors.hostname = value.clone();
                         println!("Hostname color set to: {}", value);
                     }
+                    "color.hostname_ssh" => {
+                        config.colors.hostname_ssh = value.clone();
+                        println!("SSH hostname color set to: {}", value);
+                    }
+                    "hostname.ssh_only" => {
+                        config.hostname.ssh_only = value.to_lowercase() == "true";
+                        println!("Only show hostname over SSH: {}", config.hostname.ssh_only);
+                    }
                     "color.directory" => {
                         config.colors.directory = value.clone();
                         println!("Directory color set to: {}", value);
                     }
+                    "directory.max_length" => match value.parse::<usize>() {
+                        Ok(length) => {
+                            config.directory.max_length = length;
+                            println!("Directory max length set to: {}", length);
+                        }
+                        Err(_) => {
+                            println!("Invalid directory max length: {}", value);
+                            return;
+                        }
+                    },
+                    "directory.keep_components" => match value.parse::<usize>() {
+                        Ok(count) => {
+                            config.directory.keep_components = count;
+                            println!("Directory components kept set to: {}", count);
+                        }
+                        Err(_) => {
+                            println!("Invalid number of directory components: {}", value);
+                            return;
+                        }
+                    },
+                    "directory.truncation_symbol" => {
+                        config.directory.truncation_symbol = value.clone();
+                        println!("Directory truncation symbol set to: {}", value);
+                    }
+                    prefix_key if prefix_key.starts_with("directory.substitutions.") => {
+                        let prefix = prefix_key.trim_start_matches("directory.substitutions.");
+                        config
+                            .directory
+                            .substitutions
+                            .insert(prefix.to_string(), value.clone());
+                        println!("Directory label for {} set to: {}", prefix, value);
+                    }
+                    "directory.truncate_to_repo" => {
+                        config.directory.truncate_to_repo = value.to_lowercase() == "true";
+                        println!(
+                            "Show directory from the repository root: {}",
+                            config.directory.truncate_to_repo
+                        );
+                    }
                     "color.git_branch" => {
                         config.colors.git_branch = value.clone();
                         println!("Git branch color set to: {}", value);
                     }
+                    "git.show_staged" => {
+                        config.git.show_staged = value.to_lowercase() == "true";
+                        println!("Show staged file count: {}", config.git.show_staged);
+                    }
+                    "git.show_modified" => {
+                        config.git.show_modified = value.to_lowercase() == "true";
+                        println!("Show modified file count: {}", config.git.show_modified);
+                    }
+                    "git.show_deleted" => {
+                        config.git.show_deleted = value.to_lowercase() == "true";
+                        println!("Show deleted file count: {}", config.git.show_deleted);
+                    }
+                    "git.show_untracked" => {
+                        config.git.show_untracked = value.to_lowercase() == "true";
+                        println!("Show untracked file count: {}", config.git.show_untracked);
+                    }
+                    "git.branch_symbol" => {
+                        config.git.branch_symbol = value.clone();
+                        println!("Git branch symbol set to: {}", value);
+                    }
+                    "git.emoji_branch_symbol" => {
+                        config.git.emoji_branch_symbol = value.clone();
+                        println!("Emoji git branch symbol set to: {}", value);
+                    }
+                    "git.ahead_symbol" => {
+                        config.git.ahead_symbol = value.clone();
+                        println!("Git ahead symbol set to: {}", value);
+                    }
+                    "git.behind_symbol" => {
+                        config.git.behind_symbol = value.clone();
+                        println!("Git behind symbol set to: {}", value);
+                    }
+                    "git.staged_symbol" => {
+                        config.git.staged_symbol = value.clone();
+                        println!("Git staged files symbol set to: {}", value);
+                    }
+                    "git.modified_symbol" => {
+                        config.git.modified_symbol = value.clone();
+                        println!("Git modified files symbol set to: {}", value);
+                    }
+                    "git.deleted_symbol" => {
+                        config.git.deleted_symbol = value.clone();
+                        println!("Git deleted files symbol set to: {}", value);
+                    }
+                    "git.untracked_symbol" => {
+                        config.git.untracked_symbol = value.clone();
+                        println!("Git untracked files symbol set to: {}", value);
+                    }
+                    "color.git_staged" => {
+                        config.colors.git_staged = value.clone();
+                        println!("Git staged files color set to: {}", value);
+                    }
+                    "color.git_deleted" => {
+                        config.colors.git_deleted = value.clone();
+                        println!("Git deleted files color set to: {}", value);
+                    }
+                    "color.git_untracked" => {
+                        config.colors.git_untracked = value.clone();
+                        println!("Git untracked files color set to: {}", value);
+                    }
+                    "git.backend" => match value.as_str() {
+                        "git" | "gix" => {
+                            config.git.backend = value.clone();
+                            println!("Git backend set to: {}", value);
+                        }
+                        _ => {
+                            println!("Invalid git backend: {} (expected git or gix)", value);
+                            return;
+                        }
+                    },
+                    "git.show_remote" => {
+                        config.git.show_remote = value.to_lowercase() == "true";
+                        println!("Show git remote icon: {}", config.git.show_remote);
+                    }
+                    "git.remote_default_symbol" => {
+                        config.git.remote_default_symbol = value.clone();
+                        println!("Default git remote symbol set to: {}", value);
+                    }
+                    domain_key if domain_key.starts_with("git.remote_symbols.") => {
+                        let domain = domain_key.trim_start_matches("git.remote_symbols.");
+                        config
+                            .git
+                            .remote_symbols
+                            .insert(domain.to_lowercase(), value.clone());
+                        println!("Git remote symbol for {} set to: {}", domain, value);
+                    }
+                    "git.disabled_repos" => {
+                        config.git.disabled_repos = value
+                            .split(',')
+                            .map(|path| path.trim().to_string())
+                            .filter(|path| !path.is_empty())
+                            .collect();
+                        println!(
+                            "Git disabled repositories set to: {}",
+                            config.git.disabled_repos.join(", ")
+                        );
+                    }
+                    "git.branch_only_repos" => {
+                        config.git.branch_only_repos = value
+                            .split(',')
+                            .map(|path| path.trim().to_string())
+                            .filter(|path| !path.is_empty())
+                            .collect();
+                        println!(
+                            "Git branch-only repositories set to: {}",
+                            config.git.branch_only_repos.join(", ")
+                        );
+                    }
+                    "git.timeout_ms" => match value.parse::<u64>() {
+                        Ok(timeout_ms) => {
+                            config.git.timeout_ms = timeout_ms;
+                            println!("Git timeout set to: {}ms", timeout_ms);
+                        }
+                        Err(_) => {
+                            println!("Invalid git timeout: {}", value);
+                            return;
+                        }
+                    },
+                    "git.async_refresh" => {
+                        config.git.async_refresh = value.to_lowercase() == "true";
+                        println!(
+                            "Refresh git status in the background: {}",
+                            config.git.async_refresh
+                        );
+                    }
+                    "git.cache_ttl" => match value.parse::<u64>() {
+                        Ok(cache_ttl) => {
+                            config.git.cache_ttl = cache_ttl;
+                            println!("Git cache TTL set to: {}s", cache_ttl);
+                        }
+                        Err(_) => {
+                            println!("Invalid git cache TTL: {}", value);
+                            return;
+                        }
+                    },
+                    "git.truncate_branch" => match value.parse::<usize>() {
+                        Ok(length) => {
+                            config.git.truncate_branch = length;
+                            println!("Git branch truncation length set to: {}", length);
+                        }
+                        Err(_) => {
+                            println!("Invalid branch truncation length: {}", value);
+                            return;
+                        }
+                    },
+                    "git.truncate_symbol" => {
+                        config.git.truncate_symbol = value.clone();
+                        println!("Git branch truncation symbol set to: {}", value);
+                    }
+                    "git.truncate_position" => match value.as_str() {
+                        "end" | "middle" => {
+                            config.git.truncate_position = value.clone();
+                            println!("Git branch truncation position set to: {}", value);
+                        }
+                        _ => {
+                            println!(
+                                "Invalid truncation position: {} (expected end or middle)",
+                                value
+                            );
+                            return;
+                        }
+                    },
+                    "git.show_commit_age" => {
+                        config.git.show_commit_age = value.to_lowercase() == "true";
+                        println!("Show HEAD commit age: {}", config.git.show_commit_age);
+                    }
+                    "git.commit_age_symbol" => {
+                        config.git.commit_age_symbol = value.clone();
+                        println!("Commit age symbol set to: {}", value);
+                    }
+                    "color.git_commit_age" => {
+                        config.colors.git_commit_age = value.clone();
+                        println!("Commit age color set to: {}", value);
+                    }
+                    "git.expected_email" => {
+                        config.git.expected_email = value.clone();
+                        println!("Expected git email set to: {}", value);
+                    }
+                    "git.email_symbol" => {
+                        config.git.email_symbol = value.clone();
+                        println!("Git email symbol set to: {}", value);
+                    }
+                    "color.git_email" => {
+                        config.colors.git_email = value.clone();
+                        println!("Git email color set to: {}", value);
+                    }
+                    "git.show_fetch_age" => {
+                        config.git.show_fetch_age = value.to_lowercase() == "true";
+                        println!("Show last fetch age: {}", config.git.show_fetch_age);
+                    }
+                    "git.fetch_age_symbol" => {
+                        config.git.fetch_age_symbol = value.clone();
+                        println!("Fetch age symbol set to: {}", value);
+                    }
+                    "git.stale_fetch_age" => match value.parse::<u64>() {
+                        Ok(seconds) => {
+                            config.git.stale_fetch_age = seconds;
+                            println!("Stale fetch age set to: {}s", seconds);
+                        }
+                        Err(_) => {
+                            println!("Invalid stale fetch age: {}", value);
+                            return;
+                        }
+                    },
+                    "color.git_stale" => {
+                        config.colors.git_stale = value.clone();
+                        println!("Stale fetch color set to: {}", value);
+                    }
+                    "git.show_sparse" => {
+                        config.git.show_sparse = value.to_lowercase() == "true";
+                        println!("Show sparse-checkout marker: {}", config.git.show_sparse);
+                    }
+                    "git.sparse_symbol" => {
+                        config.git.sparse_symbol = value.clone();
+                        println!("Sparse-checkout marker set to: {}", value);
+                    }
+                    "color.git_sparse" => {
+                        config.colors.git_sparse = value.clone();
+                        println!("Sparse-checkout marker color set to: {}", value);
+                    }
+                    "git.show_upstream" => {
+                        config.git.show_upstream = value.to_lowercase() == "true";
+                        println!("Show git upstream: {}", config.git.show_upstream);
+                    }
+                    "color.git_upstream" => {
+                        config.colors.git_upstream = value.clone();
+                        println!("Git upstream color set to: {}", value);
+                    }
+                    "git.show_tag" => {
+                        config.git.show_tag = value.to_lowercase() == "true";
+                        println!("Show git tag: {}", config.git.show_tag);
+                    }
+                    "git.tag_symbol" => {
+                        config.git.tag_symbol = value.clone();
+                        println!("Git tag symbol set to: {}", value);
+                    }
+                    "color.git_tag" => {
+                        config.colors.git_tag = value.clone();
+                        println!("Git tag color set to: {}", value);
+                    }
+                    "git.show_operation" => {
+                        config.git.show_operation = value.to_lowercase() == "true";
+                        println!("Show git operation: {}", config.git.show_operation);
+                    }
+                    "color.git_operation" => {
+                        config.colors.git_operation = value.clone();
+                        println!("Git operation color set to: {}", value);
+                    }
+                    "git.show_stash" => {
+                        config.git.show_stash = value.to_lowercase() == "true";
+                        println!("Show git stash count: {}", config.git.show_stash);
+                    }
+                    "git.stash_symbol" => {
+                        config.git.stash_symbol = value.clone();
+                        println!("Git stash symbol set to: {}", value);
+                    }
+                    "color.git_stash" => {
+                        config.colors.git_stash = value.clone();
+                        println!("Git stash color set to: {}", value);
+                    }
                     "color.git_dirty" => {
                         config.colors.git_dirty = value.clone();
                         println!("Git dirty indicator color set to: {}", value);
@@ -675,6 +7723,376 @@ fn main() {
                         config.colors.time = value.clone();
                         println!("Time color set to: {}", value);
                     }
+                    "color.status" => {
+                        config.colors.status = value.clone();
+                        println!("Exit status color set to: {}", value);
+                    }
+                    "status.symbol" => {
+                        config.status.symbol = value.clone();
+                        println!("Exit status symbol set to: {}", value);
+                    }
+                    "status.pipestatus_format" => {
+                        config.status.pipestatus_format = value.clone();
+                        println!("Pipestatus format set to: {}", value);
+                    }
+                    "python.show" => {
+                        config.python.show = value.to_lowercase() == "true";
+                        println!("Show python virtualenv: {}", config.python.show);
+                    }
+                    "python.symbol" => {
+                        config.python.symbol = value.clone();
+                        println!("Python virtualenv symbol set to: {}", value);
+                    }
+                    "color.python" => {
+                        config.colors.python = value.clone();
+                        println!("Python virtualenv color set to: {}", value);
+                    }
+                    "conda.show" => {
+                        config.conda.show = value.to_lowercase() == "true";
+                        println!("Show conda environment: {}", config.conda.show);
+                    }
+                    "conda.symbol" => {
+                        config.conda.symbol = value.clone();
+                        println!("Conda environment symbol set to: {}", value);
+                    }
+                    "conda.hide_base" => {
+                        config.conda.hide_base = value.to_lowercase() == "true";
+                        println!("Hide conda base environment: {}", config.conda.hide_base);
+                    }
+                    "color.conda" => {
+                        config.colors.conda = value.clone();
+                        println!("Conda environment color set to: {}", value);
+                    }
+                    "node.show" => {
+                        config.node.show = value.to_lowercase() == "true";
+                        println!("Show node version: {}", config.node.show);
+                    }
+                    "node.symbol" => {
+                        config.node.symbol = value.clone();
+                        println!("Node version symbol set to: {}", value);
+                    }
+                    "node.cache_ttl" => match value.parse::<u64>() {
+                        Ok(cache_ttl) => {
+                            config.node.cache_ttl = cache_ttl;
+                            println!("Node version cache TTL set to: {}s", cache_ttl);
+                        }
+                        Err(_) => {
+                            println!("Invalid node version cache TTL: {}", value);
+                            return;
+                        }
+                    },
+                    "color.node" => {
+                        config.colors.node = value.clone();
+                        println!("Node version color set to: {}", value);
+                    }
+                    "gcp.show" => {
+                        config.gcp.show = value.to_lowercase() == "true";
+                        println!("Show gcloud project: {}", config.gcp.show);
+                    }
+                    "gcp.show_account" => {
+                        config.gcp.show_account = value.to_lowercase() == "true";
+                        println!("Show gcloud account: {}", config.gcp.show_account);
+                    }
+                    "gcp.symbol" => {
+                        config.gcp.symbol = value.clone();
+                        println!("Gcloud project symbol set to: {}", value);
+                    }
+                    "color.gcp" => {
+                        config.colors.gcp = value.clone();
+                        println!("Gcloud project color set to: {}", value);
+                    }
+                    "nix.show" => {
+                        config.nix.show = value.to_lowercase() == "true";
+                        println!("Show nix shell: {}", config.nix.show);
+                    }
+                    "nix.symbol" => {
+                        config.nix.symbol = value.clone();
+                        println!("Nix shell symbol set to: {}", value);
+                    }
+                    "color.nix" => {
+                        config.colors.nix = value.clone();
+                        println!("Nix shell color set to: {}", value);
+                    }
+                    "wsl.show" => {
+                        config.wsl.show = value.to_lowercase() == "true";
+                        println!("Show WSL distribution: {}", config.wsl.show);
+                    }
+                    "wsl.symbol" => {
+                        config.wsl.symbol = value.clone();
+                        println!("WSL symbol set to: {}", value);
+                    }
+                    "wsl.windows_paths" => {
+                        config.wsl.windows_paths = value.to_lowercase() == "true";
+                        println!("Show Windows drive paths: {}", config.wsl.windows_paths);
+                    }
+                    "color.wsl" => {
+                        config.colors.wsl = value.clone();
+                        println!("WSL color set to: {}", value);
+                    }
+                    "container.show" => {
+                        config.container.show = value.to_lowercase() == "true";
+                        println!("Show container indicator: {}", config.container.show);
+                    }
+                    "container.symbol" => {
+                        config.container.symbol = value.clone();
+                        println!("Container symbol set to: {}", value);
+                    }
+                    "color.container" => {
+                        config.colors.container = value.clone();
+                        println!("Container color set to: {}", value);
+                    }
+                    "terminal.semantic_prompt" => {
+                        config.terminal.semantic_prompt = value.to_lowercase() == "true";
+                        println!(
+                            "Semantic prompt markers: {}",
+                            config.terminal.semantic_prompt
+                        );
+                    }
+                    "terminal.report_directory" => {
+                        config.terminal.report_directory = value.to_lowercase() == "true";
+                        println!(
+                            "Working directory reporting: {}",
+                            config.terminal.report_directory
+                        );
+                    }
+                    "terminal.title" => {
+                        config.terminal.title = value.clone();
+                        println!("Window title: {}", config.terminal.title);
+                    }
+                    "plugins.dirs" => {
+                        config.plugins.dirs = value
+                            .split(',')
+                            .map(|dir| dir.trim().to_string())
+                            .filter(|dir| !dir.is_empty())
+                            .collect();
+                        println!(
+                            "Plugin directories set to: {}",
+                            config.plugins.dirs.join(", ")
+                        );
+                    }
+                    "plugins.timeout_ms" => match value.parse::<u64>() {
+                        Ok(timeout_ms) => {
+                            config.plugins.timeout_ms = timeout_ms;
+                            println!("Plugin timeout set to: {}ms", timeout_ms);
+                        }
+                        Err(_) => {
+                            println!("Invalid plugin timeout: {}", value);
+                            return;
+                        }
+                    },
+                    "truncation.max_width" => match value.parse::<f64>() {
+                        Ok(max_width) if max_width >= 0.0 => {
+                            config.truncation.max_width = max_width;
+                            println!("Maximum prompt width set to: {}", max_width);
+                        }
+                        _ => {
+                            println!(
+                                "Invalid maximum prompt width: {} (use a fraction of the terminal width, e.g. 0.8)",
+                                value
+                            );
+                            return;
+                        }
+                    },
+                    "truncation.drop_order" => {
+                        config.truncation.drop_order = value
+                            .split(',')
+                            .map(|name| name.trim().to_string())
+                            .filter(|name| !name.is_empty())
+                            .collect();
+                        println!(
+                            "Segments dropped in narrow terminals: {}",
+                            config.truncation.drop_order.join(", ")
+                        );
+                    }
+                    "fill.symbol" => {
+                        config.fill.symbol = value.clone();
+                        println!("Fill symbol set to: {}", value);
+                    }
+                    "color.fill" => {
+                        config.colors.fill = value.clone();
+                        println!("Fill color set to: {}", value);
+                    }
+                    "clipboard.backend" => match value.as_str() {
+                        "auto" | "osc52" => {
+                            config.clipboard.backend = value.clone();
+                            println!("Clipboard backend set to: {}", value);
+                        }
+                        _ => {
+                            println!(
+                                "Invalid clipboard backend: {} (expected auto or osc52)",
+                                value
+                            );
+                            return;
+                        }
+                    },
+                    "load.show" => {
+                        config.load.show = value.to_lowercase() == "true";
+                        println!("Show system load: {}", config.load.show);
+                    }
+                    "load.symbol" => {
+                        config.load.symbol = value.clone();
+                        println!("System load symbol set to: {}", value);
+                    }
+                    "time.format" => {
+                        config.time.format = value.clone();
+                        println!("Time format set to: {}", value);
+                    }
+                    "segments.order" | "segments.disabled" => {
+                        let names: Vec<String> = value
+                            .split(',')
+                            .map(|name| name.trim().to_string())
+                            .filter(|name| !name.is_empty())
+                            .collect();
+                        if let Some(unknown) = names
+                            .iter()
+                            .find(|name| !format::SEGMENTS.contains(&name.as_str()))
+                        {
+                            println!(
+                                "Invalid segment: {} (expected {})",
+                                unknown,
+                                format::SEGMENTS.join(", ")
+                            );
+                            return;
+                        }
+                        if key == "segments.order" {
+                            config.segments.order = names;
+                            println!("Segment order set to: {}", config.segments.order.join(", "));
+                        } else {
+                            config.segments.disabled = names;
+                            println!(
+                                "Disabled segments set to: {}",
+                                config.segments.disabled.join(", ")
+                            );
+                        }
+                    }
+                    "time.timezones" => {
+                        config.time.timezones = value
+                            .split(',')
+                            .map(|name| name.trim().to_string())
+                            .filter(|name| !name.is_empty())
+                            .collect();
+                        println!(
+                            "Extra timezones set to: {}",
+                            config.time.timezones.join(", ")
+                        );
+                    }
+                    "time.show_seconds" => {
+                        config.time.show_seconds = value.to_lowercase() == "true";
+                        println!("Show seconds: {}", config.time.show_seconds);
+                    }
+                    "tmux.show" => {
+                        config.tmux.show = value.to_lowercase() == "true";
+                        println!("Show tmux/screen session: {}", config.tmux.show);
+                    }
+                    "tmux.symbol" => {
+                        config.tmux.symbol = value.clone();
+                        println!("tmux/screen session symbol set to: {}", value);
+                    }
+                    "tmux.hide_hostname" => {
+                        config.tmux.hide_hostname = value.to_lowercase() == "true";
+                        println!(
+                            "Hide hostname inside tmux/screen: {}",
+                            config.tmux.hide_hostname
+                        );
+                    }
+                    "color.tmux" => {
+                        config.colors.tmux = value.clone();
+                        println!("tmux/screen session color set to: {}", value);
+                    }
+                    "env_var.name" => {
+                        config.env_var.name = value.clone();
+                        println!("Environment variable segment set to: {}", value);
+                    }
+                    "env_var.prefix" => {
+                        config.env_var.prefix = value.clone();
+                        println!("Environment variable prefix set to: {}", value);
+                    }
+                    "env_var.suffix" => {
+                        config.env_var.suffix = value.clone();
+                        println!("Environment variable suffix set to: {}", value);
+                    }
+                    "env_var.fallback" => {
+                        config.env_var.fallback = value.clone();
+                        println!("Environment variable fallback set to: {}", value);
+                    }
+                    "color.env_var" => {
+                        config.colors.env_var = value.clone();
+                        println!("Environment variable color set to: {}", value);
+                    }
+                    "character.success_symbol" => {
+                        config.character.success_symbol = value.clone();
+                        println!("Success prompt character set to: {}", value);
+                    }
+                    "character.error_symbol" => {
+                        config.character.error_symbol = value.clone();
+                        println!("Error prompt character set to: {}", value);
+                    }
+                    "character.emoji_success_symbol" => {
+                        config.character.emoji_success_symbol = value.clone();
+                        println!("Emoji success prompt character set to: {}", value);
+                    }
+                    "character.emoji_error_symbol" => {
+                        config.character.emoji_error_symbol = value.clone();
+                        println!("Emoji error prompt character set to: {}", value);
+                    }
+                    "color.character_success" => {
+                        config.colors.character_success = value.clone();
+                        println!("Success prompt character color set to: {}", value);
+                    }
+                    "color.character_error" => {
+                        config.colors.character_error = value.clone();
+                        println!("Error prompt character color set to: {}", value);
+                    }
+                    "color.character_root" => {
+                        config.colors.character_root = value.clone();
+                        println!("Root prompt character color set to: {}", value);
+                    }
+                    "keymap.normal_symbol" => {
+                        config.keymap.normal_symbol = value.clone();
+                        println!("Vi normal mode indicator set to: {}", value);
+                    }
+                    "keymap.insert_symbol" => {
+                        config.keymap.insert_symbol = value.clone();
+                        println!("Vi insert mode indicator set to: {}", value);
+                    }
+                    "keymap.visual_symbol" => {
+                        config.keymap.visual_symbol = value.clone();
+                        println!("Vi visual mode indicator set to: {}", value);
+                    }
+                    "keymap.replace_symbol" => {
+                        config.keymap.replace_symbol = value.clone();
+                        println!("Vi replace mode indicator set to: {}", value);
+                    }
+                    "color.keymap_normal" => {
+                        config.colors.keymap_normal = value.clone();
+                        println!("Vi normal mode color set to: {}", value);
+                    }
+                    "color.keymap_insert" => {
+                        config.colors.keymap_insert = value.clone();
+                        println!("Vi insert mode color set to: {}", value);
+                    }
+                    "color.keymap_visual" => {
+                        config.colors.keymap_visual = value.clone();
+                        println!("Vi visual mode color set to: {}", value);
+                    }
+                    "color.keymap_replace" => {
+                        config.colors.keymap_replace = value.clone();
+                        println!("Vi replace mode color set to: {}", value);
+                    }
+                    "color.duration" => {
+                        config.colors.duration = value.clone();
+                        println!("Command duration color set to: {}", value);
+                    }
+                    "duration.threshold" => match value.parse::<u64>() {
+                        Ok(threshold) => {
+                            config.duration.threshold = threshold;
+                            println!("Command duration threshold set to: {}ms", threshold);
+                        }
+                        Err(_) => {
+                            println!("Invalid duration threshold: {}", value);
+                            return;
+                        }
+                    },
                     _ => {
                         println!("Unknown configuration key: {}", key);
                         return;
@@ -691,28 +8109,601 @@ fn main() {
                 // If no key/value provided, show current configuration
                 println!("Current configuration:");
                 println!("  style = {}", config.style);
+                println!("  shell = {}", config.shell);
+                println!("  format = {:?}", config.format);
+                println!("  right_format = {:?}", config.right_format);
+                println!("  icons = {}", config.icons);
+                println!("  ascii = {}", config.ascii);
+                println!("  no_color = {}", config.no_color);
+                println!("  prompt_timeout = {}", config.prompt_timeout);
+                println!("  timeout_placeholder = {:?}", config.timeout_placeholder);
+                println!("  segments.order = {}", config.segments.order.join(","));
+                println!(
+                    "  segments.disabled = {}",
+                    config.segments.disabled.join(",")
+                );
                 println!("  show_git = {}", config.show_git);
                 println!("  color.username = {}", config.colors.username);
+                println!("  color.username_root = {}", config.colors.username_root);
+                println!("  root.symbol = {}", config.root.symbol);
                 println!("  color.hostname = {}", config.colors.hostname);
+                println!("  color.hostname_ssh = {}", config.colors.hostname_ssh);
+                println!("  hostname.ssh_only = {}", config.hostname.ssh_only);
                 println!("  color.directory = {}", config.colors.directory);
+                println!("  directory.max_length = {}", config.directory.max_length);
+                println!(
+                    "  directory.keep_components = {}",
+                    config.directory.keep_components
+                );
+                println!(
+                    "  directory.truncation_symbol = {}",
+                    config.directory.truncation_symbol
+                );
+                println!(
+                    "  directory.truncate_to_repo = {}",
+                    config.directory.truncate_to_repo
+                );
+                for (prefix, label) in &config.directory.substitutions {
+                    println!("  directory.substitutions.{} = {}", prefix, label);
+                }
                 println!("  color.git_branch = {}", config.colors.git_branch);
                 println!("  color.git_dirty = {}", config.colors.git_dirty);
+                println!("  git.show_staged = {}", config.git.show_staged);
+                println!("  git.show_modified = {}", config.git.show_modified);
+                println!("  git.show_deleted = {}", config.git.show_deleted);
+                println!("  git.show_untracked = {}", config.git.show_untracked);
+                println!("  git.branch_symbol = {}", config.git.branch_symbol);
+                println!(
+                    "  git.emoji_branch_symbol = {}",
+                    config.git.emoji_branch_symbol
+                );
+                println!("  git.ahead_symbol = {}", config.git.ahead_symbol);
+                println!("  git.behind_symbol = {}", config.git.behind_symbol);
+                println!("  git.staged_symbol = {}", config.git.staged_symbol);
+                println!("  git.modified_symbol = {}", config.git.modified_symbol);
+                println!("  git.deleted_symbol = {}", config.git.deleted_symbol);
+                println!("  git.untracked_symbol = {}", config.git.untracked_symbol);
+                println!("  color.git_staged = {}", config.colors.git_staged);
+                println!("  color.git_deleted = {}", config.colors.git_deleted);
+                println!("  color.git_untracked = {}", config.colors.git_untracked);
+                println!("  git.backend = {}", config.git.backend);
+                println!("  git.show_remote = {}", config.git.show_remote);
+                for (domain, symbol) in &config.git.remote_symbols {
+                    println!("  git.remote_symbols.{} = {}", domain, symbol);
+                }
+                println!(
+                    "  git.remote_default_symbol = {}",
+                    config.git.remote_default_symbol
+                );
+                println!(
+                    "  git.disabled_repos = {}",
+                    config.git.disabled_repos.join(", ")
+                );
+                println!(
+                    "  git.branch_only_repos = {}",
+                    config.git.branch_only_repos.join(", ")
+                );
+                println!("  git.timeout_ms = {}", config.git.timeout_ms);
+                println!("  git.async_refresh = {}", config.git.async_refresh);
+                println!("  git.cache_ttl = {}", config.git.cache_ttl);
+                println!("  git.truncate_branch = {}", config.git.truncate_branch);
+                println!("  git.truncate_symbol = {}", config.git.truncate_symbol);
+                println!("  git.truncate_position = {}", config.git.truncate_position);
+                println!("  git.show_commit_age = {}", config.git.show_commit_age);
+                println!("  git.commit_age_symbol = {}", config.git.commit_age_symbol);
+                println!("  color.git_commit_age = {}", config.colors.git_commit_age);
+                println!("  git.expected_email = {}", config.git.expected_email);
+                println!("  git.email_symbol = {}", config.git.email_symbol);
+                println!("  color.git_email = {}", config.colors.git_email);
+                println!("  git.show_fetch_age = {}", config.git.show_fetch_age);
+                println!("  git.fetch_age_symbol = {}", config.git.fetch_age_symbol);
+                println!("  git.stale_fetch_age = {}", config.git.stale_fetch_age);
+                println!("  color.git_stale = {}", config.colors.git_stale);
+                println!("  git.show_sparse = {}", config.git.show_sparse);
+                println!("  git.sparse_symbol = {}", config.git.sparse_symbol);
+                println!("  color.git_sparse = {}", config.colors.git_sparse);
+                println!("  git.show_upstream = {}", config.git.show_upstream);
+                println!("  color.git_upstream = {}", config.colors.git_upstream);
+                println!("  git.show_tag = {}", config.git.show_tag);
+                println!("  git.tag_symbol = {}", config.git.tag_symbol);
+                println!("  color.git_tag = {}", config.colors.git_tag);
+                println!("  git.show_operation = {}", config.git.show_operation);
+                println!("  color.git_operation = {}", config.colors.git_operation);
+                println!("  git.show_stash = {}", config.git.show_stash);
+                println!("  git.stash_symbol = {}", config.git.stash_symbol);
+                println!("  color.git_stash = {}", config.colors.git_stash);
                 println!("  color.time = {}", config.colors.time);
+                println!("  color.status = {}", config.colors.status);
+                println!("  status.symbol = {}", config.status.symbol);
+                println!(
+                    "  status.pipestatus_format = {}",
+                    config.status.pipestatus_format
+                );
+                println!("  color.duration = {}", config.colors.duration);
+                println!("  duration.threshold = {}", config.duration.threshold);
+                println!("  python.show = {}", config.python.show);
+                println!("  python.symbol = {}", config.python.symbol);
+                println!("  color.python = {}", config.colors.python);
+                println!("  conda.show = {}", config.conda.show);
+                println!("  conda.symbol = {}", config.conda.symbol);
+                println!("  conda.hide_base = {}", config.conda.hide_base);
+                println!("  color.conda = {}", config.colors.conda);
+                println!("  node.show = {}", config.node.show);
+                println!("  node.symbol = {}", config.node.symbol);
+                println!("  node.cache_ttl = {}", config.node.cache_ttl);
+                println!("  color.node = {}", config.colors.node);
+                println!("  gcp.show = {}", config.gcp.show);
+                println!("  gcp.show_account = {}", config.gcp.show_account);
+                println!("  gcp.symbol = {}", config.gcp.symbol);
+                println!("  color.gcp = {}", config.colors.gcp);
+                println!("  nix.show = {}", config.nix.show);
+                println!("  nix.symbol = {}", config.nix.symbol);
+                println!("  color.nix = {}", config.colors.nix);
+                println!("  tmux.show = {}", config.tmux.show);
+                println!("  tmux.symbol = {}", config.tmux.symbol);
+                println!("  tmux.hide_hostname = {}", config.tmux.hide_hostname);
+                println!("  color.tmux = {}", config.colors.tmux);
+                println!("  env_var.name = {}", config.env_var.name);
+                println!("  env_var.prefix = {}", config.env_var.prefix);
+                println!("  env_var.suffix = {}", config.env_var.suffix);
+                println!("  env_var.fallback = {}", config.env_var.fallback);
+                println!("  color.env_var = {}", config.colors.env_var);
+                println!(
+                    "  character.success_symbol = {}",
+                    config.character.success_symbol
+                );
+                println!(
+                    "  character.error_symbol = {}",
+                    config.character.error_symbol
+                );
+                println!(
+                    "  character.emoji_success_symbol = {}",
+                    config.character.emoji_success_symbol
+                );
+                println!(
+                    "  character.emoji_error_symbol = {}",
+                    config.character.emoji_error_symbol
+                );
+                println!(
+                    "  color.character_success = {}",
+                    config.colors.character_success
+                );
+                println!(
+                    "  color.character_error = {}",
+                    config.colors.character_error
+                );
+                println!("  color.character_root = {}", config.colors.character_root);
+                println!("  keymap.normal_symbol = {}", config.keymap.normal_symbol);
+                println!("  keymap.insert_symbol = {}", config.keymap.insert_symbol);
+                println!("  keymap.visual_symbol = {}", config.keymap.visual_symbol);
+                println!("  keymap.replace_symbol = {}", config.keymap.replace_symbol);
+                println!("  color.keymap_normal = {}", config.colors.keymap_normal);
+                println!("  color.keymap_insert = {}", config.colors.keymap_insert);
+                println!("  color.keymap_visual = {}", config.colors.keymap_visual);
+                println!("  color.keymap_replace = {}", config.colors.keymap_replace);
+                println!("  wsl.show = {}", config.wsl.show);
+                println!("  wsl.symbol = {}", config.wsl.symbol);
+                println!("  wsl.windows_paths = {}", config.wsl.windows_paths);
+                println!("  color.wsl = {}", config.colors.wsl);
+                println!("  container.show = {}", config.container.show);
+                println!("  container.symbol = {}", config.container.symbol);
+                println!("  color.container = {}", config.colors.container);
+                println!("  fill.symbol = {}", config.fill.symbol);
+                println!("  truncation.max_width = {}", config.truncation.max_width);
+                println!(
+                    "  truncation.drop_order = {}",
+                    config.truncation.drop_order.join(",")
+                );
+                println!(
+                    "  terminal.semantic_prompt = {}",
+                    config.terminal.semantic_prompt
+                );
+                println!(
+                    "  terminal.report_directory = {}",
+                    config.terminal.report_directory
+                );
+                println!("  terminal.title = {}", config.terminal.title);
+                println!("  plugins.dirs = {}", config.plugins.dirs.join(", "));
+                println!("  plugins.timeout_ms = {}", config.plugins.timeout_ms);
+                println!("  color.fill = {}", config.colors.fill);
+                println!("  clipboard.backend = {}", config.clipboard.backend);
+                println!("  load.show = {}", config.load.show);
+                println!("  load.symbol = {}", config.load.symbol);
+                println!("  time.format = {}", config.time.format);
+                println!("  time.show_seconds = {}", config.time.show_seconds);
+                println!("  time.timezones = {}", config.time.timezones.join(","));
+            }
+        }
+        Some(Commands::Version { json }) => {
+            if *json {
+                println!("{}", version_json());
+            } else {
+                println!("Version: {}", get_full_version());
             }
         }
-        Some(Commands::Version) => {
-            println!("Version: {}", get_full_version());
+        Some(Commands::Init { shell }) => match ShellKind::parse(shell) {
+            Some(shell) => {
+                print!("{}", shell.init_script());
+                if load_config().terminal.semantic_prompt {
+                    print!("{}", shell.semantic_prompt_hooks());
+                }
+            }
+            None => {
+                eprintln!("Unsupported shell: {}", shell);
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Completions { shell }) => {
+            // Generated in full first, as writing straight to a closed pipe panics
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut Args::command(), "loco-pilot", &mut script);
+            let _ = io::stdout().write_all(&script);
         }
-        None => {
-            // Only load config if needed for the style information
-            let style = if args.style != "default" {
-                args.style
+        Some(Commands::GitRemoteUrl { https }) => {
+            let url = env::current_dir()
+                .ok()
+                .and_then(|current_dir| repo_root(&current_dir, &git_ceilings(&Env::default())))
+                .and_then(|root| resolve_git_dir(&root.join(".git")))
+                .and_then(|git_dir| origin_url(&git_dir));
+            match url {
+                Some(url) => {
+                    let url = if *https { browsable_url(&url) } else { url };
+                    print_and_copy(&url, args.print_only);
+                }
+                None => {
+                    eprintln!("No origin remote found");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Commands::OpenRepo { file, branch }) => {
+            let root = env::current_dir()
+                .ok()
+                .and_then(|dir| repo_root(&dir, &git_ceilings(&Env::default())));
+            let git_dir = root
+                .as_deref()
+                .and_then(|root| resolve_git_dir(&root.join(".git")));
+            let (Some(root), Some(git_dir)) = (root, git_dir) else {
+                eprintln!("Not in a git repository");
+                std::process::exit(1);
+            };
+            let Some(base) = origin_url(&git_dir).map(|url| browsable_url(&url)) else {
+                eprintln!("No origin remote found");
+                std::process::exit(1);
+            };
+
+            // A detached HEAD has no branch page, so link the commit instead
+            let current_ref = head_branch(&git_dir)
+                .map(|status| status.branch)
+                .filter(|branch| !branch.starts_with("detached@"))
+                .or_else(|| get_git_commit_sha(true))
+                .unwrap_or_else(|| "HEAD".to_string());
+
+            let relative = match file {
+                Some(file) => {
+                    let relative = fs::canonicalize(file).ok().and_then(|path| {
+                        let root = fs::canonicalize(&root).ok()?;
+                        path.strip_prefix(root).ok().map(Path::to_path_buf)
+                    });
+                    let Some(relative) = relative else {
+                        eprintln!("File is not inside the repository: {}", file.display());
+                        std::process::exit(1);
+                    };
+                    Some(relative.to_string_lossy().replace('\\', "/"))
+                }
+                None => None,
+            };
+
+            let url = match relative {
+                Some(relative) => forge_url(&base, &current_ref, Some(&relative)),
+                None if *branch => forge_url(&base, &current_ref, None),
+                None => base,
+            };
+            println!("{}", url);
+            if !open_in_browser(&url) {
+                eprintln!("Could not open a browser; URL printed only");
+            }
+        }
+        Some(Commands::Theme { action }) => {
+            let Some(dir) = themes_dir() else {
+                eprintln!("Could not determine config directory");
+                std::process::exit(1);
+            };
+            match action {
+                ThemeCommand::List => {
+                    let mut names: Vec<String> = fs::read_dir(&dir)
+                        .into_iter()
+                        .flatten()
+                        .flatten()
+                        .filter_map(|entry| {
+                            let name = entry.file_name().to_string_lossy().to_string();
+                            name.strip_suffix(".toml").map(str::to_string)
+                        })
+                        .collect();
+                    names.sort();
+                    if names.is_empty() {
+                        println!("No themes installed in {}", dir.display());
+                    }
+                    for name in names {
+                        println!("{}", name);
+                    }
+                }
+                ThemeCommand::Use { name } => {
+                    let path = dir.join(format!("{}.toml", name));
+                    let theme = fs::read_to_string(&path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|content| {
+                            toml::from_str::<Theme>(&content).map_err(|e| e.to_string())
+                        });
+                    let theme = match theme {
+                        Ok(theme) => theme,
+                        Err(e) => {
+                            eprintln!("Could not load theme {}: {}", name, e);
+                            std::process::exit(1);
+                        }
+                    };
+                    let mut config = read_config_file();
+                    theme.apply(&mut config);
+                    match save_config(&config) {
+                        Ok(()) => println!("Theme {} applied", name),
+                        Err(e) => eprintln!("Failed to save configuration: {}", e),
+                    }
+                }
+                ThemeCommand::Export { name } => {
+                    let theme = Theme::from_config(&read_config_file());
+                    let content = match toml::to_string_pretty(&theme) {
+                        Ok(content) => content,
+                        Err(e) => {
+                            eprintln!("Could not serialize theme: {}", e);
+                            std::process::exit(1);
+                        }
+                    };
+                    match name.as_deref().and_then(theme_name) {
+                        Some(name) => {
+                            let path = dir.join(format!("{}.toml", name));
+                            match fs::write(&path, content) {
+                                Ok(()) => println!("Theme saved to {}", path.display()),
+                                Err(e) => eprintln!("Failed to save theme: {}", e),
+                            }
+                        }
+                        None => print!("{}", content),
+                    }
+                }
+                ThemeCommand::Import { source, name } => {
+                    let Some(name) = name
+                        .as_deref()
+                        .or(Some(source.as_str()))
+                        .and_then(theme_name)
+                    else {
+                        eprintln!("Could not derive a theme name from {}, use --name", source);
+                        std::process::exit(1);
+                    };
+                    let content = match read_theme_source(source) {
+                        Ok(content) => content,
+                        Err(e) => {
+                            eprintln!("Could not read {}: {}", source, e);
+                            std::process::exit(1);
+                        }
+                    };
+                    // Refuse files that wouldn't load when used
+                    if let Err(e) = toml::from_str::<Theme>(&content) {
+                        eprintln!("{} is not a valid theme: {}", source, e.message());
+                        std::process::exit(1);
+                    }
+                    let path = dir.join(format!("{}.toml", name));
+                    match fs::write(&path, content) {
+                        Ok(()) => println!(
+                            "Theme {} installed, apply it with `loco-pilot theme use {}`",
+                            name, name
+                        ),
+                        Err(e) => eprintln!("Failed to save theme: {}", e),
+                    }
+                }
+            }
+        }
+        Some(Commands::Styles { sample }) => {
+            let config = load_config();
+            let custom = !config.format.is_empty() || !config.segments.order.is_empty();
+            let active = if custom {
+                "custom"
+            } else {
+                config.style.as_str()
+            };
+            for (name, prompt) in style_previews(&config, *sample) {
+                let current = if name == active { " (current)" } else { "" };
+                println!("{}{}", name, current);
+                println!("  {}\n", prompt.trim_end().replace('\n', "\n  "));
+            }
+            if custom {
+                println!(
+                    "The custom layout from `format` or `segments.order` is used over the style"
+                );
             } else {
-                load_config().style
+                println!("Set one with `loco-pilot config style <name>`");
+            }
+        }
+        Some(Commands::Preset { action }) => match action {
+            PresetCommand::List { preview } => {
+                let config = read_config_file();
+                for preset in presets::PRESETS {
+                    println!("{:<12} {}", preset.name, preset.description);
+                    if !*preview {
+                        continue;
+                    }
+                    match apply_preset(&config, preset) {
+                        Ok(preset_config) => println!(
+                            "  {}\n",
+                            preview_prompt(&preset_config).replace('\n', "\n  ")
+                        ),
+                        Err(e) => println!("  (invalid preset: {})\n", e),
+                    }
+                }
+            }
+            PresetCommand::Use { name } => {
+                let Some(preset) = presets::find(name) else {
+                    let names: Vec<&str> =
+                        presets::PRESETS.iter().map(|preset| preset.name).collect();
+                    eprintln!("Unknown preset: {} (expected {})", name, names.join(", "));
+                    std::process::exit(1);
+                };
+                let config = match apply_preset(&read_config_file(), preset) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        eprintln!("Could not apply preset {}: {}", name, e);
+                        std::process::exit(1);
+                    }
+                };
+                match save_config(&config) {
+                    Ok(()) => println!("Preset {} applied", name),
+                    Err(e) => eprintln!("Failed to save configuration: {}", e),
+                }
+            }
+        },
+        Some(Commands::Worktrees) => {
+            let worktrees = Command::new("git")
+                .args(["worktree", "list", "--porcelain"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| parse_worktrees(&String::from_utf8_lossy(&output.stdout)))
+                .unwrap_or_default();
+            if worktrees.is_empty() {
+                eprintln!("Not in a git repository");
+                std::process::exit(1);
+            }
+
+            let items: Vec<String> = worktrees
+                .iter()
+                .map(|worktree| format!("{} [{}]", worktree.path, worktree.label))
+                .collect();
+            match select_item("Worktrees:", &items) {
+                Some(index) => println!("{}", worktrees[index].path),
+                None => std::process::exit(1),
+            }
+        }
+        Some(Commands::StashSelect { apply, pop }) => {
+            let stashes = Command::new("git")
+                .args(["stash", "list", "--format=%gd%x09%cr%x09%gs"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| parse_stash_list(&String::from_utf8_lossy(&output.stdout)))
+                .unwrap_or_default();
+            if stashes.is_empty() {
+                eprintln!("No stash entries found");
+                std::process::exit(1);
+            }
+
+            let items: Vec<String> = stashes
+                .iter()
+                .map(|stash| format!("{} ({}) {}", stash.reference, stash.age, stash.message))
+                .collect();
+            let Some(index) = select_item("Stashes:", &items) else {
+                std::process::exit(1);
             };
+            let reference = &stashes[index].reference;
 
-            // Generate and print the prompt
-            print!("{}", generate_prompt(&style));
+            let action = if *apply {
+                Some("apply")
+            } else if *pop {
+                Some("pop")
+            } else {
+                None
+            };
+            match action {
+                Some(action) => {
+                    let succeeded = Command::new("git")
+                        .args(["stash", action, reference])
+                        .status()
+                        .is_ok_and(|status| status.success());
+                    if !succeeded {
+                        std::process::exit(1);
+                    }
+                }
+                None => {
+                    print_and_copy(reference, args.print_only);
+                }
+            }
+        }
+        Some(Commands::GitSha { full }) => match get_git_commit_sha(*full) {
+            Some(sha) => {
+                print_and_copy(&sha, args.print_only);
+            }
+            None => {
+                eprintln!("Not in a git repository");
+                std::process::exit(1);
+            }
+        },
+        Some(Commands::Daemon) => {
+            #[cfg(unix)]
+            {
+                let Some(path) = daemon::socket_path() else {
+                    eprintln!("Could not determine the socket path");
+                    std::process::exit(1);
+                };
+                match daemon::bind(&path) {
+                    Ok(listener) => {
+                        println!("Listening on {}", path.display());
+                        daemon::serve(listener, serve_prompt);
+                    }
+                    Err(e) => {
+                        eprintln!("Could not start the daemon: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            #[cfg(not(unix))]
+            {
+                eprintln!("The daemon needs Unix domain sockets");
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Explain { json }) => explain_prompt(*json),
+        Some(Commands::Bench { iterations, cached }) => run_bench(*iterations, *cached),
+        Some(Commands::Doctor) => run_doctor(),
+        Some(Commands::SelfUpdate { check }) => {
+            let current = env!("CARGO_PKG_VERSION");
+            let release = match update::latest_release() {
+                Ok(release) => release,
+                Err(e) => {
+                    eprintln!("Could not check for a new release: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if !update::is_newer(&release.tag_name, current) {
+                println!("loco-pilot {} is up to date", current);
+                return;
+            }
+            if *check {
+                println!(
+                    "loco-pilot {} is available (installed: {})",
+                    release.tag_name, current
+                );
+                return;
+            }
+            match update::install(&release) {
+                Ok(path) => println!("Updated {} to {}", path.display(), release.tag_name),
+                Err(e) => {
+                    eprintln!("Update failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Commands::Prompt { format }) => match format.as_str() {
+            "text" => {
+                let _ = io::stdout()
+                    .write_all(render_prompt_or_fallback(&args, &Env::default()).as_bytes());
+            }
+            "json" => println!("{}", or_fallback(|| prompt_segments_json(&args), "[]")),
+            _ => {
+                eprintln!("Unknown format: {} (use text or json)", format);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            // A shell that went away mid-prompt isn't worth a panic message
+            let _ = io::stdout()
+                .write_all(render_prompt_or_fallback(&args, &Env::default()).as_bytes());
         }
     }
 }
@@ -0,0 +1,115 @@
+// Built-in presets: config overrides for a complete look
+
+/// A built-in preset, stored as the config settings it overrides
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub settings: &'static str,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "minimal",
+        description: "Only the prompt character, plus the last command's status when it failed",
+        settings: r#"
+format = "{$cmd }$char "
+
+[character]
+success_symbol = "❯"
+error_symbol = "❯"
+
+[colors]
+character_success = "green"
+"#,
+    },
+    Preset {
+        name: "powerline",
+        description: "Segments on colored backgrounds, best with a powerline font",
+        settings: r#"
+format = "$user  $dir { $git }{ $cmd} $char "
+
+[character]
+success_symbol = "❯"
+error_symbol = "❯"
+
+[colors]
+username = "bold white on blue"
+directory = "bold black on cyan"
+git_branch = "black on green"
+"#,
+    },
+    Preset {
+        name: "nerd-font",
+        description: "Nerd Font icons for the environment and git segments",
+        settings: r#"
+format = "$user{@$host} $dir{ $env}{ $git}{ $cmd} $char "
+
+[character]
+success_symbol = "❯"
+error_symbol = "❯"
+
+[python]
+symbol = " "
+
+[conda]
+symbol = " "
+
+[node]
+symbol = " "
+
+[container]
+symbol = " "
+
+[git]
+branch_symbol = " "
+stash_symbol = " "
+tag_symbol = " "
+"#,
+    },
+    Preset {
+        name: "plain-ascii",
+        description: "No symbols outside ASCII, for limited fonts and terminals",
+        settings: r#"
+format = "$user{@$host}:$dir{ $env}{ $git}{ $cmd} $char "
+
+[character]
+success_symbol = "$"
+error_symbol = "$"
+
+[python]
+symbol = "py:"
+
+[conda]
+symbol = "conda:"
+
+[node]
+symbol = "node:"
+
+[container]
+symbol = "ctr:"
+
+[git]
+ahead_symbol = "^"
+behind_symbol = "v"
+stash_symbol = "s"
+tag_symbol = "tag:"
+truncate_symbol = "..."
+"#,
+    },
+    Preset {
+        name: "two-line",
+        description: "Everything on the first line, the prompt character alone on the second",
+        settings: r#"
+format = "[$time] $user{@$host} $dir{ $env}{ $git}{ $cmd}\n$char "
+
+[character]
+success_symbol = "❯"
+error_symbol = "❯"
+"#,
+    },
+];
+
+/// Find a built-in preset by name
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
@@ -0,0 +1,26 @@
+// Conda/mamba environment detection
+
+use crate::Env;
+use std::path::Path;
+
+/// Name of the active conda or mamba environment
+pub fn environment(env: &Env, hide_base: bool) -> Option<String> {
+    let env_name = env
+        .var("CONDA_DEFAULT_ENV")
+        .or_else(|| env.var("CONDA_PREFIX"))?;
+    let name = env_display_name(&env_name);
+
+    if name.is_empty() || (hide_base && name == "base") {
+        return None;
+    }
+    Some(name)
+}
+
+/// Environments activated by path report the full prefix, so only keep its last component
+pub fn env_display_name(env_name: &str) -> String {
+    let trimmed = env_name.trim().trim_end_matches(['/', '\\']);
+    Path::new(trimmed)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| trimmed.to_string())
+}
@@ -0,0 +1,72 @@
+// Container environment detection for docker, podman, lxc and friends
+
+use crate::Env;
+use std::fs;
+use std::path::Path;
+
+/// The container the shell runs in, e.g. "podman" with the image or container name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    pub engine: String,
+    pub name: Option<String>,
+}
+
+/// Detect whether the shell is running inside a container
+pub fn detect(env: &Env) -> Option<Container> {
+    if crate::test_mode::enabled(env) {
+        return None;
+    }
+    // Podman (and toolbox/distrobox on top of it) describes the container here
+    if let Ok(contents) = fs::read_to_string("/run/.containerenv") {
+        return Some(Container {
+            engine: "podman".to_string(),
+            name: parse_containerenv(&contents),
+        });
+    }
+
+    if Path::new("/.dockerenv").exists() {
+        return Some(Container {
+            engine: "docker".to_string(),
+            name: None,
+        });
+    }
+
+    // systemd-nspawn, lxc and others export $container to PID 1
+    let engine = env
+        .var("container")
+        .filter(|engine| !engine.is_empty())
+        .or_else(|| {
+            fs::read_to_string("/proc/1/cgroup")
+                .ok()
+                .and_then(|cgroup| engine_from_cgroup(&cgroup))
+                .map(str::to_string)
+        })?;
+
+    Some(Container { engine, name: None })
+}
+
+/// Pick the container name (or image when unnamed) out of /run/.containerenv
+pub fn parse_containerenv(contents: &str) -> Option<String> {
+    let value = |key: &str| {
+        contents.lines().find_map(|line| {
+            line.strip_prefix(key)
+                .and_then(|rest| rest.strip_prefix('='))
+                .map(|value| value.trim().trim_matches('"').to_string())
+                .filter(|value| !value.is_empty())
+        })
+    };
+
+    value("name").or_else(|| value("image"))
+}
+
+/// Guess the container engine from the cgroup paths of PID 1
+pub fn engine_from_cgroup(cgroup: &str) -> Option<&'static str> {
+    ["docker", "kubepods", "containerd", "libpod", "lxc"]
+        .iter()
+        .find(|engine| cgroup.contains(*engine))
+        .map(|engine| match *engine {
+            "kubepods" => "kubernetes",
+            "libpod" => "podman",
+            engine => engine,
+        })
+}
@@ -0,0 +1,26 @@
+// Arbitrary environment variable segment
+
+use crate::Env;
+
+/// The formatted value of an environment variable, or the fallback when it's unset
+pub fn value(env: &Env, name: &str, prefix: &str, suffix: &str, fallback: &str) -> Option<String> {
+    if name.is_empty() {
+        return None;
+    }
+    format_value(env.var(name).as_deref(), prefix, suffix, fallback)
+}
+
+/// Wrap the value in its prefix and suffix, hiding the segment when there's nothing to show
+pub fn format_value(
+    value: Option<&str>,
+    prefix: &str,
+    suffix: &str,
+    fallback: &str,
+) -> Option<String> {
+    let value = match value {
+        Some(value) if !value.is_empty() => value,
+        _ if !fallback.is_empty() => fallback,
+        _ => return None,
+    };
+    Some(format!("{}{}{}", prefix, value, suffix))
+}
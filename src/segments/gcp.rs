@@ -0,0 +1,101 @@
+// Google Cloud active configuration detection
+
+use crate::{CachedItem, Env, lock};
+use once_cell::sync::Lazy;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Active gcloud project and account
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcpContext {
+    pub project: Option<String>,
+    pub account: Option<String>,
+}
+
+/// Cache for the gcloud context, since it's read from several files
+static GCP_CACHE: Lazy<Mutex<CachedItem<Option<GcpContext>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Maximum age of the cached gcloud context in seconds
+const GCP_CACHE_TTL_SECS: u64 = 5;
+
+/// Get the active gcloud project and account with caching
+pub fn context(env: &Env) -> Option<GcpContext> {
+    let mut cache = lock(&GCP_CACHE);
+    let ttl = Duration::from_secs(GCP_CACHE_TTL_SECS);
+    if let Some((cached_context, _)) = cache
+        .as_ref()
+        .filter(|(_, timestamp)| timestamp.elapsed() < ttl)
+    {
+        return cached_context.clone();
+    }
+
+    let context = read_context(env);
+    *cache = Some((context.clone(), Instant::now()));
+    context
+}
+
+/// Forget the cached context, e.g. before rendering for another shell
+pub fn reset_cache() {
+    *lock(&GCP_CACHE) = None;
+}
+
+/// Read the active configuration the same way gcloud resolves it
+fn read_context(env: &Env) -> Option<GcpContext> {
+    let config_dir = match env.var_os("CLOUDSDK_CONFIG") {
+        Some(dir) => PathBuf::from(dir),
+        None => env.home_dir()?.join(".config").join("gcloud"),
+    };
+
+    let config_name = env
+        .var("CLOUDSDK_ACTIVE_CONFIG_NAME")
+        .or_else(|| fs::read_to_string(config_dir.join("active_config")).ok())
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|| "default".to_string());
+
+    let content = fs::read_to_string(
+        config_dir
+            .join("configurations")
+            .join(format!("config_{}", config_name)),
+    )
+    .unwrap_or_default();
+    let mut context = parse_core_section(&content);
+
+    // Environment overrides win over the configuration file
+    if let Some(project) = env.var("CLOUDSDK_CORE_PROJECT") {
+        context.project = Some(project);
+    }
+
+    if context.project.is_none() && context.account.is_none() {
+        return None;
+    }
+    Some(context)
+}
+
+/// Pull the project and account out of the [core] section of a gcloud config file
+pub fn parse_core_section(content: &str) -> GcpContext {
+    let mut context = GcpContext::default();
+    let mut in_core = false;
+
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_core = line == "[core]";
+            continue;
+        }
+        if !in_core {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key.trim() {
+            "project" => context.project = Some(value),
+            "account" => context.account = Some(value),
+            _ => {}
+        }
+    }
+
+    context
+}
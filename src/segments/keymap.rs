@@ -0,0 +1,23 @@
+// Vi keymap detection for zle and fish
+
+/// The vi editing mode reported by the shell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViMode {
+    Normal,
+    Insert,
+    Visual,
+    Replace,
+}
+
+impl ViMode {
+    /// Map zle keymaps (vicmd, viins, main) and fish bind modes (default, insert, visual, replace)
+    pub fn from_keymap(keymap: &str) -> Option<Self> {
+        match keymap.trim() {
+            "vicmd" | "default" | "normal" | "command" => Some(ViMode::Normal),
+            "viins" | "main" | "insert" => Some(ViMode::Insert),
+            "visual" | "vivis" | "visual_line" => Some(ViMode::Visual),
+            "replace" | "replace_one" => Some(ViMode::Replace),
+            _ => None,
+        }
+    }
+}
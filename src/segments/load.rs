@@ -0,0 +1,62 @@
+// System load average detection
+
+use crate::Env;
+use std::fs;
+use std::process::Command;
+
+/// How busy the machine is relative to its CPU count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadLevel {
+    Low,
+    Medium,
+    High,
+}
+
+/// The 1-minute load average
+pub fn load_average(env: &Env) -> Option<f64> {
+    if crate::test_mode::enabled(env) {
+        return None;
+    }
+    // Linux exposes the load averages directly
+    if let Ok(content) = fs::read_to_string("/proc/loadavg") {
+        return parse_load_average(&content);
+    }
+
+    // macOS and the BSDs format it as "{ 1.23 1.10 1.05 }"
+    let output = Command::new("sysctl")
+        .args(["-n", "vm.loadavg"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_load_average(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse the first number out of /proc/loadavg or `sysctl vm.loadavg` output
+pub fn parse_load_average(content: &str) -> Option<f64> {
+    content
+        .split_whitespace()
+        .find(|field| *field != "{")?
+        .parse()
+        .ok()
+}
+
+/// Classify a load average against the number of CPUs
+pub fn load_level(load: f64, cpus: usize) -> LoadLevel {
+    let ratio = load / cpus.max(1) as f64;
+    if ratio < 0.7 {
+        LoadLevel::Low
+    } else if ratio < 1.0 {
+        LoadLevel::Medium
+    } else {
+        LoadLevel::High
+    }
+}
+
+/// Number of CPUs available to this process
+pub fn cpu_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+}
@@ -0,0 +1,18 @@
+// Optional prompt segments that describe the surrounding environment
+
+pub mod conda;
+pub mod container;
+pub mod env_var;
+pub mod gcp;
+pub mod keymap;
+pub mod load;
+pub mod multiplexer;
+pub mod nix;
+pub mod node;
+pub mod plugin;
+pub mod python;
+pub mod root;
+pub mod ssh;
+#[cfg(feature = "wasm-plugins")]
+pub mod wasm;
+pub mod wsl;
@@ -0,0 +1,40 @@
+// tmux and GNU screen session detection
+
+use crate::Env;
+
+/// Whether the shell is running inside tmux or screen
+pub fn in_multiplexer(env: &Env) -> bool {
+    if crate::test_mode::enabled(env) {
+        return false;
+    }
+    env.var_os("TMUX").is_some() || env.var_os("STY").is_some()
+}
+
+/// Name of the tmux or screen session the shell is running in
+pub fn session_name(env: &Env) -> Option<String> {
+    if crate::test_mode::enabled(env) {
+        return None;
+    }
+    if env.var_os("TMUX").is_some() {
+        // TMUX only holds the socket path, so ask tmux for the session name
+        let output = env
+            .command("tmux")
+            .args(["display-message", "-p", "#S"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return (!name.is_empty()).then_some(name);
+    }
+
+    env.var("STY").map(|sty| screen_session_name(&sty))
+}
+
+/// Screen's STY looks like "12345.pts-0.host", where everything after the pid is the name
+pub fn screen_session_name(sty: &str) -> String {
+    sty.split_once('.')
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| sty.to_string())
+}
@@ -0,0 +1,25 @@
+// nix-shell and `nix develop` detection
+
+use crate::Env;
+
+/// Name of the active nix dev shell, with a marker for pure shells
+pub fn shell(env: &Env) -> Option<String> {
+    let state = env.var("IN_NIX_SHELL")?;
+    // Both nix-shell and `nix develop` export the derivation name as $name
+    let name = env.var("name");
+    Some(format_shell(&state, name.as_deref()))
+}
+
+/// Format the shell name and purity for display
+pub fn format_shell(state: &str, name: Option<&str>) -> String {
+    let name = match name {
+        Some(name) if !name.is_empty() => name,
+        _ => "nix-shell",
+    };
+
+    if state == "pure" {
+        format!("{} (pure)", name)
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,57 @@
+// Node.js version detection with an on-disk cache
+
+use crate::{Env, disk_cache, find_in_path};
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+use tracing::debug;
+
+/// Files that mark a directory as a Node.js project
+const PROJECT_MARKERS: [&str; 3] = ["package.json", ".nvmrc", "node_modules"];
+
+/// Whether the directory looks like a Node.js project
+pub fn is_node_project(dir: &Path) -> bool {
+    PROJECT_MARKERS
+        .iter()
+        .any(|marker| dir.join(marker).exists())
+}
+
+/// The active node version, if the current directory is a Node.js project
+pub fn version(env: &Env, cache_ttl: Duration) -> Option<String> {
+    let current_dir = env::current_dir().ok()?;
+    if !is_node_project(&current_dir) {
+        return None;
+    }
+
+    // Key the cache on the resolved binary so switching versions with nvm/fnm is noticed
+    let node_path = find_in_path(env, "node")?;
+    let cache_path = disk_cache::entry_path("node", &node_path, env);
+
+    if let Some(version) = cache_path
+        .as_deref()
+        .and_then(|path| disk_cache::read_fresh(path, cache_ttl))
+    {
+        return Some(version);
+    }
+
+    debug!("running {} --version", node_path.display());
+    let output = env.command(&node_path).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let version = String::from_utf8(output.stdout).ok()?.trim().to_string();
+
+    if let Some(path) = cache_path {
+        // A failed cache write only costs us another `node --version` next time
+        let _ = disk_cache::write(&path, &version);
+    }
+
+    Some(version)
+}
+
+/// The version an earlier prompt found for the active node, however old, for
+/// when there's no time left to run `node --version`
+pub fn cached_version(env: &Env) -> Option<String> {
+    let cache_path = disk_cache::entry_path("node", &find_in_path(env, "node")?, env)?;
+    disk_cache::read(&cache_path).map(|(version, _)| version)
+}
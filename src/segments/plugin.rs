@@ -0,0 +1,58 @@
+// Segments from external executables named `loco-pilot-<name>`
+
+use crate::{Env, GitStatus};
+use serde::Serialize;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// What a plugin reads as JSON on stdin
+#[derive(Debug, Serialize)]
+pub struct PluginContext<'a> {
+    /// The variable the plugin was found for, e.g. "weather" for `$weather`
+    pub name: &'a str,
+    pub cwd: PathBuf,
+    pub shell: &'a str,
+    pub style: &'a str,
+    /// Terminal width, when the shell hook passed it
+    pub columns: Option<usize>,
+    /// Exit status of the previous command
+    pub status: Option<i32>,
+    /// Whether the segment is for the right prompt
+    pub right: bool,
+    /// Repository status, when the prompt collected it
+    pub git: Option<&'a GitStatus>,
+    /// Environment the prompt is rendered with, which the plugin runs in
+    #[serde(skip)]
+    pub env: &'a Env,
+}
+
+/// The executable for `$name`, from the configured directories first, then PATH.
+/// A leading `~` in the directories stands for the home directory.
+pub fn find(env: &Env, name: &str, dirs: &[String]) -> Option<PathBuf> {
+    if name.is_empty() {
+        return None;
+    }
+    let file_name = format!("loco-pilot-{}", name);
+    let home = env.home_dir();
+    let configured = dirs.iter().map(|dir| match (dir.strip_prefix('~'), &home) {
+        (Some(rest), Some(home)) => home.join(rest.trim_start_matches(['/', '\\'])),
+        _ => PathBuf::from(dir),
+    });
+    let path = env
+        .var_os("PATH")
+        .map(|path| env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    configured
+        .chain(path)
+        .find_map(|dir| executable_in(&dir, &file_name))
+}
+
+/// The executable `name` in `dir`, with `.exe` tried as well for Windows
+fn executable_in(dir: &Path, name: &str) -> Option<PathBuf> {
+    let candidate = dir.join(name);
+    if candidate.is_file() {
+        return Some(candidate);
+    }
+    let candidate = dir.join(format!("{}.exe", name));
+    candidate.is_file().then_some(candidate)
+}
@@ -0,0 +1,44 @@
+// Python virtualenv detection
+
+use crate::Env;
+use std::env;
+use std::path::Path;
+
+/// Name of the active Python virtualenv, falling back to `.venv` in the current directory
+pub fn virtualenv(env: &Env) -> Option<String> {
+    // Newer virtualenv/venv versions export the prompt name they would have used
+    if let Some(prompt) = env.var("VIRTUAL_ENV_PROMPT") {
+        let prompt = prompt.trim().trim_start_matches('(').trim_end_matches(')');
+        if !prompt.is_empty() {
+            return Some(prompt.to_string());
+        }
+    }
+
+    if let Some(venv) = env.var("VIRTUAL_ENV") {
+        return Some(venv_name(&venv));
+    }
+
+    // An inactive but present .venv is still useful to know about
+    let current_dir = env::current_dir().ok()?;
+    if current_dir.join(".venv").join("pyvenv.cfg").is_file() {
+        return Some(".venv".to_string());
+    }
+
+    None
+}
+
+/// Derive a display name from a virtualenv path
+pub fn venv_name(venv_path: &str) -> String {
+    let path = Path::new(venv_path.trim_end_matches(['/', '\\']));
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // Project-local venvs are all called .venv/venv, so show the project instead
+    let project_local = name == ".venv" || name == "venv";
+    match path.parent().and_then(|parent| parent.file_name()) {
+        Some(project) if project_local => project.to_string_lossy().to_string(),
+        _ => name,
+    }
+}
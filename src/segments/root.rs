@@ -0,0 +1,31 @@
+// Root and sudo detection
+
+use crate::Env;
+
+/// Whether the shell is running as root, either directly or through sudo
+pub fn is_root(env: &Env) -> bool {
+    if crate::test_mode::enabled(env) {
+        return false;
+    }
+    env.var_os("SUDO_USER").is_some() || effective_uid(env) == Some(0)
+}
+
+/// The effective uid of this process, read from /proc where available
+#[cfg(unix)]
+fn effective_uid(env: &Env) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    if let Ok(metadata) = std::fs::metadata("/proc/self") {
+        return Some(metadata.uid());
+    }
+
+    // Without /proc, USER is the best cheap signal we have
+    env.var("USER")
+        .map(|user| if user == "root" { 0 } else { 1 })
+}
+
+/// Windows has no uid, so elevation isn't detected there
+#[cfg(not(unix))]
+fn effective_uid(_env: &Env) -> Option<u32> {
+    None
+}
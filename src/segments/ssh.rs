@@ -0,0 +1,13 @@
+// SSH session detection
+
+use crate::Env;
+
+/// Whether the shell is running inside an SSH session
+pub fn is_ssh_session(env: &Env) -> bool {
+    if crate::test_mode::enabled(env) {
+        return false;
+    }
+    ["SSH_CONNECTION", "SSH_CLIENT", "SSH_TTY"]
+        .iter()
+        .any(|var| env.is_set(var))
+}
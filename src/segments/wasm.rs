@@ -0,0 +1,189 @@
+// Segments from WebAssembly modules in the config directory, run in-process
+
+use crate::Env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use wasmi::{Caller, Config, Engine, Extern, Linker, Memory, Module, Store};
+
+/// Import module the host functions are provided under
+const HOST_MODULE: &str = "loco_pilot";
+
+/// Instructions a plugin may execute per prompt before its segment is left out
+const FUEL: u64 = 50_000_000;
+
+/// What host functions may see: the directory and environment the prompt is
+/// rendered in
+struct HostState {
+    cwd: PathBuf,
+    env: Env,
+}
+
+/// The module for `$name`, i.e. `<config dir>/plugins/<name>.wasm`
+pub fn find(name: &str, config_dir: &Path) -> Option<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\', '.']) {
+        return None;
+    }
+    let path = config_dir.join("plugins").join(format!("{}.wasm", name));
+    path.is_file().then_some(path)
+}
+
+/// Run the module at `path` with the JSON `input`, returning what its `render`
+/// export produced. Any trap, missing export or running out of fuel is an error.
+pub fn run(path: &Path, cwd: &Path, env: &Env, input: &[u8]) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, &bytes[..]).map_err(|e| e.to_string())?;
+    let mut store = Store::new(
+        &engine,
+        HostState {
+            cwd: cwd.canonicalize().map_err(|e| e.to_string())?,
+            env: env.clone(),
+        },
+    );
+    store.set_fuel(FUEL).map_err(|e| e.to_string())?;
+
+    let mut linker = Linker::<HostState>::new(&engine);
+    linker
+        .func_wrap(HOST_MODULE, "env_var", env_var)
+        .and_then(|linker| linker.func_wrap(HOST_MODULE, "read_file", read_file))
+        .map_err(|e| e.to_string())?;
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .and_then(|instance| instance.start(&mut store))
+        .map_err(|e| e.to_string())?;
+
+    let memory = instance
+        .get_memory(&store, "memory")
+        .ok_or("module doesn't export its memory")?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&store, "alloc")
+        .map_err(|e| e.to_string())?;
+    let render = instance
+        .get_typed_func::<(i32, i32), i64>(&store, "render")
+        .map_err(|e| e.to_string())?;
+
+    let len = i32::try_from(input.len()).map_err(|e| e.to_string())?;
+    let input_ptr = alloc.call(&mut store, len).map_err(|e| e.to_string())?;
+    memory
+        .write(&mut store, input_ptr as u32 as usize, input)
+        .map_err(|e| e.to_string())?;
+    // The result comes back packed as pointer << 32 | length
+    let packed = render
+        .call(&mut store, (input_ptr, len))
+        .map_err(|e| e.to_string())? as u64;
+    // Slicing checks the result lies in memory before anything is copied
+    let (output_ptr, output_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+    let output = output_ptr
+        .checked_add(output_len)
+        .and_then(|end| memory.data(&store).get(output_ptr..end))
+        .ok_or("render returned a result outside the module's memory")?
+        .to_vec();
+    String::from_utf8(output).map_err(|e| e.to_string())
+}
+
+/// `env_var(name_ptr, name_len, out_ptr, out_cap) -> len`: copy the variable
+/// into the guest's buffer. Returns its full length, which may exceed
+/// `out_cap` so the guest can retry with a larger buffer, or -1 when unset.
+fn env_var(
+    mut caller: Caller<'_, HostState>,
+    name_ptr: i32,
+    name_len: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> i32 {
+    let Some(memory) = guest_memory(&caller) else {
+        return -1;
+    };
+    let Some(name) = read_guest(&caller, memory, name_ptr, name_len) else {
+        return -1;
+    };
+    let Some(value) = String::from_utf8(name)
+        .ok()
+        .and_then(|name| caller.data().env.var(&name))
+    else {
+        return -1;
+    };
+    write_guest(&mut caller, memory, out_ptr, out_cap, value.as_bytes())
+}
+
+/// `read_file(path_ptr, path_len, out_ptr, out_cap) -> len`: copy a file from
+/// the prompt's directory or below into the guest's buffer. Relative paths
+/// start at that directory; anything outside it reads as missing (-1).
+fn read_file(
+    mut caller: Caller<'_, HostState>,
+    path_ptr: i32,
+    path_len: i32,
+    out_ptr: i32,
+    out_cap: i32,
+) -> i32 {
+    let Some(memory) = guest_memory(&caller) else {
+        return -1;
+    };
+    let Some(path) = read_guest(&caller, memory, path_ptr, path_len)
+        .and_then(|path| String::from_utf8(path).ok())
+    else {
+        return -1;
+    };
+    // Resolving links and `..` first keeps the guest from stepping outside
+    let cwd = &caller.data().cwd;
+    let Some(path) = cwd
+        .join(path)
+        .canonicalize()
+        .ok()
+        .filter(|path| path.starts_with(cwd))
+    else {
+        return -1;
+    };
+    // Only read what fits, but report the full length like env_var does
+    let Ok(file) = fs::File::open(path) else {
+        return -1;
+    };
+    let Ok(len) = file.metadata().map(|metadata| metadata.len()) else {
+        return -1;
+    };
+    let mut contents = Vec::new();
+    let cap = u64::try_from(out_cap).unwrap_or(0);
+    if file.take(cap).read_to_end(&mut contents).is_err()
+        || write_guest(&mut caller, memory, out_ptr, out_cap, &contents) < 0
+    {
+        return -1;
+    }
+    i32::try_from(len).unwrap_or(i32::MAX)
+}
+
+fn guest_memory(caller: &Caller<'_, HostState>) -> Option<Memory> {
+    caller.get_export("memory").and_then(Extern::into_memory)
+}
+
+fn read_guest(
+    caller: &Caller<'_, HostState>,
+    memory: Memory,
+    ptr: i32,
+    len: i32,
+) -> Option<Vec<u8>> {
+    // Slicing checks the range lies in memory before anything is copied
+    let (ptr, len) = (ptr as u32 as usize, usize::try_from(len).ok()?);
+    let bytes = memory.data(caller).get(ptr..ptr.checked_add(len)?)?;
+    Some(bytes.to_vec())
+}
+
+/// Copy as much of `data` as fits in `cap` bytes, returning the full length
+fn write_guest(
+    caller: &mut Caller<'_, HostState>,
+    memory: Memory,
+    ptr: i32,
+    cap: i32,
+    data: &[u8],
+) -> i32 {
+    let copied = data.len().min(usize::try_from(cap).unwrap_or(0));
+    if memory
+        .write(caller, ptr as u32 as usize, &data[..copied])
+        .is_err()
+    {
+        return -1;
+    }
+    i32::try_from(data.len()).unwrap_or(i32::MAX)
+}
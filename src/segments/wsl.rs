@@ -0,0 +1,50 @@
+// Windows Subsystem for Linux detection
+
+use crate::Env;
+use std::fs;
+
+/// Whether the shell is running under WSL
+pub fn is_wsl(env: &Env) -> bool {
+    if crate::test_mode::enabled(env) {
+        return false;
+    }
+    if ["WSL_DISTRO_NAME", "WSL_INTEROP"]
+        .iter()
+        .any(|var| env.is_set(var))
+    {
+        return true;
+    }
+
+    // The kernel version string names Microsoft on both WSL 1 and WSL 2
+    fs::read_to_string("/proc/version")
+        .is_ok_and(|version| version.to_lowercase().contains("microsoft"))
+}
+
+/// Name of the WSL distribution, falling back to "WSL" when it isn't exported
+pub fn distro_name(env: &Env) -> String {
+    env.var("WSL_DISTRO_NAME")
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "WSL".to_string())
+}
+
+/// Rewrite a Windows drive mounted under /mnt, e.g. `/mnt/c/Users` to `C:/Users`
+pub fn windows_path(path: &str) -> Option<String> {
+    let rest = path.strip_prefix("/mnt/")?;
+    let (drive, rest) = match rest.split_once('/') {
+        Some((drive, rest)) => (drive, Some(rest)),
+        None => (rest, None),
+    };
+
+    // Only single letter mounts are Windows drives
+    let mut chars = drive.chars();
+    let letter = chars.next().filter(|c| c.is_ascii_alphabetic())?;
+    if chars.next().is_some() {
+        return None;
+    }
+
+    let drive = format!("{}:", letter.to_ascii_uppercase());
+    Some(match rest {
+        Some(rest) if !rest.is_empty() => format!("{}/{}", drive, rest),
+        _ => format!("{}/", drive),
+    })
+}
@@ -0,0 +1,359 @@
+// Shell-specific escaping for prompt output
+
+use crate::format;
+
+/// The shell a prompt is being generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+    Nu,
+    Xonsh,
+}
+
+impl ShellKind {
+    /// Parse a shell name, returning None for shells we don't support
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bash" => Some(ShellKind::Bash),
+            "zsh" => Some(ShellKind::Zsh),
+            "fish" => Some(ShellKind::Fish),
+            "powershell" | "pwsh" => Some(ShellKind::PowerShell),
+            "nu" | "nushell" => Some(ShellKind::Nu),
+            "xonsh" => Some(ShellKind::Xonsh),
+            _ => None,
+        }
+    }
+
+    /// The shell's name, as accepted by `parse`
+    pub fn name(self) -> &'static str {
+        match self {
+            ShellKind::Bash => "bash",
+            ShellKind::Zsh => "zsh",
+            ShellKind::Fish => "fish",
+            ShellKind::PowerShell => "powershell",
+            ShellKind::Nu => "nu",
+            ShellKind::Xonsh => "xonsh",
+        }
+    }
+
+    /// Parse a shell name, falling back to bash for unknown shells.
+    /// The name "auto" detects the shell from the parent process.
+    pub fn from_name(name: &str) -> Self {
+        if name.eq_ignore_ascii_case("auto") {
+            return Self::detect().unwrap_or(ShellKind::Bash);
+        }
+        Self::parse(name).unwrap_or(ShellKind::Bash)
+    }
+
+    /// Detect the invoking shell by inspecting the parent process name
+    pub fn detect() -> Option<Self> {
+        Self::from_process_name(&parent_process_name()?)
+    }
+
+    /// Map a process name like "-bash", "/usr/bin/zsh" or "pwsh.exe" to a shell
+    pub fn from_process_name(process_name: &str) -> Option<Self> {
+        let name = process_name.trim();
+        // Login shells are started with a leading dash
+        let name = name.strip_prefix('-').unwrap_or(name);
+        let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+        let name = name.strip_suffix(".exe").unwrap_or(name);
+        Self::parse(name)
+    }
+
+    /// Wrap an ANSI escape sequence so the shell doesn't count it towards the prompt width
+    pub fn color(self, ansi_code: &str) -> String {
+        match self {
+            ShellKind::Bash => bash_color(ansi_code),
+            ShellKind::Zsh => zsh_color(ansi_code),
+            // These shells measure the prompt themselves, so raw ANSI codes are fine
+            ShellKind::Fish | ShellKind::PowerShell | ShellKind::Nu | ShellKind::Xonsh => {
+                ansi_code.to_string()
+            }
+        }
+    }
+
+    /// Wrap a terminal control sequence so the shell passes it through as is and
+    /// doesn't count it towards the prompt width
+    pub fn non_printing(self, sequence: &str) -> String {
+        self.color(&self.escape(sequence))
+    }
+
+    /// Prepare a rendered prompt for the shell: its text is shown as is rather
+    /// than expanded, and the ANSI escape sequences in it are marked as non-printing
+    pub fn escape_prompt(self, prompt: &str) -> String {
+        format::map_escapes(prompt, &|sequence| self.non_printing(sequence), &|text| {
+            self.escape(text)
+        })
+    }
+
+    /// Escape the characters prompt expansion would act on, since directory and
+    /// branch names can contain anything
+    fn escape(self, text: &str) -> String {
+        match self {
+            // PS1 is decoded before it's expanded, and both steps take a
+            // backslash off. A bare \$ would be decoded to # for root.
+            ShellKind::Bash => text
+                .replace('\\', "\\\\\\\\")
+                .replace('$', "\\\\$")
+                .replace('`', "\\\\`"),
+            ShellKind::Zsh => text.replace('%', "%%"),
+            _ => text.to_string(),
+        }
+    }
+
+    /// Hooks marking where command output starts for OSC 133 semantic prompts,
+    /// added to the setup snippet when `terminal.semantic_prompt` is on
+    pub fn semantic_prompt_hooks(self) -> &'static str {
+        match self {
+            ShellKind::Bash => BASH_SEMANTIC_HOOKS,
+            ShellKind::Zsh => ZSH_SEMANTIC_HOOKS,
+            ShellKind::Fish => FISH_SEMANTIC_HOOKS,
+            ShellKind::PowerShell | ShellKind::Nu | ShellKind::Xonsh => "",
+        }
+    }
+
+    /// The setup snippet that wires loco-pilot into this shell's prompt
+    pub fn init_script(self) -> &'static str {
+        match self {
+            ShellKind::Bash => BASH_INIT,
+            ShellKind::Zsh => ZSH_INIT,
+            ShellKind::Fish => FISH_INIT,
+            ShellKind::PowerShell => POWERSHELL_INIT,
+            ShellKind::Nu => NU_INIT,
+            ShellKind::Xonsh => XONSH_INIT,
+        }
+    }
+
+    /// Startup files the setup snippet usually goes in, relative to the home directory
+    pub fn rc_files(self) -> &'static [&'static str] {
+        match self {
+            ShellKind::Bash => &[".bashrc", ".bash_profile", ".profile"],
+            ShellKind::Zsh => &[".zshrc"],
+            ShellKind::Fish => &[".config/fish/config.fish"],
+            ShellKind::PowerShell => &[
+                ".config/powershell/Microsoft.PowerShell_profile.ps1",
+                "Documents/PowerShell/Microsoft.PowerShell_profile.ps1",
+                "Documents/WindowsPowerShell/Microsoft.PowerShell_profile.ps1",
+            ],
+            ShellKind::Nu => &[
+                ".config/nushell/config.nu",
+                "Library/Application Support/nushell/config.nu",
+                "AppData/Roaming/nushell/config.nu",
+            ],
+            ShellKind::Xonsh => &[".xonshrc", ".config/xonsh/rc.xsh"],
+        }
+    }
+
+    /// The line that loads the setup snippet from the shell's startup file
+    pub fn setup_line(self) -> &'static str {
+        match self {
+            ShellKind::Bash => r#"eval "$(loco-pilot init bash)""#,
+            ShellKind::Zsh => r#"eval "$(loco-pilot init zsh)""#,
+            ShellKind::Fish => "loco-pilot init fish | source",
+            ShellKind::PowerShell => "loco-pilot init powershell | Out-String | Invoke-Expression",
+            // Nushell can only source files, so the snippet is saved first
+            ShellKind::Nu => "source ~/.cache/loco-pilot/init.nu",
+            ShellKind::Xonsh => "execx($(loco-pilot init xonsh))",
+        }
+    }
+}
+
+/// Generate properly escaped bash prompt color codes
+/// This is the key function for fixing the prompt issues
+pub fn bash_color(ansi_code: &str) -> String {
+    // Properly wrap ANSI escape codes with bash's prompt escaping sequences
+    // This ensures bash correctly calculates prompt width by ignoring non-printing characters
+    // Use literal escape sequences without backslash escaping
+    format!("\\[{}\\]", ansi_code)
+}
+
+/// Generate properly escaped zsh prompt color codes
+pub fn zsh_color(ansi_code: &str) -> String {
+    // zsh uses %{...%} to mark sequences that don't move the cursor
+    format!("%{{{}%}}", ansi_code)
+}
+
+/// Get the name of the parent process from /proc, falling back to ps
+#[cfg(unix)]
+fn parent_process_name() -> Option<String> {
+    let ppid = std::os::unix::process::parent_id();
+
+    if let Ok(comm) = std::fs::read_to_string(format!("/proc/{}/comm", ppid)) {
+        return Some(comm.trim().to_string());
+    }
+
+    // macOS and the BSDs have no /proc, so ask ps instead
+    let output = std::process::Command::new("ps")
+        .args(["-o", "comm=", "-p", &ppid.to_string()])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let name = String::from_utf8(output.stdout).ok()?;
+    Some(name.trim().to_string())
+}
+
+/// Parent process lookup isn't supported on this platform
+#[cfg(not(unix))]
+fn parent_process_name() -> Option<String> {
+    None
+}
+
+/// Usage: eval "$(loco-pilot init bash)"
+const BASH_INIT: &str = r#"# loco-pilot bash prompt integration
+_loco_pilot_preexec_ready=true
+_loco_pilot_preexec() {
+  # The DEBUG trap also fires for PROMPT_COMMAND, so only time the first
+  # command run after a prompt was drawn
+  if [ "$_loco_pilot_preexec_ready" = true ]; then
+    _loco_pilot_preexec_ready=false
+    _loco_pilot_start=${EPOCHREALTIME/[.,]/}
+    declare -F _loco_pilot_command_start >/dev/null && _loco_pilot_command_start
+  fi
+}
+trap '_loco_pilot_preexec' DEBUG
+_loco_pilot_prompt() {
+  local exit_code=$? pipe_status="${PIPESTATUS[*]}"
+  local duration=0
+  if [ -n "$_loco_pilot_start" ] && [ -n "$EPOCHREALTIME" ]; then
+    local now=${EPOCHREALTIME/[.,]/}
+    duration=$(( (now - _loco_pilot_start) / 1000 ))
+  fi
+  unset _loco_pilot_start
+  PS1="$(loco-pilot --shell bash --status $exit_code --pipestatus "$pipe_status" --cmd-duration $duration ${COLUMNS:+--columns $COLUMNS})"
+  _loco_pilot_preexec_ready=true
+  return $exit_code
+}
+PROMPT_COMMAND="_loco_pilot_prompt${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+"#;
+
+/// Usage: eval "$(loco-pilot init zsh)"
+const ZSH_INIT: &str = r#"# loco-pilot zsh prompt integration
+zmodload zsh/datetime
+_loco_pilot_keymap=""
+# The prompts are read from variables, so prompt_subst doesn't expand
+# anything in their text (like a $(...) in a branch name)
+setopt prompt_subst
+PROMPT='${_loco_pilot_prompt}'
+RPROMPT='${_loco_pilot_rprompt}'
+_loco_pilot_render() {
+  _loco_pilot_prompt="$(loco-pilot --shell zsh --status $_loco_pilot_status --pipestatus "$_loco_pilot_pipestatus" --cmd-duration $_loco_pilot_duration --columns $COLUMNS ${_loco_pilot_keymap:+--keymap=$_loco_pilot_keymap})"
+  _loco_pilot_rprompt="$(loco-pilot --right --shell zsh --status $_loco_pilot_status --pipestatus "$_loco_pilot_pipestatus" --cmd-duration $_loco_pilot_duration --columns $COLUMNS)"
+}
+_loco_pilot_preexec() {
+  _loco_pilot_start=$EPOCHREALTIME
+}
+_loco_pilot_precmd() {
+  _loco_pilot_status=$? _loco_pilot_pipestatus="$pipestatus"
+  _loco_pilot_duration=0
+  if [[ -n $_loco_pilot_start ]]; then
+    _loco_pilot_duration=$(( int((EPOCHREALTIME - _loco_pilot_start) * 1000) ))
+    unset _loco_pilot_start
+  fi
+  # Every new line starts in insert mode once vi mode has been seen
+  [[ -n $_loco_pilot_keymap ]] && _loco_pilot_keymap=viins
+  _loco_pilot_render
+}
+_loco_pilot_keymap_select() {
+  _loco_pilot_keymap=$KEYMAP
+  _loco_pilot_render
+  zle reset-prompt
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec _loco_pilot_preexec
+add-zsh-hook precmd _loco_pilot_precmd
+zle -N zle-keymap-select _loco_pilot_keymap_select
+"#;
+
+/// Marks the start of command output in bash, called from the DEBUG trap
+const BASH_SEMANTIC_HOOKS: &str = r#"_loco_pilot_command_start() {
+  printf '\033]133;C\007'
+}
+"#;
+
+/// Marks the start of command output in zsh
+const ZSH_SEMANTIC_HOOKS: &str = r#"_loco_pilot_command_start() {
+  printf '\033]133;C\007'
+}
+add-zsh-hook preexec _loco_pilot_command_start
+"#;
+
+/// Marks the start of command output in fish
+const FISH_SEMANTIC_HOOKS: &str = r#"function _loco_pilot_command_start --on-event fish_preexec
+    printf '\e]133;C\a'
+end
+"#;
+
+/// Usage: loco-pilot init fish | source
+const FISH_INIT: &str = r#"# loco-pilot fish prompt integration
+function fish_mode_prompt
+    # The vi mode indicator is part of the loco-pilot prompt
+end
+function fish_prompt
+    set -l pipe_status $pipestatus
+    set -l exit_code $pipe_status[-1]
+    set -l keymap
+    if test "$fish_key_bindings" = fish_vi_key_bindings
+        set keymap --keymap $fish_bind_mode
+    end
+    loco-pilot --shell fish --status $exit_code --pipestatus "$pipe_status" --cmd-duration $CMD_DURATION --columns $COLUMNS $keymap
+end
+function fish_right_prompt
+    set -l pipe_status $pipestatus
+    loco-pilot --right --shell fish --status $pipe_status[-1] --pipestatus "$pipe_status" --cmd-duration $CMD_DURATION --columns $COLUMNS
+end
+"#;
+
+/// Usage: loco-pilot init powershell | Out-String | Invoke-Expression
+const POWERSHELL_INIT: &str = r#"# loco-pilot PowerShell prompt integration
+function global:prompt {
+    $exitCode = if ($?) { 0 } elseif ($LASTEXITCODE) { $LASTEXITCODE } else { 1 }
+    $duration = 0
+    $last = Get-History -Count 1
+    if ($last) {
+        $duration = [int]($last.EndExecutionTime - $last.StartExecutionTime).TotalMilliseconds
+    }
+    loco-pilot --shell powershell --status $exitCode --cmd-duration $duration --columns $Host.UI.RawUI.WindowSize.Width
+}
+"#;
+
+/// Usage: loco-pilot init nu | save -f ~/.cache/loco-pilot/init.nu
+const NU_INIT: &str = r#"# loco-pilot nushell prompt integration
+$env.PROMPT_COMMAND = {||
+    loco-pilot --shell nu --status $env.LAST_EXIT_CODE --cmd-duration ($env.CMD_DURATION_MS? | default 0) --columns (term size).columns
+}
+$env.PROMPT_COMMAND_RIGHT = {||
+    loco-pilot --right --shell nu --status $env.LAST_EXIT_CODE --cmd-duration ($env.CMD_DURATION_MS? | default 0) --columns (term size).columns
+}
+$env.PROMPT_INDICATOR = ""
+"#;
+
+/// Usage: execx($(loco-pilot init xonsh))
+const XONSH_INIT: &str = r#"# loco-pilot xonsh prompt integration
+def _loco_pilot_last_command():
+    status, duration = 0, 0
+    if len(__xonsh__.history) > 0:
+        last = __xonsh__.history[-1]
+        status = last.rtn or 0
+        duration = int((last.ts[1] - last.ts[0]) * 1000)
+    return status, duration
+
+def _loco_pilot_columns():
+    import shutil
+    return shutil.get_terminal_size().columns
+
+def _loco_pilot_prompt():
+    status, duration = _loco_pilot_last_command()
+    return $(loco-pilot --shell xonsh --status @(status) --cmd-duration @(duration) --columns @(_loco_pilot_columns()))
+
+def _loco_pilot_right_prompt():
+    status, duration = _loco_pilot_last_command()
+    return $(loco-pilot --right --shell xonsh --status @(status) --cmd-duration @(duration) --columns @(_loco_pilot_columns()))
+
+$PROMPT = _loco_pilot_prompt
+$RIGHT_PROMPT = _loco_pilot_right_prompt
+"#;
@@ -0,0 +1,368 @@
+// Import settings from a starship.toml
+
+use crate::Config;
+
+/// Starship format variables and the loco-pilot variable they become. Several
+/// starship modules collapse into one loco-pilot segment, e.g. all git modules.
+const VARIABLES: &[(&str, &str)] = &[
+    ("time", "time"),
+    ("username", "user"),
+    ("hostname", "host"),
+    ("directory", "dir"),
+    ("git_branch", "git"),
+    ("git_commit", "git"),
+    ("git_state", "git"),
+    ("git_status", "git"),
+    ("python", "env"),
+    ("conda", "env"),
+    ("nodejs", "env"),
+    ("gcloud", "env"),
+    ("nix_shell", "env"),
+    ("container", "env"),
+    ("cmd_duration", "cmd"),
+    ("status", "cmd"),
+    ("character", "char"),
+];
+
+/// Translate the common subset of a starship config into `config`. Returns a
+/// line per imported or skipped setting.
+pub fn import(starship: &toml::Table, config: &mut Config) -> Vec<String> {
+    let mut report = Vec::new();
+    let string = |module: &str, key: &str| -> Option<String> {
+        starship.get(module)?.get(key)?.as_str().map(str::to_string)
+    };
+
+    let format = starship
+        .get("format")
+        .and_then(toml::Value::as_str)
+        .filter(|format| format.trim() != "$all");
+    if let Some(format) = format {
+        config.format = translate_format(format);
+        report.push(format!("format = {:?}", config.format));
+    }
+
+    let colors = &mut config.colors;
+    let styles = [
+        (
+            "username",
+            "style_user",
+            &mut colors.username,
+            "color.username",
+        ),
+        (
+            "username",
+            "style_root",
+            &mut colors.username_root,
+            "color.username_root",
+        ),
+        ("hostname", "style", &mut colors.hostname, "color.hostname"),
+        (
+            "directory",
+            "style",
+            &mut colors.directory,
+            "color.directory",
+        ),
+        (
+            "git_branch",
+            "style",
+            &mut colors.git_branch,
+            "color.git_branch",
+        ),
+        (
+            "git_status",
+            "style",
+            &mut colors.git_dirty,
+            "color.git_dirty",
+        ),
+        ("time", "style", &mut colors.time, "color.time"),
+        (
+            "cmd_duration",
+            "style",
+            &mut colors.duration,
+            "color.duration",
+        ),
+        ("status", "style", &mut colors.status, "color.status"),
+        ("python", "style", &mut colors.python, "color.python"),
+        ("conda", "style", &mut colors.conda, "color.conda"),
+        ("nodejs", "style", &mut colors.node, "color.node"),
+        (
+            "container",
+            "style",
+            &mut colors.container,
+            "color.container",
+        ),
+    ];
+    for (module, key, target, color_key) in styles {
+        if let Some(style) = string(module, key) {
+            *target = translate_style(&style);
+            report.push(format!("{} = {}", color_key, target));
+        }
+    }
+
+    // Symbols of the form "[➜](bold green)" carry a color as well
+    let characters = [
+        ("success_symbol", "character.success_symbol"),
+        ("error_symbol", "character.error_symbol"),
+    ];
+    for (key, symbol_key) in characters {
+        let Some(value) = string("character", key) else {
+            continue;
+        };
+        let (symbol, style) = split_styled_text(&value);
+        let (target, color_target, color_key) = if key == "success_symbol" {
+            (
+                &mut config.character.success_symbol,
+                &mut config.colors.character_success,
+                "color.character_success",
+            )
+        } else {
+            (
+                &mut config.character.error_symbol,
+                &mut config.colors.character_error,
+                "color.character_error",
+            )
+        };
+        *target = symbol.trim().to_string();
+        report.push(format!("{} = {}", symbol_key, target));
+        if let Some(style) = style {
+            *color_target = translate_style(&style);
+            report.push(format!("{} = {}", color_key, color_target));
+        }
+    }
+
+    let symbols = [
+        ("python", &mut config.python.symbol, "python.symbol"),
+        ("conda", &mut config.conda.symbol, "conda.symbol"),
+        ("nodejs", &mut config.node.symbol, "node.symbol"),
+        (
+            "container",
+            &mut config.container.symbol,
+            "container.symbol",
+        ),
+    ];
+    for (module, target, key) in symbols {
+        if let Some(symbol) = string(module, "symbol") {
+            *target = symbol;
+            report.push(format!("{} = {}", key, target));
+        }
+    }
+    if let Some(symbol) = string("git_branch", "symbol") {
+        config.git.branch_symbol = symbol;
+        report.push(format!("git.branch_symbol = {}", config.git.branch_symbol));
+    }
+
+    // git_status symbols may embed the count, e.g. "⇡${count}"
+    let git_symbols = [
+        ("stashed", &mut config.git.stash_symbol, "git.stash_symbol"),
+        ("ahead", &mut config.git.ahead_symbol, "git.ahead_symbol"),
+        ("behind", &mut config.git.behind_symbol, "git.behind_symbol"),
+        ("staged", &mut config.git.staged_symbol, "git.staged_symbol"),
+        (
+            "modified",
+            &mut config.git.modified_symbol,
+            "git.modified_symbol",
+        ),
+        (
+            "deleted",
+            &mut config.git.deleted_symbol,
+            "git.deleted_symbol",
+        ),
+        (
+            "untracked",
+            &mut config.git.untracked_symbol,
+            "git.untracked_symbol",
+        ),
+    ];
+    for (key, target, symbol_key) in git_symbols {
+        if let Some(value) = string("git_status", key) {
+            *target = split_styled_text(&value)
+                .0
+                .replace("${count}", "")
+                .replace("$count", "");
+            report.push(format!("{} = {}", symbol_key, target));
+        }
+    }
+
+    let ssh_only = starship
+        .get("hostname")
+        .and_then(|hostname| hostname.get("ssh_only"))
+        .and_then(toml::Value::as_bool);
+    if let Some(ssh_only) = ssh_only {
+        config.hostname.ssh_only = ssh_only;
+        report.push(format!("hostname.ssh_only = {}", ssh_only));
+    }
+    let min_time = starship
+        .get("cmd_duration")
+        .and_then(|duration| duration.get("min_time"))
+        .and_then(toml::Value::as_integer)
+        .and_then(|min_time| u64::try_from(min_time).ok());
+    if let Some(min_time) = min_time {
+        config.duration.threshold = min_time;
+        report.push(format!("duration.threshold = {}", min_time));
+    }
+    let truncation_length = starship
+        .get("directory")
+        .and_then(|directory| directory.get("truncation_length"))
+        .and_then(toml::Value::as_integer)
+        .and_then(|length| usize::try_from(length).ok());
+    if let Some(truncation_length) = truncation_length {
+        config.directory.keep_components = truncation_length;
+        report.push(format!("directory.keep_components = {}", truncation_length));
+    }
+    if let Some(symbol) = string("directory", "truncation_symbol") {
+        config.directory.truncation_symbol = symbol;
+        report.push(format!(
+            "directory.truncation_symbol = {}",
+            config.directory.truncation_symbol
+        ));
+    }
+    let truncate_to_repo = starship
+        .get("directory")
+        .and_then(|directory| directory.get("truncate_to_repo"))
+        .and_then(toml::Value::as_bool);
+    if let Some(truncate_to_repo) = truncate_to_repo {
+        config.directory.truncate_to_repo = truncate_to_repo;
+        report.push(format!("directory.truncate_to_repo = {}", truncate_to_repo));
+    }
+    let substitutions = starship
+        .get("directory")
+        .and_then(|directory| directory.get("substitutions"))
+        .and_then(toml::Value::as_table);
+    for (prefix, label) in substitutions.into_iter().flatten() {
+        if let Some(label) = label.as_str() {
+            config
+                .directory
+                .substitutions
+                .insert(prefix.clone(), label.to_string());
+            report.push(format!("directory.substitutions.{} = {}", prefix, label));
+        }
+    }
+    if let Some(time_format) = string("time", "time_format") {
+        config.time.format = time_format;
+        report.push(format!("time.format = {}", config.time.format));
+    }
+
+    let directory = starship.get("directory").and_then(toml::Value::as_table);
+    let imported = [
+        "style",
+        "truncation_length",
+        "truncation_symbol",
+        "truncate_to_repo",
+        "substitutions",
+    ];
+    for key in directory.into_iter().flat_map(|directory| directory.keys()) {
+        if !imported.contains(&key.as_str()) {
+            report.push(format!("skipped directory.{} (not supported)", key));
+        }
+    }
+
+    report
+}
+
+/// Translate a starship style like "bold fg:cyan bg:#303030" to loco-pilot's
+fn translate_style(style: &str) -> String {
+    style
+        .split_whitespace()
+        .map(|word| match word.split_once(':') {
+            Some((side @ ("fg" | "bg"), color)) => {
+                format!("{}={}", side, color.replace("bright-", "bright_"))
+            }
+            _ => word.replace("bright-", "bright_"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Split starship styled text "[text](style)" into its text and style
+fn split_styled_text(value: &str) -> (String, Option<String>) {
+    let styled = value
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("]("))
+        .and_then(|(text, style)| Some((text, style.strip_suffix(')')?)));
+    match styled {
+        Some((text, style)) => (text.to_string(), Some(style.to_string())),
+        None => (value.to_string(), None),
+    }
+}
+
+/// Translate a starship format string into a loco-pilot template. Styled text
+/// keeps its text, conditional `(...)` groups become `{...}` groups, unknown
+/// modules are dropped and modules sharing a segment only appear once.
+pub fn translate_format(format: &str) -> String {
+    let mut chars = format.chars().peekable();
+    let mut seen = Vec::new();
+    let template = translate_until(&mut chars, &mut seen, None);
+
+    // Dropped modules leave runs of spaces behind
+    let mut collapsed = String::new();
+    for c in template.chars() {
+        if !(c == ' ' && collapsed.ends_with(' ')) {
+            collapsed.push(c);
+        }
+    }
+    let mut collapsed = collapsed.trim_start().to_string();
+    if !collapsed.ends_with(' ') {
+        collapsed.push(' ');
+    }
+    collapsed
+}
+
+/// Translate until the end of the format or the given closing character
+fn translate_until(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    seen: &mut Vec<&'static str>,
+    close: Option<char>,
+) -> String {
+    let mut output = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            _ if Some(c) == close => break,
+            '\\' => output.extend(chars.next()),
+            '\n' => output.push(' '),
+            '$' => {
+                let mut name = String::new();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_ascii_alphanumeric() || next == '_') {
+                        break;
+                    }
+                    name.push(next);
+                    chars.next();
+                }
+                let variable = VARIABLES
+                    .iter()
+                    .find(|(starship, _)| *starship == name)
+                    .map(|(_, variable)| *variable);
+                match variable {
+                    Some(variable) if !seen.contains(&variable) => {
+                        seen.push(variable);
+                        output.push('$');
+                        output.push_str(variable);
+                    }
+                    _ if name == "line_break" => output.push(' '),
+                    _ => {}
+                }
+            }
+            '[' => {
+                output.push_str(&translate_until(chars, seen, Some(']')));
+                // Drop the style that follows styled text
+                if chars.peek() == Some(&'(') {
+                    for c in chars.by_ref() {
+                        if c == ')' {
+                            break;
+                        }
+                    }
+                }
+            }
+            '(' => {
+                output.push('{');
+                output.push_str(&translate_until(chars, seen, Some(')')));
+                output.push('}');
+            }
+            '{' | '}' => {}
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
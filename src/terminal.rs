@@ -0,0 +1,54 @@
+// Terminal control sequences sent along with the prompt
+
+/// An OSC sequence. BEL ends it rather than ST, whose backslash bash would
+/// take for a prompt escape.
+fn osc(body: &str) -> String {
+    format!("\x1b]{}\x07", body)
+}
+
+/// OSC 133 marks ending the previous command's output with its exit status,
+/// when known, and starting the prompt
+pub fn prompt_start(status: Option<i32>) -> String {
+    let finished = status
+        .map(|status| osc(&format!("133;D;{}", status)))
+        .unwrap_or_default();
+    format!("{}{}", finished, osc("133;A"))
+}
+
+/// OSC 133 mark ending the prompt, where command input starts
+pub fn prompt_end() -> String {
+    osc("133;B")
+}
+
+/// OSC 7 report of the working directory, so new tabs and splits can open
+/// in it
+pub fn working_directory(host: &str, path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = if path.starts_with('/') {
+        path
+    } else {
+        format!("/{}", path)
+    };
+    osc(&format!("7;file://{}{}", host, percent_encode(&path)))
+}
+
+/// Percent-encode everything but unreserved characters and separators
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// OSC 0 window and tab title, with control characters dropped so a
+/// directory name can't end the sequence early
+pub fn title(text: &str) -> String {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    osc(&format!("0;{}", text))
+}
@@ -0,0 +1,31 @@
+// Fixed stand-ins for the parts of the prompt that depend on the machine, so
+// tests can compare exact output
+
+use crate::Env;
+use chrono::{DateTime, Utc};
+
+/// Environment variable that turns test mode on
+pub const VAR: &str = "LOCO_PILOT_TEST_MODE";
+
+/// The time shown in test mode: 2024-01-02 12:34:56 UTC
+const TIME: i64 = 1_704_198_896;
+
+/// The hostname shown in test mode
+pub const HOSTNAME: &str = "laptop";
+
+/// The username shown in test mode, never root
+pub const USERNAME: &str = "user";
+
+/// Whether the prompt should be rendered from fixed data
+pub fn enabled(env: &Env) -> bool {
+    env.is_set(VAR)
+}
+
+/// The current time, or the fixed time in test mode
+pub fn now(env: &Env) -> DateTime<Utc> {
+    if enabled(env) {
+        DateTime::from_timestamp(TIME, 0).unwrap_or_default()
+    } else {
+        Utc::now()
+    }
+}
@@ -6,15 +6,200 @@ pub mod tests {
     /// Creates a mock Config for testing
     pub fn create_mock_config() -> Config {
         Config {
+            version: CONFIG_VERSION,
             style: "test_style".to_string(),
+            shell: "bash".to_string(),
+            format: "$user $dir $char ".to_string(),
+            right_format: "$time".to_string(),
+            icons: "default".to_string(),
+            ascii: false,
+            no_color: false,
+            prompt_timeout: 750,
+            timeout_placeholder: "test_…".to_string(),
             show_git: true,
+            git: GitConfig {
+                show_fetch_age: true,
+                fetch_age_symbol: "test_fetch".to_string(),
+                stale_fetch_age: 3600,
+                show_sparse: true,
+                sparse_symbol: "test_sparse".to_string(),
+                expected_email: "test@example.com".to_string(),
+                email_symbol: "test_email".to_string(),
+                show_commit_age: true,
+                commit_age_symbol: "test_age".to_string(),
+                truncate_branch: 20,
+                truncate_symbol: "test_…".to_string(),
+                truncate_position: "middle".to_string(),
+                async_refresh: false,
+                cache_ttl: 5,
+                timeout_ms: 250,
+                disabled_repos: vec!["test_disabled".to_string()],
+                branch_only_repos: vec!["test_branch_only".to_string()],
+                show_upstream: true,
+                backend: "git".to_string(),
+                show_remote: true,
+                remote_symbols: BTreeMap::from([("github.com".to_string(), "test_gh".to_string())]),
+                remote_default_symbol: "test_remote".to_string(),
+                show_tag: true,
+                tag_symbol: "test_tag".to_string(),
+                show_operation: true,
+                show_staged: true,
+                show_modified: true,
+                show_deleted: true,
+                show_untracked: true,
+                branch_symbol: "test_branch".to_string(),
+                emoji_branch_symbol: "test_emoji_branch".to_string(),
+                ahead_symbol: "test_ahead".to_string(),
+                behind_symbol: "test_behind".to_string(),
+                staged_symbol: "test_staged".to_string(),
+                modified_symbol: "test_modified".to_string(),
+                deleted_symbol: "test_deleted".to_string(),
+                untracked_symbol: "test_untracked".to_string(),
+                show_stash: true,
+                stash_symbol: "test_stash".to_string(),
+            },
             colors: ColorConfig {
                 username: "test_green".to_string(),
+                username_root: "test_red".to_string(),
                 hostname: "test_yellow".to_string(),
+                hostname_ssh: "test_red".to_string(),
                 directory: "test_cyan".to_string(),
                 git_branch: "test_green".to_string(),
                 git_dirty: "test_red".to_string(),
+                git_stash: "test_bright_black".to_string(),
+                git_staged: "test_green".to_string(),
+                git_deleted: "test_red".to_string(),
+                git_untracked: "test_bright_red".to_string(),
+                git_operation: "test_bold_yellow".to_string(),
+                git_tag: "test_yellow".to_string(),
+                git_upstream: "test_cyan".to_string(),
+                git_commit_age: "test_bright_black".to_string(),
+                git_email: "test_bold_red".to_string(),
+                git_sparse: "test_bright_black".to_string(),
+                git_stale: "test_bright_black".to_string(),
                 time: "test_blue".to_string(),
+                status: "test_red".to_string(),
+                duration: "test_yellow".to_string(),
+                python: "test_yellow".to_string(),
+                conda: "test_green".to_string(),
+                node: "test_green".to_string(),
+                gcp: "test_blue".to_string(),
+                nix: "test_blue".to_string(),
+                tmux: "test_magenta".to_string(),
+                env_var: "test_cyan".to_string(),
+                keymap_normal: "test_yellow".to_string(),
+                keymap_insert: "test_green".to_string(),
+                keymap_visual: "test_magenta".to_string(),
+                keymap_replace: "test_red".to_string(),
+                character_success: "test_none".to_string(),
+                character_error: "test_red".to_string(),
+                character_root: "test_none".to_string(),
+                wsl: "test_bright_cyan".to_string(),
+                container: "test_bright_magenta".to_string(),
+                fill: "test_bright_black".to_string(),
+            },
+            status: StatusConfig {
+                symbol: "test_symbol".to_string(),
+                pipestatus_format: "collapse".to_string(),
+            },
+            duration: DurationConfig { threshold: 0 },
+            python: PythonConfig {
+                show: true,
+                symbol: "test_python".to_string(),
+            },
+            conda: CondaConfig {
+                show: true,
+                symbol: "test_conda".to_string(),
+                hide_base: false,
+            },
+            node: NodeConfig {
+                show: true,
+                symbol: "test_node".to_string(),
+                cache_ttl: 0,
+            },
+            gcp: GcpConfig {
+                show: true,
+                show_account: true,
+                symbol: "test_gcp".to_string(),
+            },
+            nix: NixConfig {
+                show: true,
+                symbol: "test_nix".to_string(),
+            },
+            load: LoadConfig {
+                show: true,
+                symbol: "test_load".to_string(),
+            },
+            time: TimeConfig {
+                format: "%H:%M".to_string(),
+                show_seconds: false,
+                timezones: vec!["UTC".to_string()],
+            },
+            hostname: HostnameConfig { ssh_only: true },
+            directory: DirectoryConfig {
+                max_length: 30,
+                keep_components: 3,
+                truncation_symbol: "test_…".to_string(),
+                truncate_to_repo: true,
+                substitutions: BTreeMap::from([("~/test".to_string(), "test_label".to_string())]),
+            },
+            root: RootConfig {
+                symbol: "test_root".to_string(),
+            },
+            tmux: TmuxConfig {
+                show: true,
+                symbol: "test_tmux".to_string(),
+                hide_hostname: true,
+            },
+            env_var: EnvVarConfig {
+                name: "TEST_STAGE".to_string(),
+                prefix: "[".to_string(),
+                suffix: "]".to_string(),
+                fallback: "none".to_string(),
+            },
+            segments: SegmentsConfig {
+                order: vec!["dir".to_string(), "git".to_string()],
+                disabled: vec!["time".to_string()],
+            },
+            terminal: TerminalConfig {
+                semantic_prompt: true,
+                report_directory: true,
+                title: "$user@$host: $dir".to_string(),
+            },
+            plugins: PluginsConfig {
+                dirs: vec!["~/.local/share/loco-pilot/plugins".to_string()],
+                timeout_ms: 100,
+            },
+            fill: FillConfig {
+                symbol: "test_fill".to_string(),
+            },
+            truncation: TruncationConfig {
+                max_width: 0.75,
+                drop_order: vec!["test_time".to_string()],
+            },
+            clipboard: ClipboardConfig {
+                backend: "osc52".to_string(),
+            },
+            container: ContainerConfig {
+                show: true,
+                symbol: "test_container".to_string(),
+            },
+            wsl: WslConfig {
+                show: true,
+                symbol: "test_wsl".to_string(),
+                windows_paths: true,
+            },
+            character: CharacterConfig {
+                success_symbol: "test_$".to_string(),
+                error_symbol: "test_!".to_string(),
+                emoji_success_symbol: "test_ok".to_string(),
+                emoji_error_symbol: "test_err".to_string(),
+            },
+            keymap: KeymapConfig {
+                normal_symbol: "test_n".to_string(),
+                insert_symbol: "test_i".to_string(),
+                visual_symbol: "test_v".to_string(),
+                replace_symbol: "test_r".to_string(),
             },
         }
     }
@@ -0,0 +1,214 @@
+// Replacing the installed binary with the latest GitHub release
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+
+/// Where GitHub describes the latest release
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/asudarsanan/loco-pilot/releases/latest";
+
+/// A published release and the files attached to it
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// Ask GitHub for the latest release
+pub fn latest_release() -> Result<Release, String> {
+    let output = Command::new("curl")
+        .args(["-fsSL", "-H", "Accept: application/vnd.github+json"])
+        .arg(LATEST_RELEASE_URL)
+        .output()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())
+}
+
+/// Whether the release tagged `tag`, e.g. "v0.2.0", is newer than `current`
+pub fn is_newer(tag: &str, current: &str) -> bool {
+    // Pre-release suffixes like "-rc.1" are ignored
+    let numbers = |version: &str| -> Vec<u64> {
+        let version = version.trim_start_matches('v');
+        let version = version.split(['-', '+']).next().unwrap_or_default();
+        version
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    numbers(tag) > numbers(current)
+}
+
+/// Name of the release archive for an OS and architecture as Rust names them,
+/// matching what the release workflow uploads
+pub fn asset_name(tag: &str, os: &str, arch: &str) -> Option<String> {
+    let arch = match arch {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        _ => return None,
+    };
+    match os {
+        "linux" => Some(format!("loco-pilot-{}-linux-{}.gz", tag, arch)),
+        "macos" => Some(format!("loco-pilot-{}-macos-{}.gz", tag, arch)),
+        "windows" if arch == "amd64" => Some(format!("loco-pilot-{}-windows-amd64.exe.zip", tag)),
+        _ => None,
+    }
+}
+
+/// Download this platform's archive from `release`, check it against the
+/// published SHA-256 and swap it in for the running binary, returning its path
+pub fn install(release: &Release) -> Result<PathBuf, String> {
+    let name =
+        asset_name(&release.tag_name, env::consts::OS, env::consts::ARCH).ok_or_else(|| {
+            format!(
+                "no release builds for {} on {}",
+                env::consts::OS,
+                env::consts::ARCH
+            )
+        })?;
+    let find = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| format!("{} has no {}", release.tag_name, name))
+    };
+    let archive_asset = find(&name)?;
+    let checksum_asset = find(&format!("{}.sha256", name))?;
+
+    let dir = env::temp_dir().join(format!("loco-pilot-update-{}", std::process::id()));
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let result = download_and_replace(&dir, &name, archive_asset, checksum_asset);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn download_and_replace(
+    dir: &Path,
+    name: &str,
+    archive_asset: &Asset,
+    checksum_asset: &Asset,
+) -> Result<PathBuf, String> {
+    let archive = dir.join(name);
+    download(&archive_asset.browser_download_url, &archive)?;
+    let checksum_file = dir.join(&checksum_asset.name);
+    download(&checksum_asset.browser_download_url, &checksum_file)?;
+
+    // The checksum file is `sha256sum` output: the digest, then the file name
+    let expected = fs::read_to_string(&checksum_file)
+        .map_err(|e| e.to_string())?
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let actual = sha256_hex(&fs::read(&archive).map_err(|e| e.to_string())?);
+    if actual != expected {
+        return Err(format!(
+            "checksum mismatch for {}: expected {}, got {}",
+            name, expected, actual
+        ));
+    }
+    debug!("{} matches its checksum {}", name, actual);
+
+    let new_exe = extract(&archive, dir)?;
+    replace_current_exe(&new_exe)
+}
+
+fn download(url: &str, path: &Path) -> Result<(), String> {
+    debug!("downloading {}", url);
+    let output = Command::new("curl")
+        .args(["-fsSL", "-o"])
+        .arg(path)
+        .arg(url)
+        .output()
+        .map_err(|e| format!("could not run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+/// Unpack the binary from a release archive: gzip on Unix, zip on Windows
+fn extract(archive: &Path, dir: &Path) -> Result<PathBuf, String> {
+    let name = archive
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    if let Some(exe_name) = name.strip_suffix(".zip") {
+        // Windows 10 and later ship bsdtar, which reads zip files
+        let status = Command::new("tar")
+            .arg("-xf")
+            .arg(archive)
+            .arg("-C")
+            .arg(dir)
+            .status()
+            .map_err(|e| format!("could not run tar: {}", e))?;
+        if !status.success() {
+            return Err(format!("could not unpack {}", name));
+        }
+        return Ok(dir.join(exe_name));
+    }
+
+    let output = Command::new("gzip")
+        .arg("-dc")
+        .arg(archive)
+        .output()
+        .map_err(|e| format!("could not run gzip: {}", e))?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(format!("could not unpack {}", name));
+    }
+    let exe = dir.join(name.strip_suffix(".gz").unwrap_or(name));
+    fs::write(&exe, output.stdout).map_err(|e| e.to_string())?;
+    Ok(exe)
+}
+
+/// Move `new_exe` over the running binary. The copy is staged next to it first
+/// so the final rename can't leave a half-written binary behind.
+fn replace_current_exe(new_exe: &Path) -> Result<PathBuf, String> {
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let exe = exe.canonicalize().unwrap_or(exe);
+    let staged = exe.with_extension("new");
+    fs::copy(new_exe, &staged)
+        .map_err(|e| format!("could not write {}: {}", staged.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))
+            .map_err(|e| e.to_string())?;
+    }
+    // Windows won't overwrite a running executable, but lets it be renamed
+    if cfg!(windows) {
+        let old = exe.with_extension("old");
+        let _ = fs::remove_file(&old);
+        fs::rename(&exe, &old).map_err(|e| e.to_string())?;
+    }
+    fs::rename(&staged, &exe).map_err(|e| {
+        let _ = fs::remove_file(&staged);
+        if cfg!(windows) {
+            let _ = fs::rename(exe.with_extension("old"), &exe);
+        }
+        format!("could not replace {}: {}", exe.display(), e)
+    })?;
+    Ok(exe)
+}
+
+/// The SHA-256 digest of `data` in lowercase hex, as `sha256sum` prints it
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
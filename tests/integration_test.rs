@@ -1,5 +1,19 @@
 // Integration tests for loco-pilot
 
+/// The prompt character loco-pilot uses for the current user, since root gets `#`
+fn expected_prompt_char() -> &'static str {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let is_root = std::env::var_os("SUDO_USER").is_some()
+            || std::fs::metadata("/proc/self").is_ok_and(|metadata| metadata.uid() == 0);
+        if is_root {
+            return "#";
+        }
+    }
+    "$"
+}
+
 /// Test that the binary can execute normally
 #[test]
 fn test_binary_executes() {
@@ -69,7 +83,8 @@ fn test_style_options() {
 
     let minimal_stdout = String::from_utf8_lossy(&minimal_output.stdout);
     assert_eq!(
-        minimal_stdout, "$ ",
+        minimal_stdout,
+        format!("{} ", expected_prompt_char()),
         "Minimal style should be a simple dollar sign and space"
     );
 
@@ -94,3 +109,364 @@ fn test_style_options() {
         "Info style should contain username@hostname format"
     );
 }
+
+/// Test that zsh mode wraps escapes with %{...%} instead of bash markers
+#[test]
+fn test_zsh_shell_escaping() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["--shell", "zsh"])
+        .output()
+        .expect("Failed to execute loco-pilot with zsh shell");
+
+    assert!(
+        output.status.success(),
+        "Zsh shell mode should execute successfully"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("%{"),
+        "Zsh output should use %{{...%}} escape wrappers"
+    );
+    assert!(
+        !stdout.contains("\\["),
+        "Zsh output should not contain bash escape wrappers"
+    );
+}
+
+/// Render an uncolored prompt for `shell` in a git repository on `branch`, in
+/// a directory called `dir_name` under a scratch home directory
+fn prompt_in_repo(test_name: &str, dir_name: &str, branch: &str, shell: &str) -> String {
+    let home =
+        std::env::temp_dir().join(format!("loco-pilot-{}-{}", test_name, std::process::id()));
+    std::fs::create_dir_all(home.join(dir_name)).unwrap();
+    let home = home.canonicalize().unwrap();
+    let repo = home.join(dir_name);
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(&repo)
+            .output()
+            .expect("Failed to run git");
+        assert!(output.status.success(), "git {:?} failed", args);
+    };
+    git(&["init", "-q", "-b", branch]);
+    git(&["commit", "-q", "--allow-empty", "-m", "initial"]);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["--style", "info", "--shell", shell, "--no-color"])
+        .current_dir(&repo)
+        .env_clear()
+        .env("HOME", &home)
+        .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+        .output()
+        .expect("Failed to execute loco-pilot in a repository");
+    std::fs::remove_dir_all(&home).unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// Test that a % in directory and branch names is shown as is by zsh rather
+/// than taken for a prompt escape
+#[test]
+fn test_zsh_escapes_percent_in_text() {
+    let prompt = prompt_in_repo("zsh-percent", "100%Bdone", "wip%F{red}", "zsh");
+    assert!(
+        prompt.contains("~/100%%Bdone"),
+        "The directory should have its % doubled: {}",
+        prompt
+    );
+    assert!(
+        prompt.contains("(wip%%F{red})"),
+        "The branch should have its % doubled: {}",
+        prompt
+    );
+}
+
+/// Test that bash shows a $(...) in directory and branch names rather than
+/// running it when it expands PS1
+#[cfg(unix)]
+#[test]
+fn test_bash_escapes_command_substitution_in_text() {
+    let name = "$(touch${IFS}pwned)";
+    let prompt = prompt_in_repo("bash-expansion", name, &format!("feat{}", name), "bash");
+    assert!(
+        prompt.contains("~/\\\\$(touch\\\\${IFS}pwned)"),
+        "The directory should be escaped for PS1: {}",
+        prompt
+    );
+
+    // Expand the prompt the way bash does before showing it
+    let scratch = std::env::temp_dir().join(format!("loco-pilot-ps1-{}", std::process::id()));
+    std::fs::create_dir_all(&scratch).unwrap();
+    let Ok(output) = std::process::Command::new("bash")
+        .args(["-c", "printf %s \"${LOCO_PILOT_PS1@P}\""])
+        .current_dir(&scratch)
+        .env("LOCO_PILOT_PS1", &prompt)
+        .output()
+    else {
+        std::fs::remove_dir_all(&scratch).unwrap();
+        return;
+    };
+    let ran = scratch.join("pwned").exists();
+    std::fs::remove_dir_all(&scratch).unwrap();
+    assert!(!ran, "Expanding the prompt should not run commands");
+    let shown = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        shown.contains("~/$(touch${IFS}pwned)") && shown.contains("(feat$(touch${IFS}pwned))"),
+        "Bash should show the names as is: {}",
+        shown
+    );
+}
+
+/// Test that fish mode emits raw ANSI codes without any shell wrappers
+#[test]
+fn test_fish_shell_escaping() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["--shell", "fish"])
+        .output()
+        .expect("Failed to execute loco-pilot with fish shell");
+
+    assert!(
+        output.status.success(),
+        "Fish shell mode should execute successfully"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("\x1b["),
+        "Fish output should contain ANSI color codes"
+    );
+    assert!(
+        !stdout.contains("\\[") && !stdout.contains("%{"),
+        "Fish output should not contain bash or zsh escape wrappers"
+    );
+}
+
+/// Test that the JSON output contains the structured prompt data
+#[test]
+fn test_json_output() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["--shell", "nu", "--json"])
+        .output()
+        .expect("Failed to execute loco-pilot with JSON output");
+
+    assert!(
+        output.status.success(),
+        "JSON output should execute successfully"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.trim_start().starts_with('{'),
+        "JSON output should be an object"
+    );
+    assert!(
+        stdout.contains("\"username\"") && stdout.contains("\"directory\""),
+        "JSON output should contain the prompt fields"
+    );
+}
+
+/// Test that the init command prints a setup snippet and rejects unknown shells
+#[test]
+fn test_init_command() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["init", "bash"])
+        .output()
+        .expect("Failed to execute loco-pilot init command");
+
+    assert!(
+        output.status.success(),
+        "Init command should execute successfully"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("PROMPT_COMMAND"),
+        "Bash init snippet should set PROMPT_COMMAND"
+    );
+
+    let unknown_output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["init", "tcsh"])
+        .output()
+        .expect("Failed to execute loco-pilot init command");
+
+    assert!(
+        !unknown_output.status.success(),
+        "Init command should fail for unsupported shells"
+    );
+}
+
+/// Test that the exit status segment only appears for failed commands
+#[test]
+fn test_status_segment() {
+    let failed_output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["--style", "minimal", "--status", "127"])
+        .output()
+        .expect("Failed to execute loco-pilot with a failed status");
+
+    let failed_stdout = String::from_utf8_lossy(&failed_output.stdout);
+    assert!(
+        failed_stdout.contains("127"),
+        "A non-zero status should be shown in the prompt"
+    );
+
+    let success_output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["--style", "minimal", "--status", "0"])
+        .output()
+        .expect("Failed to execute loco-pilot with a successful status");
+
+    let success_stdout = String::from_utf8_lossy(&success_output.stdout);
+    assert_eq!(
+        success_stdout,
+        format!("{} ", expected_prompt_char()),
+        "A zero status should not change the prompt"
+    );
+}
+
+/// Test that the prompt character turns red after a failed command
+#[test]
+fn test_prompt_character_reflects_status() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["--style", "minimal", "--shell", "fish", "--status", "1"])
+        .output()
+        .expect("Failed to execute loco-pilot with a failed status");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.ends_with(&format!("\x1b[31m{}\x1b[0m ", expected_prompt_char())),
+        "A failed command should color the prompt character, got: {:?}",
+        stdout
+    );
+}
+
+/// Test that test mode renders the same prompt on every machine
+#[test]
+fn test_test_mode_output() {
+    let home = std::env::temp_dir().join(format!("loco-pilot-test-mode-{}", std::process::id()));
+    std::fs::create_dir_all(home.join(".git")).unwrap();
+    let home = home.canonicalize().unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["--style", "info", "--shell", "fish"])
+        .current_dir(&home)
+        .env_clear()
+        .env("HOME", &home)
+        .env("LOCO_PILOT_TEST_MODE", "1")
+        .output()
+        .expect("Failed to execute loco-pilot in test mode");
+    std::fs::remove_dir_all(&home).unwrap();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "[\x1b[34m12:34:56\x1b[0m] \x1b[32muser\x1b[0m@\x1b[33mlaptop\x1b[0m: \x1b[36m~\x1b[0m \
+         (\x1b[32mmain\x1b[0m) \x1b[01;33m^1\x1b[0m \x1b[31m~1\x1b[0m \x1b[91m?1\x1b[0m $ ",
+        "Test mode should fix the time, user, host and git status"
+    );
+}
+
+/// Test that --no-color and NO_COLOR leave every escape sequence out of the prompt
+#[test]
+fn test_no_color() {
+    let home = std::env::temp_dir().join(format!("loco-pilot-no-color-{}", std::process::id()));
+    std::fs::create_dir_all(home.join(".git")).unwrap();
+    let home = home.canonicalize().unwrap();
+    let prompt = |args: &[&str], vars: &[(&str, &str)]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+            .args(args)
+            .current_dir(&home)
+            .env_clear()
+            .env("HOME", &home)
+            .env("LOCO_PILOT_TEST_MODE", "1")
+            .envs(vars.iter().copied())
+            .output()
+            .expect("Failed to execute loco-pilot without colors");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let expected = "[12:34:56] user@laptop: ~ (main) ^1 ~1 ?1 $ ";
+    assert_eq!(
+        prompt(&["--style", "info", "--shell", "fish", "--no-color"], &[]),
+        expected,
+        "--no-color should print the plain prompt"
+    );
+    assert_eq!(
+        prompt(&["--style", "info", "--shell", "bash", "--no-color"], &[]),
+        expected.replace('$', "\\\\$"),
+        "Bash should get the plain prompt escaped for PS1"
+    );
+    assert_eq!(
+        prompt(&["--style", "info", "--shell", "zsh"], &[("NO_COLOR", "1")]),
+        expected,
+        "NO_COLOR should print the plain prompt"
+    );
+    assert!(
+        prompt(&["--style", "info", "--shell", "zsh"], &[("NO_COLOR", "")]).contains("%{"),
+        "An empty NO_COLOR should leave colors on"
+    );
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+/// Test that config migrate upgrades an older config file and keeps the original
+#[test]
+fn test_config_migrate() {
+    let dir = std::env::temp_dir().join(format!("loco-pilot-migrate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    let original = "style = \"info\"\n\n[color]\ntime = \"red\"\n";
+    std::fs::write(&path, original).unwrap();
+    let migrate = || {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+            .args(["config", "migrate", "--config"])
+            .arg(&path)
+            .output()
+            .expect("Failed to execute loco-pilot config migrate");
+        assert!(output.status.success(), "config migrate should succeed");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert!(migrate().contains("from version 1 to 2"));
+    let backup = std::fs::read_to_string(dir.join("config.toml.bak"));
+    let migrated: toml::Table = std::fs::read_to_string(&path).unwrap().parse().unwrap();
+    assert!(migrate().contains("already at version 2"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(backup.unwrap(), original, "The original should be kept");
+    assert_eq!(migrated["version"].as_integer(), Some(2));
+    assert_eq!(migrated["style"].as_str(), Some("info"));
+    assert_eq!(migrated["colors"]["time"].as_str(), Some("red"));
+    assert!(!migrated.contains_key("color"));
+}
+
+/// Test that version --json reports the build rather than the current directory's repository
+#[test]
+fn test_version_json() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_loco-pilot"))
+        .args(["version", "--json"])
+        .current_dir(std::env::temp_dir())
+        .output()
+        .expect("Failed to execute loco-pilot version --json");
+
+    assert!(
+        output.status.success(),
+        "Version JSON should execute successfully"
+    );
+
+    let version: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("Version output should be JSON");
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert!(
+        version["build_date"]
+            .as_str()
+            .is_some_and(|date| date.len() == 10),
+        "Version JSON should contain the build date"
+    );
+    assert!(
+        version["target"]
+            .as_str()
+            .is_some_and(|target| !target.is_empty()),
+        "Version JSON should contain the target triple"
+    );
+    assert!(version["features"].is_array());
+    assert!(version.get("git_sha").is_some());
+}